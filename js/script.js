@@ -7,6 +7,7 @@ var animationDomPrefix = null;
 var animationFrameResolution = 3;
 var animationFrame = 0;
 var videoId = null;
+var videoVersion = null;
 var videoList = [];
 var videoLimitIncrement = 10;
 var totalVideoCount = 0;
@@ -16,11 +17,17 @@ var pendingFilterUpdate = false;
 var numVideoParts = null;
 var startUploadKey = null;
 var finishUploadKey = null;
+var maxUploadAttempts = 5;
 var submitting = false;
 var userMapping = null;
 var controlsUpdated = false;
 var updatedTech = new Set();
 var updatedNotables = new Set();
+var commentsVideoId = null;
+var commentParentId = null;
+var commentEditId = null;
+var tagMapping = {};
+var filterTagIds = new Set();
 
 function readSlice(file, start, size) {
     return new Promise(function(resolve, reject) {
@@ -370,10 +377,22 @@ async function updateFile() {
     highlightEndTime.max = frameOffsets.length - 1;
 
     updateControls('');
-    var newVideoId = null;
-    let username = localStorage.getItem("username");
-    let token = localStorage.getItem("token");
-    
+    let csrfToken = localStorage.getItem("csrfToken");
+
+    var sessionResponse = await fetch("/upload-session", {
+        method: "POST",
+        headers: {
+            "Content-Type": "application/json",
+            "X-CSRF-Token": csrfToken,
+        },
+        body: JSON.stringify({num_parts: fileList.length}),
+    });
+    if (!sessionResponse.ok) {
+        throw new Error(`Error creating upload session: ${sessionResponse.status}`);
+    }
+    var session = await sessionResponse.json();
+    var newVideoId = session.video_id;
+
     for (var i = 0; i < fileList.length; i++) {
         var start = performance.now();
         var file = fileList[i];
@@ -381,32 +400,57 @@ async function updateFile() {
         var compressedData = new Uint8Array(await new Response(compressedStream).arrayBuffer());
         var elapsedTime = performance.now() - start;
         console.log(`part ${i}: compression time elapsed (ms)=${elapsedTime}, compressed size=${compressedData.length}`);
-    
+
         var start = performance.now();
-        var headers = {
-            "Content-Type": "video/avi",
-            "Content-Encoding": "gzip",
-            "Authorization": 'Basic ' + btoa(username + ":" + token),
-            "X-MapRandoVideos-NumParts": videoFile.files.length,
-            "X-MapRandoVideos-PartNum": i,
-        };
-        if (newVideoId !== null) {
-            headers["X-MapRandoVideos-VideoId"] = newVideoId;
+        var uploaded = false;
+        for (var attempt = 0; attempt < maxUploadAttempts && !uploaded; attempt++) {
+            if (attempt > 0) {
+                // Check whether the server already received this part before the connection failed:
+                await new Promise(r => setTimeout(r, 1000 * attempt));
+                let statusResponse = await fetch(`/upload-session/${newVideoId}`, {
+                    headers: {"X-CSRF-Token": csrfToken},
+                });
+                if (statusResponse.ok) {
+                    let status = await statusResponse.json();
+                    if (status.parts.some(p => p.part_num == i && p.size == file.size)) {
+                        uploaded = true;
+                        break;
+                    }
+                }
+            }
+            try {
+                var uploadResponse = await fetch(`/upload-session/${newVideoId}/part/${i}`, {
+                    method: "PUT",
+                    headers: {
+                        "Content-Type": "video/avi",
+                        "Content-Encoding": "gzip",
+                        "X-CSRF-Token": csrfToken,
+                    },
+                    body: compressedData,
+                });
+                uploaded = uploadResponse.ok;
+                if (!uploaded) {
+                    console.log(`part ${i}: upload attempt ${attempt} failed: ${uploadResponse.status}`);
+                }
+            } catch (e) {
+                console.log(`part ${i}: upload attempt ${attempt} failed: ${e}`);
+            }
         }
-        var uploadResponse = await fetch("/upload-video", {
-            method: "POST",
-            headers: headers,
-            body: compressedData,
-        });
         var elapsedTime = performance.now() - start;
         console.log(`part ${i}: upload time elapsed (ms)=${elapsedTime}`);
-    
-        if (!uploadResponse.ok) {
-            throw new Error(`Error uploading video: ${uploadResponse.status}`);
+        if (!uploaded) {
+            throw new Error(`Error uploading video part ${i}`);
         }
-        newVideoId = parseInt(await uploadResponse.text());
-        videoId = newVideoId;
     }
+
+    var finalizeResponse = await fetch(`/upload-session/${newVideoId}/finalize`, {
+        method: "POST",
+        headers: {"X-CSRF-Token": csrfToken},
+    });
+    if (!finalizeResponse.ok) {
+        throw new Error(`Error finalizing upload: ${finalizeResponse.status}`);
+    }
+    videoId = newVideoId;
     finishUploadKey = uploadKey;
     console.log("finished uploading video: id=" + newVideoId);
 }
@@ -521,6 +565,11 @@ async function animateLoop() {
 }
 
 function updateLogin() {
+    if (localStorage.getItem("token") !== null) {
+        // Signed in before session cookies were introduced, so sign in again to get a session:
+        localStorage.removeItem("username");
+        localStorage.removeItem("token");
+    }
     let username = localStorage.getItem("username");
     let logoutButton = document.getElementById("logoutButton");
     let loginButton = document.getElementById("loginButton");
@@ -536,19 +585,24 @@ function updateLogin() {
         loginButton.classList.remove("d-none");
         uploadButton.classList.add("d-none");
     }
-    let permission = localStorage.getItem("permission");
-    if (permission == "Editor") {
+    if (hasCapability("CurateTech")) {
         techButton.classList.remove("d-none");
     } else {
         techButton.classList.add("d-none");
     }
 }
 
+function hasCapability(capability) {
+    let capabilities = JSON.parse(localStorage.getItem("capabilities") || "[]");
+    return capabilities.includes(capability);
+}
+
 async function signIn() {
     let username = document.getElementById("username").value;
     let token = document.getElementById("token").value;
     
     let response = await fetch("/sign-in", {
+      method: "POST",
       headers: {
         "Authorization": 'Basic ' + btoa(username + ":" + token),
       }  
@@ -556,9 +610,10 @@ async function signIn() {
     if (response.ok) {
         info = await response.json();
         localStorage.setItem("username", username);
-        localStorage.setItem("token", token);
+        localStorage.setItem("csrfToken", info.csrf_token);
         localStorage.setItem("userId", info.user_id);
         localStorage.setItem("permission", info.permission);
+        localStorage.setItem("capabilities", JSON.stringify(info.capabilities));
         updateLogin();
         bootstrap.Modal.getInstance(document.getElementById("loginModal")).hide();
     } else {
@@ -567,11 +622,14 @@ async function signIn() {
     updateFilter();
 }
 
-function signOut() {
+async function signOut() {
+    await fetch("/sign-out", {method: "POST"});
     localStorage.removeItem("username");
+    localStorage.removeItem("csrfToken");
     localStorage.removeItem("token");
     localStorage.removeItem("userId");
     localStorage.removeItem("permission");
+    localStorage.removeItem("capabilities");
     updateLogin();
     updateFilter();
 }
@@ -619,17 +677,17 @@ async function submitVideo() {
         highlight_start_t: tryParseInt(formData.get("highlight_start_t")),
         highlight_end_t: tryParseInt(formData.get("highlight_end_t")),
         copyright_waiver: formData.get("copyright_waiver") == "on",
+        tags: selectedTagIds("tags"),
     };
     var json = JSON.stringify(req);
 
-    let username = localStorage.getItem("username");
-    let token = localStorage.getItem("token");
+    let csrfToken = localStorage.getItem("csrfToken");
 
     var result = await fetch("/submit-video", {
         method: "POST",
         headers: {
             "Content-Type": "application/json",
-            "Authorization": 'Basic ' + btoa(username + ":" + token),
+            "X-CSRF-Token": csrfToken,
         },
         body: json
     });
@@ -676,7 +734,61 @@ async function updateUserList() {
     }
 }
 
-function loadVideo(video, userId, permission, dateFormat, videoTableBody) {
+function selectedTagIds(selectId) {
+    let select = document.getElementById(selectId);
+    return Array.from(select.selectedOptions).map(opt => parseInt(opt.value));
+}
+
+function toggleFilterTag(tagId) {
+    if (filterTagIds.has(tagId)) {
+        filterTagIds.delete(tagId);
+    } else {
+        filterTagIds.add(tagId);
+    }
+    updateFilter();
+}
+
+// Load the tags, with counts of the videos matching the filters in `req` (as for /list-videos).
+async function updateTagList(req) {
+    let response = await fetch(`/tags?${new URLSearchParams(req)}`);
+    if (!response.ok) {
+        console.log("Error fetching tag list: " + await response.text());
+        return;
+    }
+    let tagList = await response.json();
+    tagMapping = {};
+    for (const tag of tagList) {
+        tagMapping[tag.tag_id] = tag.name;
+    }
+
+    // Buttons for filtering the video listing, showing the number of videos with each tag:
+    let filterTags = document.getElementById("filterTags");
+    filterTags.innerHTML = "";
+    for (const tag of tagList) {
+        let button = document.createElement('button');
+        button.type = "button";
+        button.classList.add("btn", "btn-sm", "me-1", "my-1");
+        button.classList.add(filterTagIds.has(tag.tag_id) ? "btn-primary" : "btn-outline-secondary");
+        button.title = tag.description;
+        button.innerText = `${tag.name} (${tag.video_count})`;
+        button.setAttribute("onclick", `toggleFilterTag(${tag.tag_id})`);
+        filterTags.appendChild(button);
+    }
+
+    for (const select of document.getElementsByClassName("tag-select")) {
+        let selected = new Set(Array.from(select.selectedOptions).map(opt => opt.value));
+        select.options.length = 0;
+        for (const tag of tagList) {
+            let opt = document.createElement('option');
+            opt.value = tag.tag_id;
+            opt.innerText = tag.name;
+            opt.selected = selected.has(String(tag.tag_id));
+            select.appendChild(opt);
+        }
+    }
+}
+
+function loadVideo(video, userId, dateFormat, videoTableBody) {
     let tr = document.createElement('tr');
     tr.classList.add("video-row");
     let td = document.createElement('td');
@@ -790,6 +902,18 @@ function loadVideo(video, userId, permission, dateFormat, videoTableBody) {
         textCol.appendChild(pDevNote);    
     }
 
+    if (video.tags.length > 0) {
+        let pTags = document.createElement('p');
+        pTags.classList.add("m-0");
+        for (const tagId of video.tags) {
+            let badge = document.createElement('span');
+            badge.classList.add("badge", "text-bg-secondary", "me-1");
+            badge.innerText = tagMapping[tagId] || `Tag ${tagId}`;
+            pTags.appendChild(badge);
+        }
+        textCol.appendChild(pTags);
+    }
+
     let shareCol = document.createElement('div');
     shareCol.classList.add("col-md-2");
     shareCol.classList.add("text-end");
@@ -807,7 +931,17 @@ function loadVideo(video, userId, permission, dateFormat, videoTableBody) {
     shareButton.innerHTML = '<i class="bi bi-clipboard"></i> Share';
     shareCol.appendChild(shareButton);
 
-    if (permission == "Editor" || (userId == video.created_user_id && video.status != "Approved")) {
+    let commentsButton = document.createElement('button');
+    commentsButton.classList.add("btn");
+    commentsButton.classList.add("btn-secondary");
+    commentsButton.classList.add("my-1");
+    commentsButton.classList.add("ms-2");
+    commentsButton.setAttribute("onclick", `openComments(${video.id})`);
+    commentsButton.innerHTML = '<i class="bi bi-chat"></i> Comments';
+    shareCol.appendChild(commentsButton);
+
+    if ((hasCapability("EditAnyVideo") || userId == video.created_user_id) &&
+            (hasCapability("ApproveVideos") || video.status != "Approved")) {
         let editButton = document.createElement('button');
         editButton.classList.add("btn");
         editButton.classList.add("btn-success");
@@ -835,7 +969,6 @@ function loadVideo(video, userId, permission, dateFormat, videoTableBody) {
 
 async function loadVideoBatch(oldVideoLimit, newVideoLimit) {
     let userId = localStorage.getItem("userId");
-    let permission = localStorage.getItem("permission");
     let videoTableBody = document.getElementById("videoTableBody");
     let dateFormat = new Intl.DateTimeFormat(undefined, {
         year: 'numeric',
@@ -848,7 +981,7 @@ async function loadVideoBatch(oldVideoLimit, newVideoLimit) {
 
     for (var i = oldVideoLimit; i < newVideoLimit; i++) {
         const video = videoList[i];
-        loadVideo(video, userId, permission, dateFormat, videoTableBody);
+        loadVideo(video, userId, dateFormat, videoTableBody);
     }
 }
 
@@ -927,6 +1060,7 @@ async function updateFilter() {
     let user = document.getElementById("filterUser").value;
     let status = document.getElementById("filterStatus").value;
     let notes = document.getElementById("filterNotes").value;
+    let review = document.getElementById("filterReview").value;
     let statuses = [];
     
     if (status == "") {
@@ -959,7 +1093,18 @@ async function updateFilter() {
     if (notes !== "") {
         req.notes = notes;
     }
+    if (filterTagIds.size > 0) {
+        req.tags = Array.from(filterTagIds);
+        req.tag_match = document.getElementById("filterTagMatch").value;
+    }
+    if (review == "AwaitingMyReview") {
+        req.review_filter = "AwaitingReview";
+        req.reviewer_id = parseInt(localStorage.getItem("userId"));
+    } else if (review != "") {
+        req.review_filter = review;
+    }
     req.status_list = statuses;
+    await updateTagList(req);
     req.sort_by = document.getElementById("filterSortBy").value;
     
     frameOffsets = null;
@@ -972,8 +1117,7 @@ async function updateFilter() {
 async function downloadVideos() {
     // Download the video parts into OPFS as /0.avi, /1.avi, etc.
     console.log("storage:" + (await navigator.storage.estimate()).quota);
-    let username = localStorage.getItem("username");
-    let token = localStorage.getItem("token");
+    let csrfToken = localStorage.getItem("csrfToken");
     let dir = await navigator.storage.getDirectory();
     var fileList = [];
     for (var i = 0; i < numVideoParts; i++) {
@@ -981,7 +1125,7 @@ async function downloadVideos() {
         let response = await fetch(
             `/download-video?video_id=${videoId}&part_num=${i}`, {
                 headers: {
-                    "Authorization": 'Basic ' + btoa(username + ":" + token),
+                    "X-CSRF-Token": csrfToken,
                 }
             });
         const ds = new DecompressionStream("gzip");
@@ -1044,6 +1188,7 @@ async function openEditVideo(id) {
     }
     videoId = id;
     let video = await videoResponse.json();
+    videoVersion = video.version;
 
     let room = document.getElementById("editRoom");
     room.value = video.room_id;
@@ -1065,6 +1210,11 @@ async function openEditVideo(id) {
     let devNote = document.getElementById("editDevNote");
     devNote.value = video.dev_note;
 
+    let tagIds = new Set(video.tags.map(String));
+    for (const opt of document.getElementById("editTags").options) {
+        opt.selected = tagIds.has(opt.value);
+    }
+
     let cropSize = document.getElementById("edit-cropSize");
     cropSize.value = video.crop_size;
 
@@ -1101,13 +1251,26 @@ async function openEditVideo(id) {
     document.getElementById("edit-show-preview").classList.remove("d-none");
     document.getElementById("edit-preview").classList.add("d-none");
 
-    let permission = localStorage.getItem("permission");
-    if (permission != "Editor") {
+    // Videos are approved through review, so the status can only be left as Approved, not set to it.
+    if (video.status != "Approved") {
         document.getElementById("editStatusApproved").classList.add("d-none");
     } else {
         document.getElementById("editStatusApproved").classList.remove("d-none");
     }
 
+    document.getElementById("editReviewComment").value = "";
+    if (hasCapability("ApproveVideos") && video.status == "Complete") {
+        document.getElementById("editReviewSection").classList.remove("d-none");
+    } else {
+        document.getElementById("editReviewSection").classList.add("d-none");
+    }
+    if (video.reviewer_id == localStorage.getItem("userId")) {
+        document.getElementById("editAssignToMe").classList.add("d-none");
+    } else {
+        document.getElementById("editAssignToMe").classList.remove("d-none");
+    }
+    await loadReviewHistory(id);
+
     if (video.permanent) {
         document.getElementById("deleteVideoButton").classList.add("d-none");
     } else {
@@ -1144,6 +1307,7 @@ async function submitEditVideo() {
         strat_id: tryParseInt(document.getElementById("editStrat").value),
         note: document.getElementById("editNote").value,
         dev_note: document.getElementById("editDevNote").value,
+        tags: selectedTagIds("editTags"),
         crop_size: tryParseInt(document.getElementById("edit-cropSize").value),
         crop_center_x: tryParseInt(document.getElementById("edit-cropCenterX").value),
         crop_center_y: tryParseInt(document.getElementById("edit-cropCenterY").value),
@@ -1152,17 +1316,17 @@ async function submitEditVideo() {
         highlight_end_t: tryParseInt(document.getElementById("edit-highlightEndTime").value),
         priority: tryParseInt(document.getElementById("edit-priority").value),
         controls_updated: controlsUpdated,
+        version: videoVersion,
     };
     var json = JSON.stringify(req);
 
-    let username = localStorage.getItem("username");
-    let token = localStorage.getItem("token");
+    let csrfToken = localStorage.getItem("csrfToken");
 
     var result = await fetch("/edit-video", {
         method: "POST",
         headers: {
             "Content-Type": "application/json",
-            "Authorization": 'Basic ' + btoa(username + ":" + token),
+            "X-CSRF-Token": csrfToken,
         },
         body: json
     });
@@ -1173,6 +1337,10 @@ async function submitEditVideo() {
         frameOffsets = null;
         document.getElementById("videoFile").value = null;
         updateFilter();
+    } else if (result.status == 409) {
+        console.log("Video was modified by someone else since it was opened");
+        alert("This video was changed by someone else while you were editing it. It will be reopened with the latest changes.");
+        await openEditVideo(videoId);
     } else {
         resultText = await result.text();
         console.log(`Failed to edit video: ${resultText}`);
@@ -1200,14 +1368,273 @@ function updateEditStatus() {
     form.classList.remove('was-validated');
 }
 
+async function loadReviewHistory(id) {
+    let reviewHistory = document.getElementById("editReviewHistory");
+    reviewHistory.innerHTML = "";
+    let response = await fetch(`/video-reviews?video_id=${id}`);
+    if (!response.ok) {
+        console.log(`Error status ${response.status} loading reviews: ${await response.text()}`);
+        return;
+    }
+    let dateFormat = new Intl.DateTimeFormat(undefined, {
+        year: 'numeric',
+        month: 'short',
+        day: 'numeric',
+    });
+    for (const review of await response.json()) {
+        let li = document.createElement("li");
+        let date = dateFormat.format(new Date(review.created_ts));
+        if (review.action == "Assign") {
+            let assigned = review.assigned_account_id === null ? "nobody" : (userMapping?.[review.assigned_account_id] ?? `user ${review.assigned_account_id}`);
+            li.innerText = `${date}: ${review.username} assigned review to ${assigned}`;
+        } else {
+            li.innerText = `${date}: ${review.username} (${review.action}): ${review.comment}`;
+        }
+        reviewHistory.appendChild(li);
+    }
+}
+
+async function submitReview(decision) {
+    let editModal = bootstrap.Modal.getInstance(document.getElementById("editModal"));
+    let comment = document.getElementById("editReviewComment");
+    if (comment.value.trim() == "") {
+        comment.classList.add("is-invalid");
+        return;
+    }
+    comment.classList.remove("is-invalid");
+    let csrfToken = localStorage.getItem("csrfToken");
+    let response = await fetch("/review-video", {
+        method: "POST",
+        headers: {
+            "Content-Type": "application/json",
+            "X-CSRF-Token": csrfToken,
+        },
+        body: JSON.stringify({
+            video_id: videoId,
+            version: videoVersion,
+            decision: decision,
+            comment: comment.value,
+        }),
+    });
+    if (response.ok) {
+        console.log(`Reviewed video: video_id=${videoId}, decision=${decision}`);
+        editModal.hide();
+        updateFilter();
+    } else if (response.status == 409) {
+        alert("This video was changed by someone else while you were reviewing it. It will be reopened with the latest changes.");
+        editModal.hide();
+        await openEditVideo(videoId);
+    } else {
+        console.log(`Error reviewing video ${videoId}: ${await response.text()}`);
+    }
+}
+
+async function assignReviewToMe() {
+    let csrfToken = localStorage.getItem("csrfToken");
+    let response = await fetch("/assign-reviewer", {
+        method: "POST",
+        headers: {
+            "Content-Type": "application/json",
+            "X-CSRF-Token": csrfToken,
+        },
+        body: JSON.stringify({
+            video_id: videoId,
+            reviewer_id: parseInt(localStorage.getItem("userId")),
+        }),
+    });
+    if (response.ok) {
+        document.getElementById("editAssignToMe").classList.add("d-none");
+        await loadReviewHistory(videoId);
+    } else {
+        console.log(`Error assigning review of video ${videoId}: ${await response.text()}`);
+    }
+}
+
+async function openComments(id) {
+    commentsVideoId = id;
+    resetCommentForm();
+    if (localStorage.getItem("username") !== null) {
+        document.getElementById("commentForm").classList.remove("d-none");
+    } else {
+        document.getElementById("commentForm").classList.add("d-none");
+    }
+    await loadComments();
+    let commentsModal = new bootstrap.Modal(document.getElementById("commentsModal"));
+    commentsModal.show();
+}
+
+function resetCommentForm() {
+    commentParentId = null;
+    commentEditId = null;
+    document.getElementById("commentBody").value = "";
+    document.getElementById("commentFrame").value = "";
+    document.getElementById("commentFormStatus").classList.add("d-none");
+}
+
+function setCommentFormStatus(text) {
+    document.getElementById("commentFormStatusText").innerText = text;
+    document.getElementById("commentFormStatus").classList.remove("d-none");
+    document.getElementById("commentBody").focus();
+}
+
+function renderComment(comment, repliesByParent, container) {
+    let userId = localStorage.getItem("userId");
+    let div = document.createElement("div");
+    div.classList.add("border-start", "ps-2", "my-2");
+
+    let header = document.createElement("div");
+    header.classList.add("small", "text-secondary");
+    let dateFormat = new Intl.DateTimeFormat(undefined, {
+        year: 'numeric',
+        month: 'short',
+        day: 'numeric',
+        hour12: false,
+        hour: 'numeric',
+        minute: '2-digit',
+    });
+    let headerText = `${comment.username} on ${dateFormat.format(new Date(comment.created_ts))}`;
+    if (comment.edited_ts !== null) {
+        headerText += " (edited)";
+    }
+    if (comment.frame_number !== null) {
+        headerText += ` at frame ${comment.frame_number}`;
+    }
+    header.innerText = headerText;
+    div.appendChild(header);
+
+    let body = document.createElement("div");
+    if (comment.deleted) {
+        body.classList.add("fst-italic", "text-secondary");
+        body.innerText = "[deleted]";
+    } else {
+        // The HTML is rendered and sanitized by the server.
+        body.innerHTML = comment.body_html;
+    }
+    div.appendChild(body);
+
+    if (!comment.deleted && userId !== null) {
+        let actions = document.createElement("div");
+        actions.classList.add("small");
+        let addAction = (text, onclick) => {
+            let link = document.createElement("a");
+            link.href = "#";
+            link.classList.add("me-2");
+            link.innerText = text;
+            link.onclick = () => { onclick(); return false; };
+            actions.appendChild(link);
+        };
+        addAction("Reply", () => {
+            resetCommentForm();
+            commentParentId = comment.id;
+            setCommentFormStatus(`Replying to ${comment.username}.`);
+        });
+        if (comment.account_id == userId) {
+            addAction("Edit", () => {
+                resetCommentForm();
+                commentEditId = comment.id;
+                document.getElementById("commentBody").value = comment.body;
+                document.getElementById("commentFrame").value = comment.frame_number ?? "";
+                setCommentFormStatus("Editing comment.");
+            });
+        }
+        if (comment.account_id == userId || hasCapability("EditAnyVideo")) {
+            addAction("Delete", () => deleteComment(comment.id));
+        }
+        div.appendChild(actions);
+    }
+
+    for (const reply of repliesByParent.get(comment.id) ?? []) {
+        renderComment(reply, repliesByParent, div);
+    }
+    container.appendChild(div);
+}
+
+async function loadComments() {
+    let thread = document.getElementById("commentThread");
+    thread.innerHTML = "";
+    let response = await fetch(`/video-comments?video_id=${commentsVideoId}`);
+    if (!response.ok) {
+        console.log(`Error status ${response.status} loading comments: ${await response.text()}`);
+        return;
+    }
+    let comments = await response.json();
+    let repliesByParent = new Map();
+    for (const comment of comments) {
+        if (!repliesByParent.has(comment.parent_id)) {
+            repliesByParent.set(comment.parent_id, []);
+        }
+        repliesByParent.get(comment.parent_id).push(comment);
+    }
+    for (const comment of repliesByParent.get(null) ?? []) {
+        renderComment(comment, repliesByParent, thread);
+    }
+    if (comments.length == 0) {
+        thread.innerHTML = '<p class="text-secondary">No comments yet.</p>';
+    }
+}
+
+async function submitComment() {
+    let body = document.getElementById("commentBody").value;
+    let frameNumber = tryParseInt(document.getElementById("commentFrame").value);
+    let csrfToken = localStorage.getItem("csrfToken");
+    let response;
+    if (commentEditId !== null) {
+        response = await fetch("/edit-video-comment", {
+            method: "POST",
+            headers: {
+                "Content-Type": "application/json",
+                "X-CSRF-Token": csrfToken,
+            },
+            body: JSON.stringify({id: commentEditId, body: body, frame_number: frameNumber}),
+        });
+    } else {
+        response = await fetch("/video-comments", {
+            method: "POST",
+            headers: {
+                "Content-Type": "application/json",
+                "X-CSRF-Token": csrfToken,
+            },
+            body: JSON.stringify({
+                video_id: commentsVideoId,
+                parent_id: commentParentId,
+                body: body,
+                frame_number: frameNumber,
+            }),
+        });
+    }
+    if (response.ok) {
+        resetCommentForm();
+        await loadComments();
+    } else {
+        console.log(`Error posting comment: ${await response.text()}`);
+    }
+}
+
+async function deleteComment(id) {
+    if (!confirm("Delete this comment?")) {
+        return;
+    }
+    let csrfToken = localStorage.getItem("csrfToken");
+    let response = await fetch(`/video-comments?id=${id}`, {
+        method: "DELETE",
+        headers: {
+            "X-CSRF-Token": csrfToken,
+        },
+    });
+    if (response.ok) {
+        await loadComments();
+    } else {
+        console.log(`Error deleting comment ${id}: ${await response.text()}`);
+    }
+}
+
 async function deleteVideo() {
     let editModal = bootstrap.Modal.getInstance(document.getElementById("editModal"));
-    let username = localStorage.getItem("username");
-    let token = localStorage.getItem("token");
+    let csrfToken = localStorage.getItem("csrfToken");
     let response = await fetch(`/?video_id=${videoId}`, {
         "method": "DELETE",
         "headers": {
-            "Authorization": 'Basic ' + btoa(username + ":" + token),        
+            "X-CSRF-Token": csrfToken,
         }
     });
     if (response.ok) {
@@ -1567,14 +1994,13 @@ async function postTechUpdates() {
     }
     let reqJson = JSON.stringify(reqArray);
 
-    let username = localStorage.getItem("username");
-    let token = localStorage.getItem("token");
+    let csrfToken = localStorage.getItem("csrfToken");
 
     let response = await fetch("/tech", {
         method: "POST",
         headers: {
             "Content-Type": "application/json",
-            "Authorization": 'Basic ' + btoa(username + ":" + token),
+            "X-CSRF-Token": csrfToken,
         },
         body: reqJson
     });
@@ -1600,14 +2026,13 @@ async function postNotableUpdates() {
     }
     let reqJson = JSON.stringify(reqArray);
 
-    let username = localStorage.getItem("username");
-    let token = localStorage.getItem("token");
+    let csrfToken = localStorage.getItem("csrfToken");
 
     let response = await fetch("/notables", {
         method: "POST",
         headers: {
             "Content-Type": "application/json",
-            "Authorization": 'Basic ' + btoa(username + ":" + token),
+            "X-CSRF-Token": csrfToken,
         },
         body: reqJson
     });
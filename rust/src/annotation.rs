@@ -0,0 +1,115 @@
+use std::fmt::Write as _;
+
+use serde::{Deserialize, Serialize};
+
+// A named range of frames within a video (e.g. "setup", "execution"), stored in the
+// `video.annotations` column and published as a WebVTT chapters file alongside the mp4.
+#[derive(Serialize, Deserialize, Debug, Clone, PartialEq, Eq)]
+pub struct VideoAnnotation {
+    pub start_t: i32,
+    // Exclusive:
+    pub end_t: i32,
+    pub name: String,
+}
+
+// Used for videos uploaded before the frame rate was recorded.
+pub const DEFAULT_FRAME_RATE: f64 = 60.0;
+
+fn format_timestamp(frame: i32, frame_rate: f64) -> String {
+    let total_ms = (frame as f64 * 1000.0 / frame_rate).round() as i64;
+    format!(
+        "{:02}:{:02}:{:02}.{:03}",
+        total_ms / 3_600_000,
+        total_ms / 60_000 % 60,
+        total_ms / 1000 % 60,
+        total_ms % 1000
+    )
+}
+
+fn escape_cue_text(text: &str) -> String {
+    text.replace('&', "&amp;")
+        .replace('<', "&lt;")
+        .replace('>', "&gt;")
+        .replace(['\r', '\n'], " ")
+}
+
+pub fn webvtt_chapters(annotations: &[VideoAnnotation], frame_rate: f64) -> String {
+    let mut out = "WEBVTT\n".to_string();
+    let mut sorted: Vec<&VideoAnnotation> = annotations.iter().collect();
+    sorted.sort_by_key(|a| (a.start_t, a.end_t));
+    for (i, a) in sorted.into_iter().enumerate() {
+        write!(
+            out,
+            "\n{}\n{} --> {}\n{}\n",
+            i + 1,
+            format_timestamp(a.start_t, frame_rate),
+            format_timestamp(a.end_t, frame_rate),
+            escape_cue_text(&a.name)
+        )
+        .unwrap();
+    }
+    out
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn annotation(start_t: i32, end_t: i32, name: &str) -> VideoAnnotation {
+        VideoAnnotation {
+            start_t,
+            end_t,
+            name: name.to_string(),
+        }
+    }
+
+    #[test]
+    fn timestamps() {
+        assert_eq!(format_timestamp(0, 60.0), "00:00:00.000");
+        assert_eq!(format_timestamp(90, 60.0), "00:00:01.500");
+        assert_eq!(format_timestamp(1, 60.0), "00:00:00.017");
+        assert_eq!(format_timestamp(60 * 61, 60.0), "00:01:01.000");
+        // Past an hour, and at other frame rates:
+        assert_eq!(format_timestamp(60 * 3725, 60.0), "01:02:05.000");
+        assert_eq!(format_timestamp(30 * 36000, 30.0), "10:00:00.000");
+        assert_eq!(format_timestamp(1, 29.97), "00:00:00.033");
+    }
+
+    #[test]
+    fn cue_text_escaping() {
+        assert_eq!(escape_cue_text("a & b"), "a &amp; b");
+        assert_eq!(escape_cue_text("<b>"), "&lt;b&gt;");
+        // Otherwise "-->" would end a cue's text early when parsed as a timing line:
+        assert_eq!(escape_cue_text("a --> b"), "a --&gt; b");
+        assert_eq!(escape_cue_text("&lt;"), "&amp;lt;");
+        assert_eq!(escape_cue_text("line 1\r\nline 2"), "line 1  line 2");
+    }
+
+    #[test]
+    fn chapters_sorted_by_start() {
+        let annotations = [
+            annotation(120, 180, "third"),
+            annotation(0, 60, "first"),
+            annotation(60, 90, "second & more"),
+        ];
+        assert_eq!(
+            webvtt_chapters(&annotations, 60.0),
+            "WEBVTT\n\
+             \n1\n00:00:00.000 --> 00:00:01.000\nfirst\n\
+             \n2\n00:00:01.000 --> 00:00:01.500\nsecond &amp; more\n\
+             \n3\n00:00:02.000 --> 00:00:03.000\nthird\n"
+        );
+    }
+
+    #[test]
+    fn chapters_with_same_start_sorted_by_end() {
+        let annotations = [annotation(0, 120, "long"), annotation(0, 60, "short")];
+        let chapters = webvtt_chapters(&annotations, 60.0);
+        assert!(chapters.find("short").unwrap() < chapters.find("long").unwrap());
+    }
+
+    #[test]
+    fn no_chapters() {
+        assert_eq!(webvtt_chapters(&[], 60.0), "WEBVTT\n");
+    }
+}
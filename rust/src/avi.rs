@@ -0,0 +1,544 @@
+use std::fmt;
+
+// Incremental parser for the RIFF structure of an AVI file, as produced by BizHawk.
+// Data is fed in arbitrary-sized pieces as it arrives, so that an upload can be validated
+// and its frames counted without buffering the whole file; only the `hdrl` header list is
+// held in memory. The `movi` data is skipped, and frames are counted from the `idx1` index.
+
+#[derive(Debug)]
+pub struct AviError(String);
+
+impl fmt::Display for AviError {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        write!(f, "invalid AVI: {}", self.0)
+    }
+}
+
+impl std::error::Error for AviError {}
+
+fn avi_error<T>(msg: impl Into<String>) -> Result<T, AviError> {
+    Err(AviError(msg.into()))
+}
+
+#[derive(Debug, Clone, PartialEq)]
+pub struct AviInfo {
+    pub width: i32,
+    pub height: i32,
+    pub frame_rate: f64,
+    pub frame_count: i32,
+}
+
+// The `hdrl` list is small (a few KB); anything larger indicates a corrupt or hostile file.
+const MAX_HEADER_LIST_SIZE: u32 = 1 << 20;
+const INDEX_ENTRY_SIZE: usize = 16;
+
+enum State {
+    FileHeader,
+    ChunkHeader,
+    ListType { size: u32 },
+    HeaderList { size: u32 },
+    Index { remaining: u64 },
+    Skip { remaining: u64 },
+    Trailing,
+}
+
+pub struct AviIndexer {
+    state: State,
+    buf: Vec<u8>,
+    riff_remaining: u64,
+    header: Option<AviHeader>,
+    found_movi: bool,
+    found_index: bool,
+    index_frame_count: i32,
+}
+
+#[derive(Debug)]
+struct AviHeader {
+    total_frames: u32,
+    width: u32,
+    height: u32,
+    frame_rate: f64,
+}
+
+fn read_u16(data: &[u8], pos: usize) -> Result<u16, AviError> {
+    match data.get(pos..pos + 2) {
+        Some(b) => Ok(u16::from_le_bytes([b[0], b[1]])),
+        None => avi_error("truncated header"),
+    }
+}
+
+fn read_u32(data: &[u8], pos: usize) -> Result<u32, AviError> {
+    match data.get(pos..pos + 4) {
+        Some(b) => Ok(u32::from_le_bytes([b[0], b[1], b[2], b[3]])),
+        None => avi_error("truncated header"),
+    }
+}
+
+fn fourcc(data: &[u8], pos: usize) -> Result<&[u8], AviError> {
+    match data.get(pos..pos + 4) {
+        Some(b) => Ok(b),
+        None => avi_error("truncated header"),
+    }
+}
+
+// RIFF chunks are padded to an even number of bytes.
+fn padded(size: u32) -> u64 {
+    size as u64 + (size & 1) as u64
+}
+
+fn parse_header_list(data: &[u8]) -> Result<AviHeader, AviError> {
+    if fourcc(data, 0)? != b"avih" {
+        return avi_error("missing avih header");
+    }
+    let avih_size = read_u32(data, 4)?;
+    let avih = 8;
+    let total_frames = read_u32(data, avih + 16)?;
+    let width = read_u32(data, avih + 32)?;
+    let height = read_u32(data, avih + 36)?;
+
+    // The first stream is expected to be the video stream:
+    let strl = avih + padded(avih_size) as usize;
+    if fourcc(data, strl)? != b"LIST" || fourcc(data, strl + 8)? != b"strl" {
+        return avi_error("missing strl list");
+    }
+    if fourcc(data, strl + 12)? != b"strh" {
+        return avi_error("missing strh header");
+    }
+    let strh_size = read_u32(data, strl + 16)?;
+    let strh = strl + 20;
+    if fourcc(data, strh)? != b"vids" {
+        return avi_error("first stream is not a video stream");
+    }
+    let scale = read_u32(data, strh + 20)?;
+    let rate = read_u32(data, strh + 24)?;
+    if scale == 0 || rate == 0 {
+        return avi_error("invalid frame rate");
+    }
+
+    let strf = strh + padded(strh_size) as usize;
+    if fourcc(data, strf)? != b"strf" {
+        return avi_error("missing strf header");
+    }
+    let strf_data = strf + 8;
+    let strf_width = read_u32(data, strf_data + 4)?;
+    let strf_height = read_u32(data, strf_data + 8)?;
+    let bit_count = read_u16(data, strf_data + 14)?;
+    let compression = read_u32(data, strf_data + 16)?;
+    if strf_width != width || strf_height != height {
+        return avi_error(format!(
+            "inconsistent dimensions in strf: {} x {} vs {} x {}",
+            strf_width, strf_height, width, height
+        ));
+    }
+    if bit_count != 24 {
+        return avi_error(format!("unexpected bit count (not 24): {}", bit_count));
+    }
+    if compression != 0 {
+        return avi_error(format!("unexpected compression: {}", compression));
+    }
+    if width == 0 || height == 0 || width > i32::MAX as u32 || height > i32::MAX as u32 {
+        return avi_error(format!("invalid dimensions: {} x {}", width, height));
+    }
+
+    Ok(AviHeader {
+        total_frames,
+        width,
+        height,
+        frame_rate: rate as f64 / scale as f64,
+    })
+}
+
+impl Default for AviIndexer {
+    fn default() -> Self {
+        Self::new()
+    }
+}
+
+impl AviIndexer {
+    pub fn new() -> Self {
+        AviIndexer {
+            state: State::FileHeader,
+            buf: vec![],
+            riff_remaining: 0,
+            header: None,
+            found_movi: false,
+            found_index: false,
+            index_frame_count: 0,
+        }
+    }
+
+    // Begin skipping/reading a chunk body of the given size, accounting for it in the RIFF size.
+    fn consume_chunk(&mut self, size: u64) -> Result<(), AviError> {
+        if size > self.riff_remaining {
+            return avi_error("chunk extends past end of RIFF");
+        }
+        self.riff_remaining -= size;
+        Ok(())
+    }
+
+    fn next_chunk_state(&self) -> State {
+        if self.riff_remaining == 0 {
+            State::Trailing
+        } else {
+            State::ChunkHeader
+        }
+    }
+
+    fn process_index_entry(&mut self, entry: &[u8]) {
+        let chunk_id = &entry[0..4];
+        let size = u32::from_le_bytes([entry[12], entry[13], entry[14], entry[15]]);
+        if (chunk_id == b"00db" || chunk_id == b"00dc") && size != 0 {
+            self.index_frame_count += 1;
+        }
+    }
+
+    pub fn update(&mut self, mut data: &[u8]) -> Result<(), AviError> {
+        while !data.is_empty() {
+            match self.state {
+                State::Skip { remaining } => {
+                    let n = (remaining.min(data.len() as u64)) as usize;
+                    data = &data[n..];
+                    let remaining = remaining - n as u64;
+                    self.state = if remaining == 0 {
+                        self.next_chunk_state()
+                    } else {
+                        State::Skip { remaining }
+                    };
+                }
+                State::Index { remaining } => {
+                    let n = (remaining.min(data.len() as u64)) as usize;
+                    self.buf.extend_from_slice(&data[..n]);
+                    data = &data[n..];
+                    let remaining = remaining - n as u64;
+                    let num_entries = self.buf.len() / INDEX_ENTRY_SIZE;
+                    let entries = std::mem::take(&mut self.buf);
+                    for entry in entries[..num_entries * INDEX_ENTRY_SIZE].chunks(INDEX_ENTRY_SIZE)
+                    {
+                        self.process_index_entry(entry);
+                    }
+                    self.buf = entries[num_entries * INDEX_ENTRY_SIZE..].to_vec();
+                    if remaining == 0 {
+                        // Any leftover partial entry (or pad byte) is ignored.
+                        self.buf.clear();
+                        self.state = self.next_chunk_state();
+                    } else {
+                        self.state = State::Index { remaining };
+                    }
+                }
+                State::Trailing => {
+                    // Some writers leave junk after the RIFF; it has no bearing on the frames.
+                    return Ok(());
+                }
+                _ => {
+                    let needed = match self.state {
+                        State::FileHeader => 12,
+                        State::ChunkHeader => 8,
+                        State::ListType { .. } => 4,
+                        State::HeaderList { size } => size as usize - 4,
+                        _ => unreachable!(),
+                    };
+                    let n = (needed - self.buf.len()).min(data.len());
+                    self.buf.extend_from_slice(&data[..n]);
+                    data = &data[n..];
+                    if self.buf.len() == needed {
+                        let buf = std::mem::take(&mut self.buf);
+                        self.process_buffered(&buf)?;
+                    }
+                }
+            }
+        }
+        Ok(())
+    }
+
+    fn process_buffered(&mut self, buf: &[u8]) -> Result<(), AviError> {
+        match self.state {
+            State::FileHeader => {
+                if &buf[0..4] != b"RIFF" {
+                    return avi_error("bad header: RIFF");
+                }
+                if &buf[8..12] != b"AVI " {
+                    return avi_error("bad header: AVI");
+                }
+                // The RIFF size includes the 4-byte "AVI " form type.
+                self.riff_remaining = (read_u32(buf, 4)? as u64).saturating_sub(4);
+                self.state = self.next_chunk_state();
+            }
+            State::ChunkHeader => {
+                self.consume_chunk(8)?;
+                let id = &buf[0..4];
+                let size = read_u32(buf, 4)?;
+                if id == b"LIST" {
+                    if size < 4 {
+                        return avi_error("invalid LIST size");
+                    }
+                    self.state = State::ListType { size };
+                } else if id == b"idx1" {
+                    if !self.found_movi {
+                        return avi_error("idx1 index before movi list");
+                    }
+                    self.found_index = true;
+                    self.consume_chunk(padded(size))?;
+                    self.state = State::Index {
+                        remaining: padded(size),
+                    };
+                } else {
+                    self.consume_chunk(padded(size))?;
+                    self.state = State::Skip {
+                        remaining: padded(size),
+                    };
+                }
+                if let State::Skip { remaining: 0 } | State::Index { remaining: 0 } = self.state {
+                    self.state = self.next_chunk_state();
+                }
+            }
+            State::ListType { size } => {
+                self.consume_chunk(4)?;
+                let list_type = &buf[0..4];
+                if list_type == b"hdrl" {
+                    if self.header.is_some() {
+                        return avi_error("duplicate hdrl list");
+                    }
+                    if size > MAX_HEADER_LIST_SIZE {
+                        return avi_error("hdrl list too large");
+                    }
+                    if size == 4 {
+                        return avi_error("empty hdrl list");
+                    }
+                    self.consume_chunk(padded(size) - 4)?;
+                    self.state = State::HeaderList { size };
+                } else {
+                    if list_type == b"movi" {
+                        if self.header.is_none() {
+                            return avi_error("movi list before hdrl list");
+                        }
+                        self.found_movi = true;
+                    }
+                    let remaining = padded(size) - 4;
+                    self.consume_chunk(remaining)?;
+                    self.state = if remaining == 0 {
+                        self.next_chunk_state()
+                    } else {
+                        State::Skip { remaining }
+                    };
+                }
+            }
+            State::HeaderList { size } => {
+                self.header = Some(parse_header_list(buf)?);
+                self.state = if size & 1 == 1 {
+                    State::Skip { remaining: 1 }
+                } else {
+                    self.next_chunk_state()
+                };
+            }
+            _ => unreachable!(),
+        }
+        Ok(())
+    }
+
+    pub fn finish(self) -> Result<AviInfo, AviError> {
+        match self.state {
+            State::Trailing => {}
+            State::FileHeader if self.buf.is_empty() => return avi_error("empty file"),
+            _ => return avi_error("file is truncated"),
+        }
+        let header = match self.header {
+            Some(h) => h,
+            None => return avi_error("missing hdrl list"),
+        };
+        if !self.found_movi {
+            return avi_error("missing movi list");
+        }
+        if !self.found_index {
+            return avi_error("missing idx1 index");
+        }
+        // As in the browser, allow a difference of one frame: BizHawk multi-part AVIs may have
+        // an audio frame with no corresponding video frame at a part boundary.
+        if (header.total_frames as i64 - self.index_frame_count as i64).abs() > 1 {
+            return avi_error(format!(
+                "index frame count {} does not match header frame count {}",
+                self.index_frame_count, header.total_frames
+            ));
+        }
+        Ok(AviInfo {
+            width: header.width as i32,
+            height: header.height as i32,
+            frame_rate: header.frame_rate,
+            frame_count: self.index_frame_count,
+        })
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn chunk(id: &[u8; 4], data: &[u8]) -> Vec<u8> {
+        let mut out = id.to_vec();
+        out.extend_from_slice(&(data.len() as u32).to_le_bytes());
+        out.extend_from_slice(data);
+        if data.len() % 2 == 1 {
+            out.push(0);
+        }
+        out
+    }
+
+    fn list(list_type: &[u8; 4], contents: &[u8]) -> Vec<u8> {
+        let mut data = list_type.to_vec();
+        data.extend_from_slice(contents);
+        chunk(b"LIST", &data)
+    }
+
+    fn put_u32(data: &mut [u8], pos: usize, value: u32) {
+        data[pos..pos + 4].copy_from_slice(&value.to_le_bytes());
+    }
+
+    fn header_list(total_frames: u32, width: u32, height: u32) -> Vec<u8> {
+        let mut avih = vec![0u8; 56];
+        put_u32(&mut avih, 16, total_frames);
+        put_u32(&mut avih, 32, width);
+        put_u32(&mut avih, 36, height);
+
+        let mut strh = vec![0u8; 56];
+        strh[0..4].copy_from_slice(b"vids");
+        put_u32(&mut strh, 20, 1000);
+        put_u32(&mut strh, 24, 60000);
+
+        let mut strf = vec![0u8; 40];
+        put_u32(&mut strf, 0, 40);
+        put_u32(&mut strf, 4, width);
+        put_u32(&mut strf, 8, height);
+        strf[14..16].copy_from_slice(&24u16.to_le_bytes());
+
+        let strl = list(
+            b"strl",
+            &[chunk(b"strh", &strh), chunk(b"strf", &strf)].concat(),
+        );
+        list(b"hdrl", &[chunk(b"avih", &avih), strl].concat())
+    }
+
+    // A movi list with the given frame chunk sizes, and the matching idx1 index.
+    fn movi_and_index(frame_sizes: &[u32]) -> (Vec<u8>, Vec<u8>) {
+        let mut movi = vec![];
+        let mut index = vec![];
+        for &size in frame_sizes {
+            let mut entry = b"00dc".to_vec();
+            entry.extend_from_slice(&0x10u32.to_le_bytes());
+            entry.extend_from_slice(&(movi.len() as u32 + 4).to_le_bytes());
+            entry.extend_from_slice(&size.to_le_bytes());
+            index.extend_from_slice(&entry);
+            movi.extend_from_slice(&chunk(b"00dc", &vec![0x55; size as usize]));
+        }
+        (list(b"movi", &movi), chunk(b"idx1", &index))
+    }
+
+    fn riff(chunks: &[Vec<u8>]) -> Vec<u8> {
+        let mut data = b"AVI ".to_vec();
+        data.extend_from_slice(&chunks.concat());
+        let mut out = b"RIFF".to_vec();
+        out.extend_from_slice(&(data.len() as u32).to_le_bytes());
+        out.extend_from_slice(&data);
+        out
+    }
+
+    fn valid_avi() -> Vec<u8> {
+        let (movi, index) = movi_and_index(&[6, 6, 6, 6]);
+        riff(&[header_list(4, 256, 224), movi, index])
+    }
+
+    fn index_in_pieces(data: &[u8], piece_size: usize) -> Result<AviInfo, AviError> {
+        let mut indexer = AviIndexer::new();
+        for piece in data.chunks(piece_size) {
+            indexer.update(piece)?;
+        }
+        indexer.finish()
+    }
+
+    #[test]
+    fn valid_file() {
+        let info = index_in_pieces(&valid_avi(), usize::MAX).unwrap();
+        assert_eq!(
+            info,
+            AviInfo {
+                width: 256,
+                height: 224,
+                frame_rate: 60.0,
+                frame_count: 4,
+            }
+        );
+    }
+
+    #[test]
+    fn headers_split_across_updates() {
+        let data = valid_avi();
+        let expected = index_in_pieces(&data, usize::MAX).unwrap();
+        for piece_size in [1, 2, 3, 5, 7, 13, 64] {
+            assert_eq!(index_in_pieces(&data, piece_size).unwrap(), expected);
+        }
+    }
+
+    #[test]
+    fn truncated_input() {
+        let data = valid_avi();
+        for len in 0..data.len() {
+            assert!(
+                index_in_pieces(&data[..len], 7).is_err(),
+                "accepted file truncated to {} bytes",
+                len
+            );
+        }
+    }
+
+    #[test]
+    fn trailing_junk_is_ignored() {
+        let mut data = valid_avi();
+        data.extend_from_slice(b"junk");
+        assert_eq!(index_in_pieces(&data, 5).unwrap().frame_count, 4);
+    }
+
+    #[test]
+    fn index_before_movi() {
+        let (movi, index) = movi_and_index(&[6, 6]);
+        let data = riff(&[header_list(2, 256, 224), index, movi]);
+        let err = index_in_pieces(&data, usize::MAX).unwrap_err();
+        assert!(err.to_string().contains("idx1 index before movi list"));
+    }
+
+    #[test]
+    fn odd_chunk_sizes_are_padded() {
+        let (movi, index) = movi_and_index(&[5, 7, 3]);
+        let junk = chunk(b"JUNK", &[1, 2, 3]);
+        let data = riff(&[header_list(3, 256, 224), junk, movi, index]);
+        for piece_size in [1, 3, usize::MAX] {
+            assert_eq!(index_in_pieces(&data, piece_size).unwrap().frame_count, 3);
+        }
+    }
+
+    #[test]
+    fn chunk_past_end_of_riff() {
+        let mut data = valid_avi();
+        // Shrink the RIFF size so that the idx1 chunk no longer fits:
+        let riff_size = u32::from_le_bytes([data[4], data[5], data[6], data[7]]);
+        put_u32(&mut data, 4, riff_size - 2);
+        let err = index_in_pieces(&data, usize::MAX).unwrap_err();
+        assert!(err.to_string().contains("past end of RIFF"));
+    }
+
+    #[test]
+    fn frame_count_tolerance() {
+        // Empty frame chunks (dropped frames) aren't counted:
+        let (movi, index) = movi_and_index(&[6, 0, 6, 6]);
+        let data = riff(&[header_list(4, 256, 224), movi, index]);
+        assert_eq!(index_in_pieces(&data, usize::MAX).unwrap().frame_count, 3);
+
+        for (header_frames, ok) in [(2, false), (3, true), (4, true), (5, true), (6, false)] {
+            let (movi, index) = movi_and_index(&[6, 6, 6, 6]);
+            let data = riff(&[header_list(header_frames, 256, 224), movi, index]);
+            assert_eq!(
+                index_in_pieces(&data, usize::MAX).is_ok(),
+                ok,
+                "header frame count {}",
+                header_frames
+            );
+        }
+    }
+}
@@ -0,0 +1,195 @@
+use anyhow::{bail, Result};
+use clap::{Parser, Subcommand};
+use lapin::{
+    message::BasicGetMessage,
+    options::{BasicAckOptions, BasicGetOptions, BasicNackOptions, BasicPublishOptions},
+    Channel,
+};
+use log::info;
+use map_rando_videos::encoding_job::{
+    declare_dead_letter_queue, requeue_encoding_job, EncodingJobMessage,
+};
+
+// Inspects the encoder's dead-letter queue (of tasks which failed on every attempt), and re-drives
+// tasks from it back to the encoding queue, e.g. once the cause of the failures has been fixed.
+
+#[derive(Parser)]
+struct Args {
+    #[arg(long, env)]
+    postgres_host: String,
+    #[arg(long, env)]
+    postgres_db: String,
+    #[arg(long, env)]
+    postgres_user: String,
+    #[arg(long, env)]
+    postgres_password: String,
+    #[arg(long, env)]
+    rabbit_url: String,
+    #[arg(long, env)]
+    rabbit_queue: String,
+    #[command(subcommand)]
+    command: Command,
+}
+
+#[derive(Subcommand)]
+enum Command {
+    // List the dead-lettered tasks, leaving them in the queue:
+    List,
+    // Re-publish dead-lettered tasks to the encoding queue, with their attempts reset:
+    Redrive {
+        // Only re-drive the task of this encoding job:
+        #[arg(long, required_unless_present = "all")]
+        job_id: Option<i32>,
+        // Re-drive all dead-lettered tasks:
+        #[arg(long)]
+        all: bool,
+    },
+}
+
+// Take all messages currently in the queue, without acknowledging them.
+async fn get_all_messages(channel: &Channel, queue: &str) -> Result<Vec<BasicGetMessage>> {
+    let mut messages = vec![];
+    while let Some(msg) = channel
+        .basic_get(queue, BasicGetOptions { no_ack: false })
+        .await?
+    {
+        messages.push(msg);
+    }
+    Ok(messages)
+}
+
+fn describe_message(data: &[u8]) -> String {
+    match serde_json::from_slice::<EncodingJobMessage>(data) {
+        Ok(msg) => format!(
+            "job {}: {} (video {}) failed {} times: {}",
+            msg.job_id
+                .map(|id| id.to_string())
+                .unwrap_or("-".to_string()),
+            msg.task.task_type(),
+            msg.task.video_id(),
+            msg.failed_attempts,
+            msg.last_error.as_deref().unwrap_or("")
+        ),
+        Err(_) => format!("unparseable message: {}", String::from_utf8_lossy(data)),
+    }
+}
+
+async fn list(channel: &Channel, dead_letter_queue: &str) -> Result<()> {
+    let messages = get_all_messages(channel, dead_letter_queue).await?;
+    for msg in &messages {
+        println!("{}", describe_message(&msg.delivery.data));
+    }
+    println!("{} dead-lettered tasks", messages.len());
+    for msg in messages {
+        msg.delivery
+            .nack(BasicNackOptions {
+                requeue: true,
+                ..Default::default()
+            })
+            .await?;
+    }
+    Ok(())
+}
+
+async fn redrive(
+    args: &Args,
+    db: &deadpool_postgres::Client,
+    channel: &Channel,
+    dead_letter_queue: &str,
+    job_id: Option<i32>,
+) -> Result<()> {
+    let props = lapin::BasicProperties::default().with_delivery_mode(2); // persistent delivery
+    let messages = get_all_messages(channel, dead_letter_queue).await?;
+    let mut cnt_redriven = 0;
+    for msg in messages {
+        let parsed: Option<EncodingJobMessage> = serde_json::from_slice(&msg.delivery.data).ok();
+        let selected = match (&parsed, job_id) {
+            (Some(m), Some(job_id)) => m.job_id == Some(job_id),
+            (Some(_), None) => true,
+            // Unparseable messages can't be processed, so are left in place:
+            (None, _) => false,
+        };
+        let Some(mut task_msg) = parsed.filter(|_| selected) else {
+            msg.delivery
+                .nack(BasicNackOptions {
+                    requeue: true,
+                    ..Default::default()
+                })
+                .await?;
+            continue;
+        };
+
+        info!("Re-driving {}", describe_message(&msg.delivery.data));
+        task_msg.failed_attempts = 0;
+        task_msg.last_error = None;
+        if let Some(job_id) = task_msg.job_id {
+            requeue_encoding_job(db, job_id).await?;
+        }
+        let confirmation = channel
+            .basic_publish(
+                "",
+                &args.rabbit_queue,
+                BasicPublishOptions::default(),
+                &serde_json::to_vec(&task_msg)?,
+                props.clone(),
+            )
+            .await?
+            .await?;
+        // The dead-lettered message is only removed once its copy is safely in the queue:
+        if !confirmation.is_ack() {
+            bail!(
+                "Publishing to queue '{}' was not acknowledged",
+                args.rabbit_queue
+            );
+        }
+        msg.delivery.ack(BasicAckOptions::default()).await?;
+        cnt_redriven += 1;
+    }
+    info!(
+        "Re-drove {} tasks to queue '{}'",
+        cnt_redriven, args.rabbit_queue
+    );
+    Ok(())
+}
+
+#[tokio::main]
+async fn main() -> Result<()> {
+    env_logger::Builder::from_env(env_logger::Env::default().default_filter_or("info"))
+        .format_timestamp_millis()
+        .init();
+
+    let args = Args::parse();
+
+    let mut config = deadpool_postgres::Config::new();
+    config.host = Some(args.postgres_host.clone());
+    config.dbname = Some(args.postgres_db.clone());
+    config.user = Some(args.postgres_user.clone());
+    config.password = Some(args.postgres_password.clone());
+    let db_pool = config.create_pool(
+        Some(deadpool_postgres::Runtime::Tokio1),
+        tokio_postgres::NoTls,
+    )?;
+    let db = db_pool.get().await?;
+
+    let cfg = deadpool_lapin::Config {
+        url: Some(args.rabbit_url.clone()),
+        ..Default::default()
+    };
+    let mq_pool = cfg.create_pool(Some(deadpool_lapin::Runtime::Tokio1))?;
+    let mq = mq_pool.get().await?;
+    let channel = mq.create_channel().await?;
+    channel
+        .confirm_select(lapin::options::ConfirmSelectOptions::default())
+        .await?;
+    let dead_letter_queue = declare_dead_letter_queue(&channel, &args.rabbit_queue).await?;
+
+    match &args.command {
+        Command::List => list(&channel, &dead_letter_queue).await?,
+        Command::Redrive { job_id, all } => {
+            let job_id = if *all { None } else { *job_id };
+            redrive(&args, &db, &channel, &dead_letter_queue, job_id).await?;
+        }
+    }
+    channel.close(200, "OK").await?;
+    Ok(())
+}
@@ -1,33 +1,125 @@
 use actix_web::{
-    self, delete,
+    self,
+    body::EitherBody,
+    cookie::{time::Duration as CookieDuration, Cookie, SameSite},
+    delete,
+    dev::{forward_ready, Service, ServiceRequest, ServiceResponse, Transform},
     error::ErrorNotFound,
     get,
-    http::StatusCode,
+    http::{
+        header::{self, Header as _},
+        Method, StatusCode,
+    },
     middleware::{Compress, Logger},
-    post, web, App, HttpRequest, HttpResponse, HttpServer, Responder,
+    post, put, web, App, HttpMessage as _, HttpRequest, HttpResponse, HttpServer, Responder,
+};
+use actix_web_httpauth::{
+    extractors::basic::BasicAuth,
+    headers::authorization::{Authorization, Basic},
 };
-use actix_web_httpauth::extractors::basic::BasicAuth;
 use anyhow::{bail, Context, Result};
 use askama::Template;
 use clap::Parser;
-use core::str;
 use futures::executor::block_on;
-use futures_util::StreamExt as _;
+use futures_util::{future::LocalBoxFuture, StreamExt as _};
 use log::{error, info};
-use map_rando_videos::{create_object_store, EncodingTask};
-use object_store::ObjectStore;
+use map_rando_videos::{
+    annotation::VideoAnnotation,
+    avi::{AviError, AviIndexer, AviInfo},
+    cache_invalidation::{create_cache_invalidator, CacheInvalidationArgs, CacheInvalidator},
+    create_object_store,
+    encoding_job::{create_encoding_job, finish_encoding_job, EncodingJobStatus},
+    hex_string,
+    markdown::render_markdown,
+    password::{
+        generate_token, hash_password, hash_random_token, verify_dummy_password,
+        verify_legacy_token_hash, verify_password,
+    },
+    rate_limit::RateLimiter,
+    session::{decode_session, encode_session, SessionClaims},
+    EncodingTask,
+};
+use object_store::{ObjectStore, WriteMultipart};
 use serde::{Deserialize, Serialize};
 use sha2::{Digest, Sha256};
+use std::future::{ready, Ready};
+use std::rc::Rc;
 use std::str::FromStr as _;
+use subtle::ConstantTimeEq;
 use tokio::io::AsyncReadExt as _;
 use tokio::io::AsyncWriteExt as _;
 use tokio::join;
 use tokio_postgres::types::ToSql;
 
-#[derive(strum::EnumString, Serialize)]
+// An account's role, which determines its capabilities (see `Permission::capabilities`).
+#[derive(strum::EnumString, Serialize, Deserialize, Debug, Clone, Copy)]
 enum Permission {
+    // Uploads videos, and manages their own videos until they are approved:
     Default,
+    // Reviews and approves videos by any uploader:
+    Reviewer,
+    // Maintains tech difficulties and notable strat settings:
+    Curator,
+    // Everything except account and `permanent` flag management (the role which predates the
+    // others, and had all of these abilities), including maintaining the tag vocabulary and
+    // inviting new contributors. Existing Editors who should keep managing accounts must be made
+    // Admin (see sql/upgrade.sql):
     Editor,
+    // Everything, including managing accounts and `permanent` flags:
+    Admin,
+}
+
+#[derive(Serialize, Debug, Clone, Copy, PartialEq, Eq)]
+enum Capability {
+    // Upload and submit videos, and edit or delete one's own videos which are not yet Approved:
+    UploadVideos,
+    // Edit videos uploaded by other accounts, and revert videos to earlier revisions:
+    EditAnyVideo,
+    // Set videos as Approved, and edit Approved videos:
+    ApproveVideos,
+    // Delete (non-permanent) videos uploaded by other accounts, and list or restore deleted videos:
+    DeleteAnyVideo,
+    // Update tech difficulties and notable strat settings:
+    CurateTech,
+    // Create, rename, and delete the tags which videos can be given:
+    ManageTags,
+    // Set or clear the `permanent` flag, which protects a video from deletion:
+    ManagePermanent,
+    // Create, list, and revoke invite codes (for roles with no capabilities beyond one's own):
+    ManageInviteCodes,
+    // Manage accounts, and view the audit log:
+    ManageAccounts,
+}
+
+impl Permission {
+    fn capabilities(self) -> &'static [Capability] {
+        use Capability::*;
+        match self {
+            Permission::Default => &[UploadVideos],
+            Permission::Reviewer => &[UploadVideos, EditAnyVideo, ApproveVideos],
+            Permission::Curator => &[UploadVideos, CurateTech],
+            Permission::Editor => &[
+                UploadVideos,
+                EditAnyVideo,
+                ApproveVideos,
+                DeleteAnyVideo,
+                CurateTech,
+                ManageTags,
+                ManageInviteCodes,
+            ],
+            Permission::Admin => &[
+                UploadVideos,
+                EditAnyVideo,
+                ApproveVideos,
+                DeleteAnyVideo,
+                CurateTech,
+                ManageTags,
+                ManagePermanent,
+                ManageInviteCodes,
+                ManageAccounts,
+            ],
+        }
+    }
 }
 
 #[derive(Parser)]
@@ -52,13 +144,63 @@ struct Args {
     video_storage_client_url: String,
     #[arg(long, env)]
     xz_compression_level: i32,
+    #[arg(long, env, default_value_t = 4 * 1024 * 1024 * 1024)]
+    max_upload_part_size: u64,
+    #[arg(long, env, default_value_t = 1200)]
+    max_highlight_frames: i32,
+    // Number of days that deleted videos are kept in the trash (and can be restored) before being purged:
+    #[arg(long, env, default_value_t = 30)]
+    deleted_video_retention_days: i32,
+    #[command(flatten)]
+    cache_invalidation: CacheInvalidationArgs,
+    // Secret key used to sign session cookies:
+    #[arg(long, env)]
+    session_secret: String,
+    #[arg(long, env, default_value_t = 24 * 7)]
+    session_duration_hours: i64,
+    // Whether to mark session cookies as HTTPS-only:
+    #[arg(long, env)]
+    session_cookie_secure: bool,
+    // Failed sign-in attempts allowed per account, and per IP address (which also covers invalid
+    // API tokens and invite codes), before further attempts are locked out:
+    #[arg(long, env, default_value_t = 5)]
+    login_max_failures: u64,
+    #[arg(long, env, default_value_t = 20)]
+    login_max_failures_per_ip: u64,
+    #[arg(long, env, default_value_t = 15)]
+    login_lockout_minutes: u64,
+    // Upload sessions which may be started per hour, by each account and by each IP address:
+    #[arg(long, env, default_value_t = 20)]
+    uploads_per_hour: u64,
+    // Uncompressed video bytes which may be uploaded per day, by each account and by each IP address:
+    #[arg(long, env, default_value_t = 20 * 1024 * 1024 * 1024)]
+    upload_bytes_per_day: u64,
+    // Whether to take client IP addresses for rate limiting from the `Forwarded` or
+    // `X-Forwarded-For` headers (only safe behind a reverse proxy which sets them):
+    #[arg(long, env)]
+    rate_limit_trust_proxy: bool,
 }
 
+const SESSION_COOKIE_NAME: &str = "session";
+const CSRF_HEADER_NAME: &str = "X-CSRF-Token";
+
+// How often to check for deleted videos that are due to be purged:
+const PURGE_INTERVAL: std::time::Duration = std::time::Duration::from_secs(3600);
+// Postgres advisory lock held while purging, so that only one server replica purges at a time:
+const PURGE_LOCK_ID: i64 = 7_001;
+
+// Compressed video data is sent to object storage in chunks of this size, with at most
+// this many chunks in flight per upload:
+const UPLOAD_CHUNK_SIZE: usize = 16 * 1024 * 1024;
+const UPLOAD_MAX_CONCURRENCY: usize = 2;
+
 struct AppData {
     args: Args,
     db: deadpool_postgres::Pool,
     video_store: Box<dyn ObjectStore>,
     mq: deadpool_lapin::Pool,
+    cache_invalidator: Box<dyn CacheInvalidator>,
+    rate_limiter: RateLimiter,
 }
 
 #[derive(Template)]
@@ -91,15 +233,19 @@ async fn build_app_data() -> AppData {
     let _ = db_pool.get().await.unwrap();
 
     // Create RabbitMQ connection pool
-    let mut cfg = deadpool_lapin::Config::default();
-    cfg.url = Some(args.rabbit_url.clone());
+    let cfg = deadpool_lapin::Config {
+        url: Some(args.rabbit_url.clone()),
+        ..Default::default()
+    };
     let mq_pool = cfg
         .create_pool(Some(deadpool_lapin::Runtime::Tokio1))
         .unwrap();
     let mq = mq_pool.get().await.unwrap();
     let channel = mq.create_channel().await.unwrap();
-    let mut opts = lapin::options::QueueDeclareOptions::default();
-    opts.durable = true;
+    let opts = lapin::options::QueueDeclareOptions {
+        durable: true,
+        ..Default::default()
+    };
     channel
         .queue_declare(
             &args.rabbit_queue,
@@ -114,6 +260,8 @@ async fn build_app_data() -> AppData {
         video_store: create_object_store(&args.video_storage_bucket_url),
         db: db_pool,
         mq: mq_pool,
+        cache_invalidator: create_cache_invalidator(&args.cache_invalidation).unwrap(),
+        rate_limiter: RateLimiter::default(),
         args,
     }
 }
@@ -180,25 +328,20 @@ async fn video_html(
         FROM video v
         LEFT JOIN room r ON r.room_id = v.room_id
         LEFT JOIN strat s ON s.room_id = v.room_id AND s.strat_id = v.strat_id
-        WHERE v.id = $1
+        WHERE v.id = $1 AND v.status != 'Deleted'
     "#;
     let db = app_data.db.get().await.unwrap();
-    let stmt = db.prepare_cached(&sql).await.unwrap();
+    let stmt = db.prepare_cached(sql).await.unwrap();
     let row = db
         .query_one(&stmt, &[&*video_id])
         .await
-        .map_err(|e| ErrorNotFound(e))?;
+        .map_err(ErrorNotFound)?;
 
     let room_name: Option<String> = row.get("room_name");
     let strat_name: Option<String> = row.get("strat_name");
-    let og_title = if room_name.is_some() && strat_name.is_some() {
-        Some(format!("{}: {}", room_name.unwrap(), strat_name.unwrap()))
-    } else if room_name.is_some() {
-        room_name
-    } else if strat_name.is_some() {
-        strat_name
-    } else {
-        None
+    let og_title = match (room_name, strat_name) {
+        (Some(room_name), Some(strat_name)) => Some(format!("{}: {}", room_name, strat_name)),
+        (room_name, strat_name) => room_name.or(strat_name),
     };
 
     let home_template = HomeTemplate {
@@ -222,59 +365,490 @@ async fn video_html(
         .body(home_template.render().unwrap()))
 }
 
+// What a request is allowed to do on behalf of its account. Browser sessions have full access
+// (limited by the account's permission), while API tokens are issued with a narrower scope.
+#[derive(
+    strum::EnumString, Serialize, Deserialize, Debug, Clone, Copy, PartialEq, Eq, PartialOrd, Ord,
+)]
+enum TokenScope {
+    // Read-only access to endpoints which require an account (e.g. downloading videos):
+    ReadOnly,
+    // Uploading and submitting videos:
+    Upload,
+    // Everything the account is permitted to do:
+    Editor,
+}
+
+#[derive(Clone)]
 struct AccountInfo {
     id: i32,
     permission: Permission,
+    scope: TokenScope,
+}
+
+impl AccountInfo {
+    fn can(&self, capability: Capability) -> bool {
+        self.permission.capabilities().contains(&capability)
+    }
 }
 
-async fn authenticate(app_data: web::Data<AppData>, auth: &BasicAuth) -> Result<AccountInfo> {
+async fn authenticate_password(
+    app_data: &AppData,
+    username: &str,
+    password: &str,
+) -> Result<AccountInfo> {
     let db_client = app_data.db.get().await.unwrap();
     let stmt = db_client
-        .prepare_cached("SELECT id, token_hash, permission FROM account WHERE username=$1")
+        .prepare_cached(
+            "SELECT id, token_hash, password_hash, permission FROM account WHERE username=$1 AND NOT disabled",
+        )
         .await
         .context("preparing statement")?;
-    let username = auth.user_id().to_owned();
     let result = db_client.query_opt(&stmt, &[&username]).await?;
     match result {
-        None => bail!("user not found"),
+        None => {
+            let password = password.to_owned();
+            actix_web::rt::task::spawn_blocking(move || verify_dummy_password(&password)).await?;
+            bail!("user not found")
+        }
         Some(row) => {
             let id: i32 = row.get("id");
-            let stored_token_hash: Vec<u8> = row.get("token_hash");
+            let stored_token_hash: Option<Vec<u8>> = row.get("token_hash");
+            let stored_password_hash: Option<String> = row.get("password_hash");
             let permission_str: String = row.get("permission");
 
-            let mut hasher = Sha256::new();
-            hasher.update(auth.password().unwrap_or(""));
-            let presented_token_hash: Vec<u8> = hasher.finalize().to_vec();
-
-            if stored_token_hash == presented_token_hash {
-                let permission =
-                    Permission::from_str(&permission_str).context("parsing permission")?;
-                Ok(AccountInfo { id, permission })
-            } else {
+            // Password hashing is deliberately expensive, so keep it off the async workers:
+            let password = password.to_owned();
+            let (verified, upgraded_password_hash) =
+                actix_web::rt::task::spawn_blocking(move || -> Result<(bool, Option<String>)> {
+                    match (stored_password_hash, stored_token_hash) {
+                        (Some(password_hash), _) => {
+                            Ok((verify_password(&password, &password_hash)?, None))
+                        }
+                        (None, Some(token_hash)) => {
+                            if verify_legacy_token_hash(&password, &token_hash) {
+                                Ok((true, Some(hash_password(&password)?)))
+                            } else {
+                                Ok((false, None))
+                            }
+                        }
+                        (None, None) => Ok((false, None)),
+                    }
+                })
+                .await??;
+            if !verified {
                 bail!("incorrect token")
             }
+
+            if let Some(password_hash) = upgraded_password_hash {
+                let sql = r#"
+                    UPDATE account
+                    SET password_hash = $2, token_hash = NULL
+                    WHERE id = $1 AND password_hash IS NULL
+                "#;
+                let stmt = db_client.prepare_cached(sql).await?;
+                db_client.execute(&stmt, &[&id, &password_hash]).await?;
+                info!("Rehashed legacy token for account {}", id);
+            }
+
+            let permission = Permission::from_str(&permission_str).context("parsing permission")?;
+            Ok(AccountInfo {
+                id,
+                permission,
+                scope: TokenScope::Editor,
+            })
+        }
+    }
+}
+
+async fn authenticate_api_token(app_data: &AppData, token: &str) -> Result<AccountInfo> {
+    let db_client = app_data.db.get().await?;
+    let sql = r#"
+        SELECT t.account_id, t.scope, a.permission
+        FROM api_token t
+        JOIN account a ON a.id = t.account_id
+        WHERE t.token_hash = $1
+          AND NOT a.disabled
+          AND t.revoked_ts IS NULL
+          AND (t.expires_ts IS NULL OR t.expires_ts > current_timestamp)
+    "#;
+    let stmt = db_client.prepare_cached(sql).await?;
+    let token_hash = hash_random_token(token);
+    let row = db_client
+        .query_opt(&stmt, &[&token_hash])
+        .await?
+        .context("invalid API token")?;
+    let scope_str: String = row.get("scope");
+    let permission_str: String = row.get("permission");
+    Ok(AccountInfo {
+        id: row.get("account_id"),
+        permission: Permission::from_str(&permission_str).context("parsing permission")?,
+        scope: TokenScope::from_str(&scope_str).context("parsing scope")?,
+    })
+}
+
+async fn authenticate_session(
+    app_data: &AppData,
+    req: &HttpRequest,
+    session_cookie: &str,
+) -> Result<AccountInfo> {
+    let claims = decode_session(
+        app_data.args.session_secret.as_bytes(),
+        session_cookie,
+        chrono::Utc::now().timestamp(),
+    )
+    .context("invalid or expired session")?;
+
+    // Requests which may change state must carry the session's CSRF token:
+    if !req.method().is_safe() {
+        let csrf_token = req
+            .headers()
+            .get(CSRF_HEADER_NAME)
+            .and_then(|h| h.to_str().ok())
+            .unwrap_or("");
+        if !bool::from(csrf_token.as_bytes().ct_eq(claims.csrf_token.as_bytes())) {
+            bail!("missing or incorrect CSRF token");
+        }
+    }
+
+    let db_client = app_data.db.get().await?;
+    let sql = r#"
+        SELECT permission, sessions_valid_after_ts
+        FROM account
+        WHERE id=$1 AND NOT disabled
+    "#;
+    let stmt = db_client.prepare_cached(sql).await?;
+    let row = db_client
+        .query_opt(&stmt, &[&claims.account_id])
+        .await?
+        .context("account not found")?;
+    let permission_str: String = row.get("permission");
+    let sessions_valid_after_ts: Option<chrono::DateTime<chrono::offset::Utc>> =
+        row.get("sessions_valid_after_ts");
+    if sessions_valid_after_ts.is_some_and(|ts| claims.issued_ts < ts.timestamp_millis()) {
+        bail!("session was issued before the account's token was changed");
+    }
+    Ok(AccountInfo {
+        id: claims.account_id,
+        permission: Permission::from_str(&permission_str).context("parsing permission")?,
+        scope: TokenScope::Editor,
+    })
+}
+
+// Authenticate a request, using either an API token (`Authorization: Bearer ...`) or a session
+// cookie, and check that it is allowed the given scope. Requests which the rate limiter already
+// authenticated have the result in their extensions, which is used instead.
+async fn authenticate(
+    app_data: web::Data<AppData>,
+    req: &HttpRequest,
+    scope: TokenScope,
+) -> Result<AccountInfo> {
+    let bearer_token = req
+        .headers()
+        .get(header::AUTHORIZATION)
+        .and_then(|h| h.to_str().ok())
+        .and_then(|h| h.strip_prefix("Bearer "));
+    let authenticated = req.extensions().get::<AccountInfo>().cloned();
+    let account_info = if let Some(account_info) = authenticated {
+        account_info
+    } else if let Some(token) = bearer_token {
+        authenticate_api_token(&app_data, token.trim()).await?
+    } else if let Some(cookie) = req.cookie(SESSION_COOKIE_NAME) {
+        authenticate_session(&app_data, req, cookie.value()).await?
+    } else {
+        bail!("no credentials");
+    };
+    if account_info.scope < scope {
+        bail!(
+            "{:?} scope is insufficient (requires {:?})",
+            account_info.scope,
+            scope
+        );
+    }
+    Ok(account_info)
+}
+
+// Authenticate a request as in `authenticate`, and check that the account's role has the given
+// capability. All role-based access checks should go through here (or `AccountInfo::can`, for
+// checks which depend on the video being acted on).
+async fn authorize(
+    app_data: web::Data<AppData>,
+    req: &HttpRequest,
+    scope: TokenScope,
+    capability: Capability,
+) -> actix_web::Result<AccountInfo> {
+    let account_info = match authenticate(app_data, req, scope).await {
+        Ok(ai) => ai,
+        Err(e) => {
+            error!("Failed authentication: {}", e);
+            return Err(actix_web::error::ErrorUnauthorized("Unauthorized"));
+        }
+    };
+    if !account_info.can(capability) {
+        info!(
+            "Account {} ({:?}) lacks capability {:?}",
+            account_info.id, account_info.permission, capability
+        );
+        return Err(actix_web::error::ErrorForbidden(format!(
+            "Not authorized ({:?} capability required)",
+            capability
+        )));
+    }
+    Ok(account_info)
+}
+
+const HOUR: std::time::Duration = std::time::Duration::from_secs(3600);
+const DAY: std::time::Duration = std::time::Duration::from_secs(24 * 3600);
+
+// Uncompressed size of an uploaded video part, recorded in the request extensions by
+// `upload_part` so that the rate limiter can count it against the daily quota.
+struct UploadedBytes(u64);
+
+// Middleware enforcing the configured rate limits, responding with 429 Too Many Requests (and a
+// `Retry-After` header) once a limit is reached:
+// - failed authentication attempts (sign-in, registration, or API tokens), per account and per IP,
+//   with further attempts locked out until the window ends,
+// - upload sessions started per hour, per account and per IP,
+// - uploaded bytes per day, per account and per IP.
+struct RateLimit;
+
+impl<S, B> Transform<S, ServiceRequest> for RateLimit
+where
+    S: Service<ServiceRequest, Response = ServiceResponse<B>, Error = actix_web::Error> + 'static,
+    B: 'static,
+{
+    type Response = ServiceResponse<EitherBody<B>>;
+    type Error = actix_web::Error;
+    type Transform = RateLimitMiddleware<S>;
+    type InitError = ();
+    type Future = Ready<Result<Self::Transform, Self::InitError>>;
+
+    fn new_transform(&self, service: S) -> Self::Future {
+        ready(Ok(RateLimitMiddleware {
+            service: Rc::new(service),
+        }))
+    }
+}
+
+struct RateLimitMiddleware<S> {
+    service: Rc<S>,
+}
+
+impl<S, B> Service<ServiceRequest> for RateLimitMiddleware<S>
+where
+    S: Service<ServiceRequest, Response = ServiceResponse<B>, Error = actix_web::Error> + 'static,
+    B: 'static,
+{
+    type Response = ServiceResponse<EitherBody<B>>;
+    type Error = actix_web::Error;
+    type Future = LocalBoxFuture<'static, Result<Self::Response, Self::Error>>;
+
+    forward_ready!(service);
+
+    fn call(&self, req: ServiceRequest) -> Self::Future {
+        Box::pin(call_rate_limited(self.service.clone(), req))
+    }
+}
+
+fn client_ip(req: &ServiceRequest, trust_proxy: bool) -> String {
+    if trust_proxy {
+        if let Some(ip) = req.connection_info().realip_remote_addr() {
+            return ip.to_string();
+        }
+    }
+    req.peer_addr()
+        .map(|a| a.ip().to_string())
+        .unwrap_or_default()
+}
+
+async fn call_rate_limited<S, B>(
+    service: Rc<S>,
+    req: ServiceRequest,
+) -> actix_web::Result<ServiceResponse<EitherBody<B>>>
+where
+    S: Service<ServiceRequest, Response = ServiceResponse<B>, Error = actix_web::Error>,
+{
+    let app_data = req.app_data::<web::Data<AppData>>().unwrap().clone();
+    let args = &app_data.args;
+    let limiter = &app_data.rate_limiter;
+    let ip = client_ip(&req, args.rate_limit_trust_proxy);
+    let path = req.path().to_string();
+
+    let is_sign_in = path == "/sign-in";
+    let is_auth_attempt =
+        is_sign_in || path == "/register" || req.headers().contains_key(header::AUTHORIZATION);
+    let is_new_upload = req.method() == Method::POST && path == "/upload-session";
+    let is_upload_part = req.method() == Method::PUT
+        && path.starts_with("/upload-session/")
+        && path.contains("/part/");
+
+    let mut keys: Vec<(String, u64)> = vec![];
+    let mut username: Option<String> = None;
+    if is_auth_attempt {
+        keys.push((format!("login-ip:{}", ip), args.login_max_failures_per_ip));
+    }
+    if is_sign_in {
+        if let Ok(auth) = Authorization::<Basic>::parse(&req) {
+            let user_id = auth.as_ref().user_id().to_string();
+            keys.push((
+                format!("login-account:{}", user_id),
+                args.login_max_failures,
+            ));
+            username = Some(user_id);
+        }
+    }
+    let mut account_id: Option<i32> = None;
+    if is_new_upload || is_upload_part {
+        // The endpoint reuses the account (from the request extensions) rather than
+        // authenticating again. Invalid credentials are left for the endpoint itself to reject.
+        if let Ok(account_info) =
+            authenticate(app_data.clone(), req.request(), TokenScope::Upload).await
+        {
+            account_id = Some(account_info.id);
+            req.extensions_mut().insert(account_info);
+        }
+    }
+    let upload_keys: Vec<String> = std::iter::once(format!("ip:{}", ip))
+        .chain(account_id.map(|id| format!("account:{}", id)))
+        .collect();
+    if is_new_upload {
+        for k in &upload_keys {
+            keys.push((format!("uploads-{}", k), args.uploads_per_hour));
+        }
+    }
+    // When the client declares the length of a part's (compressed) body, it is reserved against
+    // the daily quota up front, so that concurrent uploads can't all get past the check, and is
+    // adjusted to the part's uncompressed size once it is stored (or refunded if it isn't).
+    let declared_length = req
+        .headers()
+        .get(header::CONTENT_LENGTH)
+        .and_then(|h| h.to_str().ok())
+        .and_then(|s| s.parse::<u64>().ok());
+    let mut reserved_bytes = 0;
+    if is_upload_part && declared_length.is_none() {
+        for k in &upload_keys {
+            keys.push((format!("upload-bytes-{}", k), args.upload_bytes_per_day));
+        }
+    }
+
+    for (key, limit) in &keys {
+        if let Some(wait) = limiter.check(key, *limit) {
+            return Ok(too_many_requests(req, key, wait).map_into_right_body());
+        }
+    }
+    if let (true, Some(length)) = (is_upload_part, declared_length) {
+        for (i, k) in upload_keys.iter().enumerate() {
+            let key = format!("upload-bytes-{}", k);
+            if let Err(wait) = limiter.try_add(&key, length, args.upload_bytes_per_day, DAY) {
+                for k in &upload_keys[..i] {
+                    limiter.remove(&format!("upload-bytes-{}", k), length);
+                }
+                return Ok(too_many_requests(req, &key, wait).map_into_right_body());
+            }
+        }
+        reserved_bytes = length;
+    }
+
+    let res = service.call(req).await;
+    let uploaded_bytes = match &res {
+        Ok(res) if res.status().is_success() => res
+            .request()
+            .extensions()
+            .get::<UploadedBytes>()
+            .map_or(0, |b| b.0),
+        _ => 0,
+    };
+    for k in &upload_keys {
+        let key = format!("upload-bytes-{}", k);
+        if uploaded_bytes > reserved_bytes {
+            limiter.add(&key, uploaded_bytes - reserved_bytes, DAY);
+        } else if reserved_bytes > uploaded_bytes {
+            limiter.remove(&key, reserved_bytes - uploaded_bytes);
+        }
+    }
+    let res = res?;
+    let status = res.status();
+    let login_window = std::time::Duration::from_secs(args.login_lockout_minutes * 60);
+    let failed_auth = status == StatusCode::UNAUTHORIZED
+        || (path == "/register" && status == StatusCode::FORBIDDEN);
+    if is_auth_attempt && failed_auth {
+        limiter.add(&format!("login-ip:{}", ip), 1, login_window);
+        if let Some(username) = &username {
+            limiter.add(&format!("login-account:{}", username), 1, login_window);
+        }
+    } else if status.is_success() {
+        if let Some(username) = &username {
+            limiter.reset(&format!("login-account:{}", username));
+        }
+        if is_new_upload {
+            for k in &upload_keys {
+                limiter.add(&format!("uploads-{}", k), 1, HOUR);
+            }
         }
     }
+    Ok(res.map_into_left_body())
+}
+
+fn too_many_requests(req: ServiceRequest, key: &str, wait: std::time::Duration) -> ServiceResponse {
+    let retry_after_secs = wait.as_secs() + 1;
+    info!(
+        "Rate limit reached for {} ({} {}): retry after {}s",
+        key,
+        req.method(),
+        req.path(),
+        retry_after_secs
+    );
+    let response = HttpResponse::TooManyRequests()
+        .insert_header((header::RETRY_AFTER, retry_after_secs.to_string()))
+        .body("Too many requests");
+    req.into_response(response)
+}
+
+fn session_cookie(app_data: &AppData, value: String) -> Cookie<'static> {
+    Cookie::build(SESSION_COOKIE_NAME, value)
+        .path("/")
+        .http_only(true)
+        .secure(app_data.args.session_cookie_secure)
+        .same_site(SameSite::Lax)
+        .max_age(CookieDuration::hours(app_data.args.session_duration_hours))
+        .finish()
 }
 
 #[derive(Serialize)]
 struct SignInResponse {
     user_id: i32,
     permission: Permission,
+    capabilities: &'static [Capability],
+    csrf_token: String,
 }
 
-#[get("/sign-in")]
+// Exchange the account's username/token (as HTTP Basic credentials) for a session cookie.
+#[post("/sign-in")]
 async fn sign_in(
     app_data: web::Data<AppData>,
     auth: BasicAuth,
 ) -> actix_web::Result<impl Responder> {
-    match authenticate(app_data.clone(), &auth).await {
+    let password = auth.password().unwrap_or("");
+    match authenticate_password(&app_data, auth.user_id(), password).await {
         Ok(account_info) => {
+            let now = chrono::Utc::now();
+            let now_ts = now.timestamp();
+            let claims = SessionClaims {
+                account_id: account_info.id,
+                issued_ts: now.timestamp_millis(),
+                expires_ts: now_ts + app_data.args.session_duration_hours * 3600,
+                csrf_token: generate_token(),
+            };
+            let cookie_value = encode_session(app_data.args.session_secret.as_bytes(), &claims);
             let response = SignInResponse {
                 user_id: account_info.id,
                 permission: account_info.permission,
+                capabilities: account_info.permission.capabilities(),
+                csrf_token: claims.csrf_token,
             };
-            Ok(web::Json(response))
+            Ok(HttpResponse::Ok()
+                .cookie(session_cookie(&app_data, cookie_value))
+                .json(response))
         }
         Err(e) => {
             error!("Failed sign-in: {}", e);
@@ -283,612 +857,4565 @@ async fn sign_in(
     }
 }
 
-async fn try_upload_video(
-    req: &HttpRequest,
-    mut gzip_payload: web::Payload,
-    app_data: web::Data<AppData>,
-    account_info: &AccountInfo,
-) -> Result<i32> {
-    let video_id: Option<i32> = if let Some(h) = req.headers().get("X-MapRandoVideos-VideoId") {
-        let s = str::from_utf8(h.as_bytes())?;
-        Some(i32::from_str(s)?)
-    } else {
-        None
-    };
-    info!("video_id: {:?}", video_id);
-    let num_parts = {
-        let v = req
-            .headers()
-            .get("X-MapRandoVideos-NumParts")
-            .context("missing X-MapRandoVideos-NumParts")?;
-        let s = str::from_utf8(v.as_bytes())?;
-        i32::from_str(s)?
-    };
-    info!("num_parts: {}", num_parts);
-    let part_num = {
-        let v = req
-            .headers()
-            .get("X-MapRandoVideos-PartNum")
-            .context("missing X-MapRandoVideos-PartNum")?;
-        let s = str::from_utf8(v.as_bytes())?;
-        i32::from_str(s)?
-    };
-    info!("part_num: {}", part_num);
-
-    if part_num == 0 && video_id.is_some() {
-        bail!("Unexpected X-MapRandoVideos-VideoId header on first part.");
-    } else if part_num != 0 && video_id.is_none() {
-        bail!("Missing X-MapRandoVideos-VideoId header on non-first part.");
-    }
-
-    let db_client = app_data.db.get().await.unwrap();
-    let id = if let Some(id) = video_id {
-        id
-    } else {
-        let sql = "SELECT nextval('video_id_seq')";
-        let stmt = db_client.prepare_cached(sql).await?;
-        let result = db_client.query_one(&stmt, &[]).await?;
-        let id = result.get::<_, i64>(0) as i32;
-        id
-    };
-
-    if part_num != 0 {
-        let sql = r#"
-            SELECT next_part_num
-            FROM video
-            WHERE id = $1 AND created_account_id = $2
-        "#;
-        let stmt = db_client.prepare_cached(sql).await?;
-        let result = db_client.query_one(&stmt, &[&id, &account_info.id]).await?;
-        let next_part_num: i32 = result.get(0);
-        if next_part_num != part_num {
-            bail!(
-                "Out-of-sequence part number {}. Expecting {}",
-                part_num,
-                next_part_num
-            );
-        }
-    }
+#[post("/sign-out")]
+async fn sign_out(app_data: web::Data<AppData>) -> impl Responder {
+    let mut cookie = session_cookie(&app_data, "".to_string());
+    cookie.make_removal();
+    HttpResponse::Ok().cookie(cookie).body("")
+}
 
-    let mut compressed_data: Vec<u8> = vec![];
-    let xz_enc = async_compression::tokio::write::XzEncoder::with_quality(
-        &mut compressed_data,
-        async_compression::Level::Precise(app_data.args.xz_compression_level),
-    );
-    let mut gz_dec = async_compression::tokio::write::GzipDecoder::new(xz_enc);
+#[derive(Deserialize)]
+struct CreateApiTokenRequest {
+    name: String,
+    scope: TokenScope,
+    expires_in_days: Option<i32>,
+}
 
-    info!(
-        "Compressing video id={} from user_id={}",
-        id, account_info.id,
-    );
+#[derive(Serialize)]
+struct CreateApiTokenResponse {
+    id: i32,
+    // The token itself is only returned here, as only its hash is stored.
+    token: String,
+}
 
-    while let Some(item) = gzip_payload.next().await {
-        gz_dec.write(&item?).await?;
-    }
-    gz_dec.shutdown().await?;
-    let mut xz_enc = gz_dec.into_inner();
-    xz_enc.shutdown().await?;
+#[derive(Serialize)]
+struct ApiTokenListing {
+    id: i32,
+    name: String,
+    scope: TokenScope,
+    created_ts: i64,
+    expires_ts: Option<i64>,
+    revoked_ts: Option<i64>,
+}
 
-    let object_path = object_store::path::Path::parse(format!(
-        "{}avi-xz/{}-{}.avi.xz",
-        app_data.args.video_storage_prefix, id, part_num
-    ))?;
-    let compressed_len = compressed_data.len();
-    info!(
-        "Storing compressed video at {}/{} ({} bytes)",
-        app_data.args.video_storage_bucket_url, object_path, compressed_len
-    );
-    app_data
-        .video_store
-        .put(&object_path, compressed_data.into())
+async fn try_create_api_token(
+    req: &CreateApiTokenRequest,
+    app_data: &AppData,
+    account_info: &AccountInfo,
+) -> Result<CreateApiTokenResponse> {
+    let token = format!("mrv_{}", generate_token());
+    let token_hash = hash_random_token(&token);
+    let scope_str = format!("{:?}", req.scope);
+    let db_client = app_data.db.get().await?;
+    let sql = r#"
+        INSERT INTO api_token (account_id, name, scope, token_hash, expires_ts)
+        VALUES ($1, $2, $3, $4, current_timestamp + make_interval(days => $5))
+        RETURNING id
+    "#;
+    let stmt = db_client.prepare_cached(sql).await?;
+    let row = db_client
+        .query_one(
+            &stmt,
+            &[
+                &account_info.id,
+                &req.name,
+                &scope_str,
+                &token_hash,
+                &req.expires_in_days,
+            ],
+        )
         .await?;
+    let id: i32 = row.get("id");
     info!(
-        "Done storing video {}/{} ({} bytes)",
-        app_data.args.video_storage_bucket_url, object_path, compressed_len
+        "Created API token: id={}, account_id={}, scope={}",
+        id, account_info.id, scope_str
     );
-
-    if part_num == 0 {
-        let sql = r#"
-            INSERT INTO video (id, num_parts, next_part_num, status, created_account_id, updated_account_id)
-            VALUES ($1, $2, 1, 'Pending', $3, $3)
-        "#;
-        let stmt = db_client.prepare_cached(sql).await?;
-        db_client
-            .execute(&stmt, &[&id, &num_parts, &account_info.id])
-            .await?;
-        info!("Inserted video into database (id={})", id);
-    } else {
-        let sql = r#"
-            UPDATE video 
-            SET next_part_num = $1
-            WHERE id = $2
-        "#;
-        let stmt = db_client.prepare_cached(sql).await?;
-        let next_part_num = part_num + 1;
-        db_client.execute(&stmt, &[&next_part_num, &id]).await?;
-        info!("Updated next_part_num (id={})", id);
-    }
-
-    Ok(id)
+    Ok(CreateApiTokenResponse { id, token })
 }
 
-#[post("/upload-video")]
-async fn upload_video(
-    req: HttpRequest,
-    payload: web::Payload,
+#[post("/api-tokens")]
+async fn create_api_token(
+    req: web::Json<CreateApiTokenRequest>,
+    http_req: HttpRequest,
     app_data: web::Data<AppData>,
-    auth: BasicAuth,
-) -> impl Responder {
-    let account_info = match authenticate(app_data.clone(), &auth).await {
+) -> actix_web::Result<impl Responder> {
+    // Tokens with full access to the account are needed to issue other tokens:
+    let account_info = match authenticate(app_data.clone(), &http_req, TokenScope::Editor).await {
         Ok(ai) => ai,
         Err(e) => {
             error!("Failed authentication: {}", e);
-            return HttpResponse::Unauthorized().body("Unauthorized");
+            return Err(actix_web::error::ErrorUnauthorized("Unauthorized"));
         }
     };
+    let out = try_create_api_token(&req, &app_data, &account_info)
+        .await
+        .map_err(|e| actix_web::error::InternalError::new(e, StatusCode::INTERNAL_SERVER_ERROR))?;
+    Ok(web::Json(out))
+}
+
+async fn try_list_api_tokens(
+    app_data: &AppData,
+    account_info: &AccountInfo,
+) -> Result<Vec<ApiTokenListing>> {
+    let db_client = app_data.db.get().await?;
+    let sql = r#"
+        SELECT id, name, scope, created_ts, expires_ts, revoked_ts
+        FROM api_token
+        WHERE account_id = $1
+        ORDER BY id
+    "#;
+    let stmt = db_client.prepare_cached(sql).await?;
+    let rows = db_client.query(&stmt, &[&account_info.id]).await?;
+    let mut out = vec![];
+    for row in rows {
+        let scope_str: String = row.get("scope");
+        let created_ts: chrono::DateTime<chrono::offset::Utc> = row.get("created_ts");
+        let expires_ts: Option<chrono::DateTime<chrono::offset::Utc>> = row.get("expires_ts");
+        let revoked_ts: Option<chrono::DateTime<chrono::offset::Utc>> = row.get("revoked_ts");
+        out.push(ApiTokenListing {
+            id: row.get("id"),
+            name: row.get("name"),
+            scope: TokenScope::from_str(&scope_str)?,
+            created_ts: created_ts.timestamp_millis(),
+            expires_ts: expires_ts.map(|t| t.timestamp_millis()),
+            revoked_ts: revoked_ts.map(|t| t.timestamp_millis()),
+        });
+    }
+    Ok(out)
+}
 
-    let id = match try_upload_video(&req, payload, app_data, &account_info).await {
-        Ok(id) => id,
+#[get("/api-tokens")]
+async fn list_api_tokens(
+    http_req: HttpRequest,
+    app_data: web::Data<AppData>,
+) -> actix_web::Result<impl Responder> {
+    let account_info = match authenticate(app_data.clone(), &http_req, TokenScope::Editor).await {
+        Ok(ai) => ai,
         Err(e) => {
-            error!("Failed to upload video: {}", e);
-            return HttpResponse::InternalServerError().body("Failed to upload video");
+            error!("Failed authentication: {}", e);
+            return Err(actix_web::error::ErrorUnauthorized("Unauthorized"));
         }
     };
-
-    // TODO: return video ID
-    HttpResponse::Ok().body(id.to_string())
+    let out = try_list_api_tokens(&app_data, &account_info)
+        .await
+        .map_err(|e| actix_web::error::InternalError::new(e, StatusCode::INTERNAL_SERVER_ERROR))?;
+    Ok(web::Json(out))
 }
 
-#[derive(Deserialize, Debug)]
-struct SubmitVideoRequest {
-    video_id: i32,
-    room_id: Option<i32>,
-    from_node_id: Option<i32>,
-    to_node_id: Option<i32>,
-    strat_id: Option<i32>,
-    note: String,
-    dev_note: String,
-    crop_size: i32,
-    crop_center_x: i32,
-    crop_center_y: i32,
-    thumbnail_t: i32,
-    highlight_start_t: i32,
-    highlight_end_t: i32,
-    copyright_waiver: bool,
+#[derive(Deserialize)]
+struct RevokeApiTokenRequest {
+    id: i32,
 }
 
-async fn try_submit_video(
-    req_json: web::Bytes,
+#[delete("/api-tokens")]
+async fn revoke_api_token(
+    req: web::Query<RevokeApiTokenRequest>,
+    http_req: HttpRequest,
     app_data: web::Data<AppData>,
-    account_info: &AccountInfo,
-) -> Result<()> {
-    info!("submit_video: {}", std::str::from_utf8(&req_json)?);
-    let req: SubmitVideoRequest = serde_json::from_slice(&req_json)?;
+) -> actix_web::Result<impl Responder> {
+    let account_info = match authenticate(app_data.clone(), &http_req, TokenScope::Editor).await {
+        Ok(ai) => ai,
+        Err(e) => {
+            error!("Failed authentication: {}", e);
+            return Err(actix_web::error::ErrorUnauthorized("Unauthorized"));
+        }
+    };
+    let db_client = app_data
+        .db
+        .get()
+        .await
+        .map_err(actix_web::error::ErrorInternalServerError)?;
+    let sql = r#"
+        UPDATE api_token
+        SET revoked_ts = current_timestamp
+        WHERE id = $1 AND account_id = $2 AND revoked_ts IS NULL
+    "#;
+    let stmt = db_client
+        .prepare_cached(sql)
+        .await
+        .map_err(actix_web::error::ErrorInternalServerError)?;
+    let cnt = db_client
+        .execute(&stmt, &[&req.id, &account_info.id])
+        .await
+        .map_err(actix_web::error::ErrorInternalServerError)?;
+    if cnt == 0 {
+        return Err(actix_web::error::ErrorNotFound("API token not found"));
+    }
+    info!(
+        "Revoked API token: id={}, account_id={}",
+        req.id, account_info.id
+    );
+    Ok(HttpResponse::Ok().body(""))
+}
 
-    if !req.copyright_waiver {
-        bail!("copyright_waiver not checked");
+#[derive(Serialize, Debug)]
+enum AuditAction {
+    CreateAccount,
+    UpdateAccount,
+    ResetToken,
+    RegenerateToken,
+    CreateInviteCode,
+    RevokeInviteCode,
+    RedeemInviteCode,
+    SetVideoPermanent,
+}
+
+async fn record_audit_event(
+    db: &impl deadpool_postgres::GenericClient,
+    actor_account_id: i32,
+    action: AuditAction,
+    target_account_id: Option<i32>,
+    details: serde_json::Value,
+) -> Result<()> {
+    let sql = r#"
+        INSERT INTO audit_log (actor_account_id, action, target_account_id, details)
+        VALUES ($1, $2, $3, $4)
+    "#;
+    let stmt = db.prepare_cached(sql).await?;
+    let action_str = format!("{:?}", action);
+    db.execute(
+        &stmt,
+        &[&actor_account_id, &action_str, &target_account_id, &details],
+    )
+    .await?;
+    info!(
+        "Audit: actor={}, action={}, target={:?}, details={}",
+        actor_account_id, action_str, target_account_id, details
+    );
+    Ok(())
+}
+
+fn validate_username(username: &str) -> Vec<FieldError> {
+    let mut errors = vec![];
+    if username.trim().is_empty() || username.trim() != username {
+        errors.push(FieldError {
+            field: "username",
+            message: "must be non-empty, without leading or trailing spaces".to_string(),
+        });
+    } else if username.chars().count() > 100 {
+        errors.push(FieldError {
+            field: "username",
+            message: "must be at most 100 characters".to_string(),
+        });
+    } else if username.contains(':') {
+        errors.push(FieldError {
+            field: "username",
+            message: "must not contain ':'".to_string(),
+        });
     }
-    let status: &'static str;
-    if req.room_id.is_some()
-        && req.from_node_id.is_some()
-        && req.to_node_id.is_some()
-        && req.strat_id.is_some()
+    errors
+}
+
+async fn username_taken(
+    db: &impl deadpool_postgres::GenericClient,
+    username: &str,
+    exclude_account_id: Option<i32>,
+) -> Result<bool> {
+    let sql = "SELECT id FROM account WHERE lower(username) = lower($1)";
+    let stmt = db.prepare_cached(sql).await?;
+    let rows = db.query(&stmt, &[&username]).await?;
+    Ok(rows
+        .iter()
+        .any(|row| Some(row.get::<_, i32>("id")) != exclude_account_id))
+}
+
+fn username_taken_error() -> FieldError {
+    FieldError {
+        field: "username",
+        message: "is already taken".to_string(),
+    }
+}
+
+// Whether a statement failed on a unique constraint, e.g. as a concurrent request inserted the same
+// username after it was checked.
+fn is_unique_violation(e: &tokio_postgres::Error) -> bool {
+    e.code() == Some(&tokio_postgres::error::SqlState::UNIQUE_VIOLATION)
+}
+
+// Generate a new random token for an account, returning it along with its hash.
+async fn new_account_token() -> Result<(String, String)> {
+    actix_web::rt::task::spawn_blocking(|| {
+        let token = generate_token();
+        let password_hash = hash_password(&token)?;
+        Ok((token, password_hash))
+    })
+    .await?
+}
+
+#[derive(Serialize)]
+struct AccountListing {
+    id: i32,
+    username: String,
+    permission: Permission,
+    created_ts: Option<i64>,
+    active: bool,
+    disabled: bool,
+}
+
+async fn try_list_accounts(app_data: &AppData) -> Result<Vec<AccountListing>> {
+    let db_client = app_data.db.get().await?;
+    let sql = r#"
+        SELECT id, username, permission, created_ts, active, disabled
+        FROM account
+        ORDER BY id
+    "#;
+    let stmt = db_client.prepare_cached(sql).await?;
+    let rows = db_client.query(&stmt, &[]).await?;
+    let mut out = vec![];
+    for row in rows {
+        let permission_str: String = row.get("permission");
+        let created_ts: Option<chrono::DateTime<chrono::offset::Utc>> = row.get("created_ts");
+        let active: Option<bool> = row.get("active");
+        out.push(AccountListing {
+            id: row.get("id"),
+            username: row.get("username"),
+            permission: Permission::from_str(&permission_str)?,
+            created_ts: created_ts.map(|t| t.timestamp_millis()),
+            active: active.unwrap_or(false),
+            disabled: row.get("disabled"),
+        });
+    }
+    Ok(out)
+}
+
+#[get("/admin/accounts")]
+async fn list_accounts(
+    http_req: HttpRequest,
+    app_data: web::Data<AppData>,
+) -> actix_web::Result<impl Responder> {
+    authorize(
+        app_data.clone(),
+        &http_req,
+        TokenScope::Editor,
+        Capability::ManageAccounts,
+    )
+    .await?;
+    let out = try_list_accounts(&app_data)
+        .await
+        .map_err(|e| actix_web::error::InternalError::new(e, StatusCode::INTERNAL_SERVER_ERROR))?;
+    Ok(web::Json(out))
+}
+
+#[derive(Deserialize)]
+struct CreateAccountRequest {
+    username: String,
+    permission: Permission,
+}
+
+#[derive(Serialize)]
+struct AccountTokenResponse {
+    id: i32,
+    // The token is only returned once, as only its hash is stored.
+    token: String,
+}
+
+async fn try_create_account(
+    req: &CreateAccountRequest,
+    app_data: &AppData,
+    account_info: &AccountInfo,
+) -> Result<AccountTokenResponse> {
+    let mut db_client = app_data.db.get().await?;
+    let mut errors = validate_username(&req.username);
+    if errors.is_empty() && username_taken(&db_client, &req.username, None).await? {
+        errors.push(username_taken_error());
+    }
+    if !errors.is_empty() {
+        return Err(ValidationErrors { errors }.into());
+    }
+
+    let (token, password_hash) = new_account_token().await?;
+    let permission_str = format!("{:?}", req.permission);
+    let sql = r#"
+        INSERT INTO account (username, password_hash, permission)
+        VALUES ($1, $2, $3)
+        RETURNING id
+    "#;
+    let txn = db_client.transaction().await?;
+    let stmt = txn.prepare_cached(sql).await?;
+    let row = match txn
+        .query_one(&stmt, &[&req.username, &password_hash, &permission_str])
+        .await
     {
-        status = "Complete";
-    } else {
-        status = "Incomplete";
+        Ok(row) => row,
+        Err(e) if is_unique_violation(&e) => {
+            let errors = vec![username_taken_error()];
+            return Err(ValidationErrors { errors }.into());
+        }
+        Err(e) => return Err(e.into()),
+    };
+    let id: i32 = row.get("id");
+    record_audit_event(
+        &txn,
+        account_info.id,
+        AuditAction::CreateAccount,
+        Some(id),
+        serde_json::json!({"username": req.username, "permission": permission_str}),
+    )
+    .await?;
+    txn.commit().await?;
+    Ok(AccountTokenResponse { id, token })
+}
+
+#[post("/admin/accounts")]
+async fn create_account(
+    req: web::Json<CreateAccountRequest>,
+    http_req: HttpRequest,
+    app_data: web::Data<AppData>,
+) -> actix_web::Result<impl Responder> {
+    let account_info = authorize(
+        app_data.clone(),
+        &http_req,
+        TokenScope::Editor,
+        Capability::ManageAccounts,
+    )
+    .await?;
+    match try_create_account(&req, &app_data, &account_info).await {
+        Ok(out) => Ok(HttpResponse::Ok().json(out)),
+        Err(e) if e.is::<ValidationErrors>() => {
+            Ok(HttpResponse::BadRequest().json(e.downcast_ref::<ValidationErrors>()))
+        }
+        Err(e) => {
+            error!("Failed to create account: {}", e);
+            Err(actix_web::error::ErrorInternalServerError(
+                "Failed to create account",
+            ))
+        }
+    }
+}
+
+#[derive(Deserialize)]
+struct UpdateAccountRequest {
+    account_id: i32,
+    username: Option<String>,
+    permission: Option<Permission>,
+    disabled: Option<bool>,
+}
+
+async fn try_update_account(
+    req: &UpdateAccountRequest,
+    app_data: &AppData,
+    account_info: &AccountInfo,
+) -> Result<bool> {
+    let mut db_client = app_data.db.get().await?;
+    let mut errors = vec![];
+    if let Some(username) = &req.username {
+        errors.extend(validate_username(username));
+        if errors.is_empty() && username_taken(&db_client, username, Some(req.account_id)).await? {
+            errors.push(username_taken_error());
+        }
+    }
+    if req.account_id == account_info.id && (req.permission.is_some() || req.disabled == Some(true))
+    {
+        // Avoid editors accidentally locking themselves out.
+        errors.push(FieldError {
+            field: "account_id",
+            message: "cannot change the permission of or disable your own account".to_string(),
+        });
+    }
+    if !errors.is_empty() {
+        return Err(ValidationErrors { errors }.into());
+    }
+
+    // Disabling an account also ends its sessions.
+    let sql = r#"
+        UPDATE account
+        SET username = COALESCE($2, username),
+            permission = COALESCE($3, permission),
+            disabled = COALESCE($4, disabled),
+            sessions_valid_after_ts = CASE WHEN $4 THEN current_timestamp ELSE sessions_valid_after_ts END
+        WHERE id = $1
+    "#;
+    let permission_str = req.permission.as_ref().map(|p| format!("{:?}", p));
+    let txn = db_client.transaction().await?;
+    let stmt = txn.prepare_cached(sql).await?;
+    let cnt = match txn
+        .execute(
+            &stmt,
+            &[
+                &req.account_id,
+                &req.username,
+                &permission_str,
+                &req.disabled,
+            ],
+        )
+        .await
+    {
+        Ok(cnt) => cnt,
+        Err(e) if is_unique_violation(&e) => {
+            let errors = vec![username_taken_error()];
+            return Err(ValidationErrors { errors }.into());
+        }
+        Err(e) => return Err(e.into()),
+    };
+    if cnt == 0 {
+        return Ok(false);
+    }
+    record_audit_event(
+        &txn,
+        account_info.id,
+        AuditAction::UpdateAccount,
+        Some(req.account_id),
+        serde_json::json!({
+            "username": req.username,
+            "permission": permission_str,
+            "disabled": req.disabled,
+        }),
+    )
+    .await?;
+    txn.commit().await?;
+    Ok(true)
+}
+
+#[post("/admin/update-account")]
+async fn update_account(
+    req: web::Json<UpdateAccountRequest>,
+    http_req: HttpRequest,
+    app_data: web::Data<AppData>,
+) -> actix_web::Result<impl Responder> {
+    let account_info = authorize(
+        app_data.clone(),
+        &http_req,
+        TokenScope::Editor,
+        Capability::ManageAccounts,
+    )
+    .await?;
+    match try_update_account(&req, &app_data, &account_info).await {
+        Ok(true) => Ok(HttpResponse::Ok().body("")),
+        Ok(false) => Err(actix_web::error::ErrorNotFound("account not found")),
+        Err(e) if e.is::<ValidationErrors>() => {
+            Ok(HttpResponse::BadRequest().json(e.downcast_ref::<ValidationErrors>()))
+        }
+        Err(e) => {
+            error!("Failed to update account: {}", e);
+            Err(actix_web::error::ErrorInternalServerError(
+                "Failed to update account",
+            ))
+        }
+    }
+}
+
+// Replace an account's token with a newly generated one, ending any existing sessions and revoking
+// its API tokens.
+async fn reset_account_token(
+    app_data: &AppData,
+    account_id: i32,
+    actor_account_id: i32,
+    action: AuditAction,
+) -> Result<Option<AccountTokenResponse>> {
+    let (token, password_hash) = new_account_token().await?;
+    let mut db_client = app_data.db.get().await?;
+    let sql = r#"
+        UPDATE account
+        SET password_hash = $2,
+            token_hash = NULL,
+            sessions_valid_after_ts = current_timestamp
+        WHERE id = $1
+    "#;
+    let txn = db_client.transaction().await?;
+    let stmt = txn.prepare_cached(sql).await?;
+    let cnt = txn.execute(&stmt, &[&account_id, &password_hash]).await?;
+    if cnt == 0 {
+        return Ok(None);
+    }
+    let sql = r#"
+        UPDATE api_token
+        SET revoked_ts = current_timestamp
+        WHERE account_id = $1 AND revoked_ts IS NULL
+    "#;
+    let stmt = txn.prepare_cached(sql).await?;
+    txn.execute(&stmt, &[&account_id]).await?;
+    record_audit_event(
+        &txn,
+        actor_account_id,
+        action,
+        Some(account_id),
+        serde_json::json!({}),
+    )
+    .await?;
+    txn.commit().await?;
+    Ok(Some(AccountTokenResponse {
+        id: account_id,
+        token,
+    }))
+}
+
+#[derive(Deserialize)]
+struct ResetTokenRequest {
+    account_id: i32,
+}
+
+#[post("/admin/reset-token")]
+async fn reset_token(
+    req: web::Json<ResetTokenRequest>,
+    http_req: HttpRequest,
+    app_data: web::Data<AppData>,
+) -> actix_web::Result<impl Responder> {
+    let account_info = authorize(
+        app_data.clone(),
+        &http_req,
+        TokenScope::Editor,
+        Capability::ManageAccounts,
+    )
+    .await?;
+    match reset_account_token(
+        &app_data,
+        req.account_id,
+        account_info.id,
+        AuditAction::ResetToken,
+    )
+    .await
+    {
+        Ok(Some(out)) => Ok(web::Json(out)),
+        Ok(None) => Err(actix_web::error::ErrorNotFound("account not found")),
+        Err(e) => {
+            error!("Failed to reset token: {}", e);
+            Err(actix_web::error::ErrorInternalServerError(
+                "Failed to reset token",
+            ))
+        }
+    }
+}
+
+// Self-service rotation of the signed-in account's own token.
+#[post("/regenerate-token")]
+async fn regenerate_token(
+    http_req: HttpRequest,
+    app_data: web::Data<AppData>,
+) -> actix_web::Result<impl Responder> {
+    let account_info = match authenticate(app_data.clone(), &http_req, TokenScope::Editor).await {
+        Ok(ai) => ai,
+        Err(e) => {
+            error!("Failed authentication: {}", e);
+            return Err(actix_web::error::ErrorUnauthorized("Unauthorized"));
+        }
+    };
+    match reset_account_token(
+        &app_data,
+        account_info.id,
+        account_info.id,
+        AuditAction::RegenerateToken,
+    )
+    .await
+    {
+        Ok(Some(out)) => Ok(web::Json(out)),
+        Ok(None) => Err(actix_web::error::ErrorNotFound("account not found")),
+        Err(e) => {
+            error!("Failed to regenerate token: {}", e);
+            Err(actix_web::error::ErrorInternalServerError(
+                "Failed to regenerate token",
+            ))
+        }
+    }
+}
+
+#[derive(Deserialize)]
+struct AuditLogRequest {
+    limit: Option<i64>,
+    offset: Option<i64>,
+}
+
+#[derive(Serialize)]
+struct AuditLogEntry {
+    id: i32,
+    ts: i64,
+    actor_account_id: i32,
+    action: String,
+    target_account_id: Option<i32>,
+    details: serde_json::Value,
+}
+
+async fn try_list_audit_log(
+    req: &AuditLogRequest,
+    app_data: &AppData,
+) -> Result<Vec<AuditLogEntry>> {
+    let db_client = app_data.db.get().await?;
+    let sql = r#"
+        SELECT id, ts, actor_account_id, action, target_account_id, details
+        FROM audit_log
+        ORDER BY id DESC
+        LIMIT $1 OFFSET $2
+    "#;
+    let stmt = db_client.prepare_cached(sql).await?;
+    let limit = req.limit.unwrap_or(100);
+    let offset = req.offset.unwrap_or(0);
+    let rows = db_client.query(&stmt, &[&limit, &offset]).await?;
+    let mut out = vec![];
+    for row in rows {
+        let ts: chrono::DateTime<chrono::offset::Utc> = row.get("ts");
+        out.push(AuditLogEntry {
+            id: row.get("id"),
+            ts: ts.timestamp_millis(),
+            actor_account_id: row.get("actor_account_id"),
+            action: row.get("action"),
+            target_account_id: row.get("target_account_id"),
+            details: row.get("details"),
+        });
+    }
+    Ok(out)
+}
+
+#[get("/admin/audit-log")]
+async fn list_audit_log(
+    req: web::Query<AuditLogRequest>,
+    http_req: HttpRequest,
+    app_data: web::Data<AppData>,
+) -> actix_web::Result<impl Responder> {
+    authorize(
+        app_data.clone(),
+        &http_req,
+        TokenScope::Editor,
+        Capability::ManageAccounts,
+    )
+    .await?;
+    let out = try_list_audit_log(&req, &app_data)
+        .await
+        .map_err(|e| actix_web::error::InternalError::new(e, StatusCode::INTERNAL_SERVER_ERROR))?;
+    Ok(web::Json(out))
+}
+
+#[derive(Deserialize)]
+struct CreateInviteCodeRequest {
+    max_uses: Option<i32>,
+    expires_in_days: Option<i32>,
+    permission: Option<Permission>,
+}
+
+#[derive(Serialize)]
+struct CreateInviteCodeResponse {
+    id: i32,
+    // The code is only returned once, as only its hash is stored.
+    code: String,
+    expires_ts: i64,
+}
+
+async fn try_create_invite_code(
+    req: &CreateInviteCodeRequest,
+    app_data: &AppData,
+    account_info: &AccountInfo,
+) -> Result<CreateInviteCodeResponse> {
+    let max_uses = req.max_uses.unwrap_or(1);
+    let expires_in_days = req.expires_in_days.unwrap_or(7);
+    let mut errors = vec![];
+    if max_uses < 1 {
+        errors.push(FieldError {
+            field: "max_uses",
+            message: "must be at least 1".to_string(),
+        });
+    }
+    if expires_in_days < 1 {
+        errors.push(FieldError {
+            field: "expires_in_days",
+            message: "must be at least 1".to_string(),
+        });
+    }
+    let permission = req.permission.unwrap_or(Permission::Default);
+    if !permission
+        .capabilities()
+        .iter()
+        .all(|&c| account_info.can(c))
+    {
+        errors.push(FieldError {
+            field: "permission",
+            message: "must not have capabilities beyond your own".to_string(),
+        });
+    }
+    if !errors.is_empty() {
+        return Err(ValidationErrors { errors }.into());
+    }
+
+    let code = generate_token();
+    let code_hash = hash_random_token(&code);
+    let permission_str = format!("{:?}", permission);
+    let mut db_client = app_data.db.get().await?;
+    let sql = r#"
+        INSERT INTO invite_code (code_hash, created_account_id, expires_ts, max_uses, permission)
+        VALUES ($1, $2, current_timestamp + make_interval(days => $3), $4, $5)
+        RETURNING id, expires_ts
+    "#;
+    let txn = db_client.transaction().await?;
+    let stmt = txn.prepare_cached(sql).await?;
+    let row = txn
+        .query_one(
+            &stmt,
+            &[
+                &code_hash,
+                &account_info.id,
+                &expires_in_days,
+                &max_uses,
+                &permission_str,
+            ],
+        )
+        .await?;
+    let id: i32 = row.get("id");
+    let expires_ts: chrono::DateTime<chrono::offset::Utc> = row.get("expires_ts");
+    record_audit_event(
+        &txn,
+        account_info.id,
+        AuditAction::CreateInviteCode,
+        None,
+        serde_json::json!({
+            "invite_code_id": id,
+            "max_uses": max_uses,
+            "expires_in_days": expires_in_days,
+            "permission": permission_str,
+        }),
+    )
+    .await?;
+    txn.commit().await?;
+    Ok(CreateInviteCodeResponse {
+        id,
+        code,
+        expires_ts: expires_ts.timestamp_millis(),
+    })
+}
+
+#[post("/invite-codes")]
+async fn create_invite_code(
+    req: web::Json<CreateInviteCodeRequest>,
+    http_req: HttpRequest,
+    app_data: web::Data<AppData>,
+) -> actix_web::Result<impl Responder> {
+    let account_info = authorize(
+        app_data.clone(),
+        &http_req,
+        TokenScope::Editor,
+        Capability::ManageInviteCodes,
+    )
+    .await?;
+    match try_create_invite_code(&req, &app_data, &account_info).await {
+        Ok(out) => Ok(HttpResponse::Ok().json(out)),
+        Err(e) if e.is::<ValidationErrors>() => {
+            Ok(HttpResponse::BadRequest().json(e.downcast_ref::<ValidationErrors>()))
+        }
+        Err(e) => {
+            error!("Failed to create invite code: {}", e);
+            Err(actix_web::error::ErrorInternalServerError(
+                "Failed to create invite code",
+            ))
+        }
+    }
+}
+
+#[derive(Serialize)]
+struct InviteCodeListing {
+    id: i32,
+    created_user_id: i32,
+    created_ts: i64,
+    expires_ts: i64,
+    revoked_ts: Option<i64>,
+    max_uses: i32,
+    use_count: i32,
+    permission: Permission,
+}
+
+async fn try_list_invite_codes(app_data: &AppData) -> Result<Vec<InviteCodeListing>> {
+    let db_client = app_data.db.get().await?;
+    let sql = r#"
+        SELECT id, created_account_id, created_ts, expires_ts, revoked_ts, max_uses, use_count, permission
+        FROM invite_code
+        ORDER BY id DESC
+    "#;
+    let stmt = db_client.prepare_cached(sql).await?;
+    let rows = db_client.query(&stmt, &[]).await?;
+    let mut out = vec![];
+    for row in rows {
+        let created_ts: chrono::DateTime<chrono::offset::Utc> = row.get("created_ts");
+        let expires_ts: chrono::DateTime<chrono::offset::Utc> = row.get("expires_ts");
+        let revoked_ts: Option<chrono::DateTime<chrono::offset::Utc>> = row.get("revoked_ts");
+        let permission_str: String = row.get("permission");
+        out.push(InviteCodeListing {
+            id: row.get("id"),
+            created_user_id: row.get("created_account_id"),
+            created_ts: created_ts.timestamp_millis(),
+            expires_ts: expires_ts.timestamp_millis(),
+            revoked_ts: revoked_ts.map(|t| t.timestamp_millis()),
+            max_uses: row.get("max_uses"),
+            use_count: row.get("use_count"),
+            permission: Permission::from_str(&permission_str)?,
+        });
+    }
+    Ok(out)
+}
+
+#[get("/invite-codes")]
+async fn list_invite_codes(
+    http_req: HttpRequest,
+    app_data: web::Data<AppData>,
+) -> actix_web::Result<impl Responder> {
+    authorize(
+        app_data.clone(),
+        &http_req,
+        TokenScope::Editor,
+        Capability::ManageInviteCodes,
+    )
+    .await?;
+    let out = try_list_invite_codes(&app_data)
+        .await
+        .map_err(|e| actix_web::error::InternalError::new(e, StatusCode::INTERNAL_SERVER_ERROR))?;
+    Ok(web::Json(out))
+}
+
+#[derive(Deserialize)]
+struct RevokeInviteCodeRequest {
+    id: i32,
+}
+
+async fn try_revoke_invite_code(
+    req: &RevokeInviteCodeRequest,
+    app_data: &AppData,
+    account_info: &AccountInfo,
+) -> Result<bool> {
+    let mut db_client = app_data.db.get().await?;
+    let sql = r#"
+        UPDATE invite_code
+        SET revoked_ts = current_timestamp
+        WHERE id = $1 AND revoked_ts IS NULL
+    "#;
+    let txn = db_client.transaction().await?;
+    let stmt = txn.prepare_cached(sql).await?;
+    let cnt = txn.execute(&stmt, &[&req.id]).await?;
+    if cnt == 0 {
+        return Ok(false);
+    }
+    record_audit_event(
+        &txn,
+        account_info.id,
+        AuditAction::RevokeInviteCode,
+        None,
+        serde_json::json!({"invite_code_id": req.id}),
+    )
+    .await?;
+    txn.commit().await?;
+    Ok(true)
+}
+
+#[delete("/invite-codes")]
+async fn revoke_invite_code(
+    req: web::Query<RevokeInviteCodeRequest>,
+    http_req: HttpRequest,
+    app_data: web::Data<AppData>,
+) -> actix_web::Result<impl Responder> {
+    let account_info = authorize(
+        app_data.clone(),
+        &http_req,
+        TokenScope::Editor,
+        Capability::ManageInviteCodes,
+    )
+    .await?;
+    match try_revoke_invite_code(&req, &app_data, &account_info).await {
+        Ok(true) => Ok(HttpResponse::Ok().body("")),
+        Ok(false) => Err(actix_web::error::ErrorNotFound("invite code not found")),
+        Err(e) => {
+            error!("Failed to revoke invite code: {}", e);
+            Err(actix_web::error::ErrorInternalServerError(
+                "Failed to revoke invite code",
+            ))
+        }
+    }
+}
+
+#[derive(Deserialize)]
+struct RegisterRequest {
+    code: String,
+    username: String,
+}
+
+// The invite code is missing, expired, revoked or used up.
+#[derive(Debug)]
+struct InvalidInviteCode;
+
+impl std::fmt::Display for InvalidInviteCode {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        write!(f, "invalid invite code")
+    }
+}
+
+impl std::error::Error for InvalidInviteCode {}
+
+async fn try_register(req: &RegisterRequest, app_data: &AppData) -> Result<AccountTokenResponse> {
+    let mut db_client = app_data.db.get().await?;
+    let code_hash = hash_random_token(req.code.trim());
+    let txn = db_client.transaction().await?;
+
+    // Claim a use of the code first, so that without a valid code nothing is revealed about which
+    // usernames are taken (and no time is spent hashing). The conditions make this safe against
+    // concurrent redemptions, and the claim is rolled back if registering fails.
+    let sql = r#"
+        UPDATE invite_code
+        SET use_count = use_count + 1
+        WHERE code_hash = $1
+          AND revoked_ts IS NULL
+          AND expires_ts > current_timestamp
+          AND use_count < max_uses
+        RETURNING id, permission
+    "#;
+    let stmt = txn.prepare_cached(sql).await?;
+    let Some(row) = txn.query_opt(&stmt, &[&code_hash]).await? else {
+        return Err(InvalidInviteCode.into());
+    };
+    let invite_code_id: i32 = row.get("id");
+    let permission_str: String = row.get("permission");
+
+    let mut errors = validate_username(&req.username);
+    if errors.is_empty() && username_taken(&txn, &req.username, None).await? {
+        errors.push(username_taken_error());
+    }
+    if !errors.is_empty() {
+        return Err(ValidationErrors { errors }.into());
+    }
+
+    let (token, password_hash) = new_account_token().await?;
+    let sql = r#"
+        INSERT INTO account (username, password_hash, permission, invite_code_id)
+        VALUES ($1, $2, $3, $4)
+        RETURNING id
+    "#;
+    let stmt = txn.prepare_cached(sql).await?;
+    let row = match txn
+        .query_one(
+            &stmt,
+            &[
+                &req.username,
+                &password_hash,
+                &permission_str,
+                &invite_code_id,
+            ],
+        )
+        .await
+    {
+        Ok(row) => row,
+        Err(e) if is_unique_violation(&e) => {
+            let errors = vec![username_taken_error()];
+            return Err(ValidationErrors { errors }.into());
+        }
+        Err(e) => return Err(e.into()),
+    };
+    let id: i32 = row.get("id");
+    record_audit_event(
+        &txn,
+        id,
+        AuditAction::RedeemInviteCode,
+        Some(id),
+        serde_json::json!({
+            "invite_code_id": invite_code_id,
+            "username": req.username,
+            "permission": permission_str,
+        }),
+    )
+    .await?;
+    txn.commit().await?;
+    info!(
+        "Registered account: id={}, username={}, invite_code_id={}",
+        id, req.username, invite_code_id
+    );
+    Ok(AccountTokenResponse { id, token })
+}
+
+#[post("/register")]
+async fn register(
+    req: web::Json<RegisterRequest>,
+    app_data: web::Data<AppData>,
+) -> actix_web::Result<impl Responder> {
+    match try_register(&req, &app_data).await {
+        Ok(out) => Ok(HttpResponse::Ok().json(out)),
+        Err(e) if e.is::<ValidationErrors>() => {
+            Ok(HttpResponse::BadRequest().json(e.downcast_ref::<ValidationErrors>()))
+        }
+        Err(e) if e.is::<InvalidInviteCode>() => Err(actix_web::error::ErrorForbidden(
+            "invalid, expired or used-up invite code",
+        )),
+        Err(e) => {
+            error!("Failed to register: {}", e);
+            Err(actix_web::error::ErrorInternalServerError(
+                "Failed to register",
+            ))
+        }
+    }
+}
+
+#[derive(Deserialize)]
+struct CreateUploadSessionRequest {
+    num_parts: i32,
+}
+
+#[derive(Serialize)]
+struct UploadPartInfo {
+    part_num: i32,
+    size: i64,
+    sha256: String,
+    uploaded_ts: i64,
+    frame_count: i32,
+    frame_width: i32,
+    frame_height: i32,
+    frame_rate: f64,
+}
+
+#[derive(Serialize)]
+struct UploadSessionResponse {
+    video_id: i32,
+    num_parts: i32,
+    finalized: bool,
+    parts: Vec<UploadPartInfo>,
+}
+
+struct UploadSession {
+    video_id: i32,
+    account_id: i32,
+    num_parts: i32,
+    finalized: bool,
+}
+
+async fn try_create_upload_session(
+    req: &CreateUploadSessionRequest,
+    app_data: &AppData,
+    account_info: &AccountInfo,
+) -> Result<UploadSessionResponse> {
+    if req.num_parts < 1 {
+        bail!("Invalid num_parts: {}", req.num_parts);
+    }
+
+    // The video ID is allocated up front so that parts can be stored under their final object path,
+    // but the `video` row itself is only inserted once the session is finalized.
+    let db_client = app_data.db.get().await?;
+    let sql = r#"
+        INSERT INTO upload_session (video_id, account_id, num_parts)
+        VALUES (nextval('video_id_seq'), $1, $2)
+        RETURNING video_id
+    "#;
+    let stmt = db_client.prepare_cached(sql).await?;
+    let row = db_client
+        .query_one(&stmt, &[&account_info.id, &req.num_parts])
+        .await?;
+    let video_id: i32 = row.get("video_id");
+    info!(
+        "Created upload session: video_id={}, num_parts={}, user_id={}",
+        video_id, req.num_parts, account_info.id
+    );
+
+    Ok(UploadSessionResponse {
+        video_id,
+        num_parts: req.num_parts,
+        finalized: false,
+        parts: vec![],
+    })
+}
+
+#[post("/upload-session")]
+async fn create_upload_session(
+    req: web::Json<CreateUploadSessionRequest>,
+    app_data: web::Data<AppData>,
+    http_req: HttpRequest,
+) -> actix_web::Result<impl Responder> {
+    let account_info = match authenticate(app_data.clone(), &http_req, TokenScope::Upload).await {
+        Ok(ai) => ai,
+        Err(e) => {
+            error!("Failed authentication: {}", e);
+            return Err(actix_web::error::ErrorUnauthorized("Unauthorized"));
+        }
+    };
+
+    let out = try_create_upload_session(&req, &app_data, &account_info)
+        .await
+        .map_err(|e| actix_web::error::InternalError::new(e, StatusCode::INTERNAL_SERVER_ERROR))?;
+    Ok(web::Json(out))
+}
+
+// Look up an upload session, checking that it belongs to the given account.
+async fn get_upload_session(
+    video_id: i32,
+    app_data: &AppData,
+    account_info: &AccountInfo,
+) -> actix_web::Result<UploadSession> {
+    let db_client = app_data
+        .db
+        .get()
+        .await
+        .map_err(actix_web::error::ErrorInternalServerError)?;
+    let sql = r#"
+        SELECT
+            video_id,
+            account_id,
+            num_parts,
+            finalized_ts IS NOT NULL AS finalized
+        FROM upload_session
+        WHERE video_id = $1
+    "#;
+    let stmt = db_client
+        .prepare_cached(sql)
+        .await
+        .map_err(actix_web::error::ErrorInternalServerError)?;
+    let row = db_client
+        .query_opt(&stmt, &[&video_id])
+        .await
+        .map_err(actix_web::error::ErrorInternalServerError)?
+        .ok_or_else(|| actix_web::error::ErrorNotFound("upload session not found"))?;
+    let session = UploadSession {
+        video_id: row.get("video_id"),
+        account_id: row.get("account_id"),
+        num_parts: row.get("num_parts"),
+        finalized: row.get("finalized"),
+    };
+    if session.account_id != account_info.id {
+        return Err(actix_web::error::ErrorForbidden(
+            "not permitted to access upload session by other owner",
+        ));
+    }
+    Ok(session)
+}
+
+async fn try_list_upload_parts(video_id: i32, app_data: &AppData) -> Result<Vec<UploadPartInfo>> {
+    let db_client = app_data.db.get().await?;
+    let sql = r#"
+        SELECT part_num, size, sha256, uploaded_ts, frame_count, frame_width, frame_height, frame_rate
+        FROM upload_part
+        WHERE video_id = $1
+        ORDER BY part_num
+    "#;
+    let stmt = db_client.prepare_cached(sql).await?;
+    let rows = db_client.query(&stmt, &[&video_id]).await?;
+    let mut out = vec![];
+    for row in rows {
+        let sha256: Vec<u8> = row.get("sha256");
+        let uploaded_ts: chrono::DateTime<chrono::offset::Utc> = row.get("uploaded_ts");
+        out.push(UploadPartInfo {
+            part_num: row.get("part_num"),
+            size: row.get("size"),
+            sha256: hex_string(&sha256),
+            uploaded_ts: uploaded_ts.timestamp_millis(),
+            frame_count: row.get("frame_count"),
+            frame_width: row.get("frame_width"),
+            frame_height: row.get("frame_height"),
+            frame_rate: row.get("frame_rate"),
+        });
+    }
+    Ok(out)
+}
+
+#[get("/upload-session/{video_id}")]
+async fn upload_session_status(
+    video_id: web::Path<i32>,
+    app_data: web::Data<AppData>,
+    http_req: HttpRequest,
+) -> actix_web::Result<impl Responder> {
+    let account_info = match authenticate(app_data.clone(), &http_req, TokenScope::Upload).await {
+        Ok(ai) => ai,
+        Err(e) => {
+            error!("Failed authentication: {}", e);
+            return Err(actix_web::error::ErrorUnauthorized("Unauthorized"));
+        }
+    };
+
+    let session = get_upload_session(*video_id, &app_data, &account_info).await?;
+    let parts = try_list_upload_parts(session.video_id, &app_data)
+        .await
+        .map_err(|e| actix_web::error::InternalError::new(e, StatusCode::INTERNAL_SERVER_ERROR))?;
+    Ok(web::Json(UploadSessionResponse {
+        video_id: session.video_id,
+        num_parts: session.num_parts,
+        finalized: session.finalized,
+        parts,
+    }))
+}
+
+// Returned when the uncompressed size of an uploaded part exceeds `max_upload_part_size`.
+#[derive(Debug)]
+struct UploadPartTooLarge {
+    max_size: u64,
+}
+
+impl std::fmt::Display for UploadPartTooLarge {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        write!(
+            f,
+            "upload part exceeds maximum size of {} bytes",
+            self.max_size
+        )
+    }
+}
+
+impl std::error::Error for UploadPartTooLarge {}
+
+// Returned when an upload session is finalized while one of its parts is being uploaded, or when
+// its parts change while it is being finalized.
+#[derive(Debug)]
+struct UploadSessionConflict(&'static str);
+
+impl std::fmt::Display for UploadSessionConflict {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        write!(f, "{}", self.0)
+    }
+}
+
+impl std::error::Error for UploadSessionConflict {}
+
+struct StreamedPart {
+    size: i64,
+    compressed_size: i64,
+    sha256: Vec<u8>,
+    avi_info: AviInfo,
+}
+
+// Decompress the gzip payload and recompress it as xz, passing the output to the multipart upload
+// as it is produced, so that memory use per upload stays bounded regardless of the part size.
+// The uncompressed data is also validated as an AVI file along the way, indexing its frames.
+async fn stream_part_to_upload(
+    gzip_payload: web::Payload,
+    writer: &mut WriteMultipart,
+    app_data: &AppData,
+) -> Result<StreamedPart> {
+    let payload_reader =
+        tokio_util::io::StreamReader::new(gzip_payload.map(|r| r.map_err(std::io::Error::other)));
+    let mut gz_dec = async_compression::tokio::bufread::GzipDecoder::new(payload_reader);
+    let mut xz_enc = async_compression::tokio::write::XzEncoder::with_quality(
+        Vec::new(),
+        async_compression::Level::Precise(app_data.args.xz_compression_level),
+    );
+
+    // Hash the uncompressed data, so that clients can verify which parts were received intact.
+    let mut hasher = Sha256::new();
+    let mut avi_indexer = AviIndexer::new();
+    let mut size: u64 = 0;
+    let mut compressed_size: u64 = 0;
+    let mut buf = vec![0u8; 65536];
+    loop {
+        let n = gz_dec.read(&mut buf).await?;
+        if n == 0 {
+            break;
+        }
+        size += n as u64;
+        if size > app_data.args.max_upload_part_size {
+            return Err(UploadPartTooLarge {
+                max_size: app_data.args.max_upload_part_size,
+            }
+            .into());
+        }
+        hasher.update(&buf[..n]);
+        avi_indexer.update(&buf[..n])?;
+        xz_enc.write_all(&buf[..n]).await?;
+
+        let output = xz_enc.get_mut();
+        if !output.is_empty() {
+            writer.wait_for_capacity(UPLOAD_MAX_CONCURRENCY).await?;
+            writer.write(output);
+            compressed_size += output.len() as u64;
+            output.clear();
+        }
+    }
+    let avi_info = avi_indexer.finish()?;
+    xz_enc.shutdown().await?;
+    let output = xz_enc.into_inner();
+    writer.write(&output);
+    compressed_size += output.len() as u64;
+
+    Ok(StreamedPart {
+        size: size as i64,
+        compressed_size: compressed_size as i64,
+        sha256: hasher.finalize().to_vec(),
+        avi_info,
+    })
+}
+
+async fn try_upload_part(
+    session: &UploadSession,
+    part_num: i32,
+    gzip_payload: web::Payload,
+    app_data: &AppData,
+) -> Result<UploadPartInfo> {
+    // Re-sending a part overwrites the previous object, so retries are idempotent. The part is first
+    // streamed to a temporary object, which is only copied into place if the session is still
+    // unfinalized afterwards, so that a finalized video's parts never change.
+    let object_path = object_store::path::Path::parse(format!(
+        "{}avi-xz/{}-{}.avi.xz",
+        app_data.args.video_storage_prefix, session.video_id, part_num
+    ))?;
+    let temp_path = object_store::path::Path::parse(format!(
+        "{}avi-xz/{}-{}.{}.tmp",
+        app_data.args.video_storage_prefix,
+        session.video_id,
+        part_num,
+        &generate_token()[..16]
+    ))?;
+    info!(
+        "Streaming compressed video id={} part_num={} from user_id={} to {}/{}",
+        session.video_id,
+        part_num,
+        session.account_id,
+        app_data.args.video_storage_bucket_url,
+        temp_path
+    );
+
+    let res = try_store_part(
+        session,
+        part_num,
+        gzip_payload,
+        &temp_path,
+        &object_path,
+        app_data,
+    )
+    .await;
+    if let Err(e) = app_data.video_store.delete(&temp_path).await {
+        error!("Failed to delete {}: {}", temp_path, e);
+    }
+    res
+}
+
+async fn try_store_part(
+    session: &UploadSession,
+    part_num: i32,
+    gzip_payload: web::Payload,
+    temp_path: &object_store::path::Path,
+    object_path: &object_store::path::Path,
+    app_data: &AppData,
+) -> Result<UploadPartInfo> {
+    let upload = app_data.video_store.put_multipart(temp_path).await?;
+    let mut writer = WriteMultipart::new_with_chunk_size(upload, UPLOAD_CHUNK_SIZE);
+    let part = match stream_part_to_upload(gzip_payload, &mut writer, app_data).await {
+        Ok(part) => part,
+        Err(e) => {
+            if let Err(abort_err) = writer.abort().await {
+                error!("Failed to abort upload of {}: {}", temp_path, abort_err);
+            }
+            return Err(e);
+        }
+    };
+    writer.finish().await?;
+    info!(
+        "Done storing video {}/{} ({} bytes, {} frames)",
+        app_data.args.video_storage_bucket_url,
+        temp_path,
+        part.compressed_size,
+        part.avi_info.frame_count
+    );
+
+    // Holding a share lock on the session until the part is recorded makes a concurrent finalize
+    // (which locks it for update) wait until then, and then see the part as it was recorded.
+    let mut db_client = app_data.db.get().await?;
+    let txn = db_client.transaction().await?;
+    let sql = r#"
+        SELECT 1
+        FROM upload_session
+        WHERE video_id = $1 AND finalized_ts IS NULL
+        FOR SHARE
+    "#;
+    let stmt = txn.prepare_cached(sql).await?;
+    if txn.query_opt(&stmt, &[&session.video_id]).await?.is_none() {
+        return Err(UploadSessionConflict("upload session is already finalized").into());
+    }
+    app_data.video_store.copy(temp_path, object_path).await?;
+
+    let sql = r#"
+        INSERT INTO upload_part (
+            video_id, part_num, size, compressed_size, sha256,
+            frame_count, frame_width, frame_height, frame_rate
+        )
+        VALUES ($1, $2, $3, $4, $5, $6, $7, $8, $9)
+        ON CONFLICT (video_id, part_num) DO UPDATE SET
+            size = $3,
+            compressed_size = $4,
+            sha256 = $5,
+            frame_count = $6,
+            frame_width = $7,
+            frame_height = $8,
+            frame_rate = $9,
+            uploaded_ts = current_timestamp
+        RETURNING uploaded_ts
+    "#;
+    let stmt = txn.prepare_cached(sql).await?;
+    let row = txn
+        .query_one(
+            &stmt,
+            &[
+                &session.video_id,
+                &part_num,
+                &part.size,
+                &part.compressed_size,
+                &part.sha256,
+                &part.avi_info.frame_count,
+                &part.avi_info.width,
+                &part.avi_info.height,
+                &part.avi_info.frame_rate,
+            ],
+        )
+        .await?;
+    let uploaded_ts: chrono::DateTime<chrono::offset::Utc> = row.get("uploaded_ts");
+    txn.commit().await?;
+    info!(
+        "Recorded upload part (id={}, part_num={})",
+        session.video_id, part_num
+    );
+
+    Ok(UploadPartInfo {
+        part_num,
+        size: part.size,
+        sha256: hex_string(&part.sha256),
+        uploaded_ts: uploaded_ts.timestamp_millis(),
+        frame_count: part.avi_info.frame_count,
+        frame_width: part.avi_info.width,
+        frame_height: part.avi_info.height,
+        frame_rate: part.avi_info.frame_rate,
+    })
+}
+
+#[put("/upload-session/{video_id}/part/{part_num}")]
+async fn upload_part(
+    req: HttpRequest,
+    path: web::Path<(i32, i32)>,
+    payload: web::Payload,
+    app_data: web::Data<AppData>,
+) -> actix_web::Result<impl Responder> {
+    let account_info = match authenticate(app_data.clone(), &req, TokenScope::Upload).await {
+        Ok(ai) => ai,
+        Err(e) => {
+            error!("Failed authentication: {}", e);
+            return Err(actix_web::error::ErrorUnauthorized("Unauthorized"));
+        }
+    };
+
+    let (video_id, part_num) = path.into_inner();
+    let session = get_upload_session(video_id, &app_data, &account_info).await?;
+    if session.finalized {
+        return Err(actix_web::error::ErrorConflict(
+            "upload session is already finalized",
+        ));
+    }
+    if part_num < 0 || part_num >= session.num_parts {
+        return Err(actix_web::error::ErrorBadRequest(format!(
+            "part_num {} out of range (num_parts={})",
+            part_num, session.num_parts
+        )));
+    }
+
+    // Reject oversized parts up front when the client declares the length of the (compressed) body;
+    // otherwise the limit is enforced on the uncompressed data as it streams in.
+    if let Some(content_length) = req.headers().get(header::CONTENT_LENGTH) {
+        let content_length = content_length
+            .to_str()
+            .ok()
+            .and_then(|s| s.parse::<u64>().ok())
+            .ok_or_else(|| actix_web::error::ErrorBadRequest("invalid Content-Length"))?;
+        if content_length > app_data.args.max_upload_part_size {
+            return Err(actix_web::error::ErrorPayloadTooLarge(format!(
+                "upload part exceeds maximum size of {} bytes",
+                app_data.args.max_upload_part_size
+            )));
+        }
+    }
+
+    let part_info = match try_upload_part(&session, part_num, payload, &app_data).await {
+        Ok(p) => p,
+        Err(e) if e.is::<UploadPartTooLarge>() => {
+            error!("Rejected video part: {}", e);
+            return Err(actix_web::error::ErrorPayloadTooLarge(e.to_string()));
+        }
+        Err(e) if e.is::<AviError>() => {
+            error!("Rejected video part: {}", e);
+            return Err(actix_web::error::ErrorBadRequest(e.to_string()));
+        }
+        Err(e) if e.is::<UploadSessionConflict>() => {
+            error!("Rejected video part: {}", e);
+            return Err(actix_web::error::ErrorConflict(e.to_string()));
+        }
+        Err(e) => {
+            error!("Failed to upload video part: {}", e);
+            return Err(actix_web::error::ErrorInternalServerError(
+                "Failed to upload video part",
+            ));
+        }
+    };
+    req.extensions_mut()
+        .insert(UploadedBytes(part_info.size as u64));
+    Ok(web::Json(part_info))
+}
+
+async fn try_finalize_upload_session(
+    session: &UploadSession,
+    parts: &[UploadPartInfo],
+    app_data: &AppData,
+) -> Result<()> {
+    let part_frame_counts: Vec<i32> = parts.iter().map(|p| p.frame_count).collect();
+    let frame_count: i32 = part_frame_counts.iter().sum();
+    let mut db_client = app_data.db.get().await?;
+    let txn = db_client.transaction().await?;
+
+    // Lock the session, so that concurrent finalize requests don't both insert the video:
+    let sql = r#"
+        SELECT finalized_ts IS NOT NULL AS finalized
+        FROM upload_session
+        WHERE video_id = $1
+        FOR UPDATE
+    "#;
+    let stmt = txn.prepare_cached(sql).await?;
+    let row = txn.query_one(&stmt, &[&session.video_id]).await?;
+    if row.get::<_, bool>("finalized") {
+        return Ok(());
+    }
+
+    // The parts were checked before the session was locked, so fail if any has been re-sent since:
+    let sql = r#"
+        SELECT part_num, sha256
+        FROM upload_part
+        WHERE video_id = $1
+        ORDER BY part_num
+    "#;
+    let stmt = txn.prepare_cached(sql).await?;
+    let rows = txn.query(&stmt, &[&session.video_id]).await?;
+    let unchanged = rows.len() == parts.len()
+        && rows.iter().zip(parts).all(|(row, part)| {
+            row.get::<_, i32>("part_num") == part.part_num
+                && hex_string(&row.get::<_, Vec<u8>>("sha256")) == part.sha256
+        });
+    if !unchanged {
+        return Err(UploadSessionConflict("upload parts changed while finalizing").into());
+    }
+
+    let sql = r#"
+        INSERT INTO video (
+            id, num_parts, next_part_num, status, created_account_id, updated_account_id,
+            part_frame_counts, frame_count, frame_width, frame_height, frame_rate
+        )
+        VALUES ($1, $2, $2, 'Pending', $3, $3, $4, $5, $6, $7, $8)
+    "#;
+    let stmt = txn.prepare_cached(sql).await?;
+    txn.execute(
+        &stmt,
+        &[
+            &session.video_id,
+            &session.num_parts,
+            &session.account_id,
+            &part_frame_counts,
+            &frame_count,
+            &parts[0].frame_width,
+            &parts[0].frame_height,
+            &parts[0].frame_rate,
+        ],
+    )
+    .await?;
+
+    let sql = "UPDATE upload_session SET finalized_ts = current_timestamp WHERE video_id = $1";
+    let stmt = txn.prepare_cached(sql).await?;
+    txn.execute(&stmt, &[&session.video_id]).await?;
+
+    txn.commit().await?;
+    info!("Inserted video into database (id={})", session.video_id);
+    Ok(())
+}
+
+#[post("/upload-session/{video_id}/finalize")]
+async fn finalize_upload_session(
+    video_id: web::Path<i32>,
+    app_data: web::Data<AppData>,
+    http_req: HttpRequest,
+) -> actix_web::Result<impl Responder> {
+    let account_info = match authenticate(app_data.clone(), &http_req, TokenScope::Upload).await {
+        Ok(ai) => ai,
+        Err(e) => {
+            error!("Failed authentication: {}", e);
+            return Err(actix_web::error::ErrorUnauthorized("Unauthorized"));
+        }
+    };
+
+    let session = get_upload_session(*video_id, &app_data, &account_info).await?;
+    if session.finalized {
+        // Finalizing is idempotent, so that a client can safely retry after losing the response.
+        return Ok(HttpResponse::Ok().body(session.video_id.to_string()));
+    }
+    let parts = try_list_upload_parts(session.video_id, &app_data)
+        .await
+        .map_err(|e| actix_web::error::InternalError::new(e, StatusCode::INTERNAL_SERVER_ERROR))?;
+    let missing_parts: Vec<String> = (0..session.num_parts)
+        .filter(|i| !parts.iter().any(|p| p.part_num == *i))
+        .map(|i| i.to_string())
+        .collect();
+    if !missing_parts.is_empty() {
+        return Err(actix_web::error::ErrorConflict(format!(
+            "upload incomplete, missing parts: {}",
+            missing_parts.join(",")
+        )));
+    }
+    if parts.iter().any(|p| {
+        p.frame_width != parts[0].frame_width
+            || p.frame_height != parts[0].frame_height
+            || p.frame_rate != parts[0].frame_rate
+    }) {
+        return Err(actix_web::error::ErrorBadRequest(
+            "inconsistent resolution or frame rate between parts",
+        ));
+    }
+    if let Err(e) = try_finalize_upload_session(&session, &parts, &app_data).await {
+        if e.is::<UploadSessionConflict>() {
+            return Err(actix_web::error::ErrorConflict(e.to_string()));
+        }
+        error!("Failed to finalize upload session: {}", e);
+        return Err(actix_web::error::ErrorInternalServerError(
+            "Failed to finalize upload session",
+        ));
+    }
+    Ok(HttpResponse::Ok().body(session.video_id.to_string()))
+}
+
+#[derive(Serialize, Debug)]
+struct FieldError {
+    field: &'static str,
+    message: String,
+}
+
+// Returned (as a 400 response) when fields of a submit/edit request are invalid for the video.
+#[derive(Serialize, Debug)]
+struct ValidationErrors {
+    errors: Vec<FieldError>,
+}
+
+impl std::fmt::Display for ValidationErrors {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        let msgs: Vec<String> = self
+            .errors
+            .iter()
+            .map(|e| format!("{}: {}", e.field, e.message))
+            .collect();
+        write!(f, "validation failed: {}", msgs.join("; "))
+    }
+}
+
+impl std::error::Error for ValidationErrors {}
+
+struct VideoControls {
+    crop_size: i32,
+    crop_center_x: i32,
+    crop_center_y: i32,
+    thumbnail_t: i32,
+    highlight_start_t: i32,
+    highlight_end_t: i32,
+}
+
+// Frame metadata recorded at upload time. These are missing for videos uploaded before
+// server-side AVI indexing was added, in which case only the self-consistency checks apply.
+struct VideoFrameInfo {
+    num_parts: i32,
+    frame_count: Option<i32>,
+    frame_width: Option<i32>,
+    frame_height: Option<i32>,
+}
+
+async fn get_video_frame_info(
+    db_client: &deadpool_postgres::Client,
+    video_id: i32,
+) -> Result<VideoFrameInfo> {
+    let sql = "SELECT num_parts, frame_count, frame_width, frame_height FROM video WHERE id=$1";
+    let stmt = db_client.prepare_cached(sql).await?;
+    let row = db_client.query_one(&stmt, &[&video_id]).await?;
+    Ok(VideoFrameInfo {
+        num_parts: row.get("num_parts"),
+        frame_count: row.get("frame_count"),
+        frame_width: row.get("frame_width"),
+        frame_height: row.get("frame_height"),
+    })
+}
+
+const MAX_ANNOTATIONS: usize = 50;
+const MAX_ANNOTATION_NAME_LENGTH: usize = 100;
+
+fn validate_annotations(
+    annotations: &[VideoAnnotation],
+    frame_info: &VideoFrameInfo,
+) -> Vec<FieldError> {
+    let mut errors = vec![];
+    let mut error = |message: String| {
+        errors.push(FieldError {
+            field: "annotations",
+            message,
+        });
+    };
+    if annotations.len() > MAX_ANNOTATIONS {
+        error(format!("at most {} are allowed", MAX_ANNOTATIONS));
+    }
+    for a in annotations {
+        let name = a.name.trim();
+        if name.is_empty() || name.chars().count() > MAX_ANNOTATION_NAME_LENGTH {
+            error(format!(
+                "name must be 1 to {} characters",
+                MAX_ANNOTATION_NAME_LENGTH
+            ));
+        } else if name.contains(['\r', '\n']) {
+            error(format!("name \"{}\" must be a single line", name));
+        }
+        if a.start_t < 0 || a.start_t >= a.end_t {
+            error(format!(
+                "\"{}\" must have start_t before end_t (and not negative)",
+                name
+            ));
+        } else if frame_info.frame_count.is_some_and(|n| a.end_t > n) {
+            error(format!("\"{}\" extends past the end of the video", name));
+        }
+    }
+    errors
+}
+
+fn validate_video_controls(
+    controls: &VideoControls,
+    frame_info: &VideoFrameInfo,
+    max_highlight_frames: i32,
+) -> Vec<FieldError> {
+    let mut errors = vec![];
+    let mut error = |field: &'static str, message: String| {
+        errors.push(FieldError { field, message });
+    };
+
+    // Crop box (matching the computation of the crop offset in the encoder):
+    if controls.crop_size <= 0 {
+        error("crop_size", "must be positive".to_string());
+    } else {
+        let crop_x = controls.crop_center_x - controls.crop_size / 2;
+        let crop_y = controls.crop_center_y - controls.crop_size / 2;
+        if crop_x < 0 {
+            error(
+                "crop_center_x",
+                format!("crop box extends past left edge (x={})", crop_x),
+            );
+        }
+        if crop_y < 0 {
+            error(
+                "crop_center_y",
+                format!("crop box extends past top edge (y={})", crop_y),
+            );
+        }
+        if let (Some(width), Some(height)) = (frame_info.frame_width, frame_info.frame_height) {
+            if controls.crop_size > width.min(height) {
+                error(
+                    "crop_size",
+                    format!("exceeds frame dimensions {} x {}", width, height),
+                );
+            } else {
+                if crop_x >= 0 && crop_x + controls.crop_size > width {
+                    error(
+                        "crop_center_x",
+                        format!("crop box extends past right edge (width={})", width),
+                    );
+                }
+                if crop_y >= 0 && crop_y + controls.crop_size > height {
+                    error(
+                        "crop_center_y",
+                        format!("crop box extends past bottom edge (height={})", height),
+                    );
+                }
+            }
+        }
+    }
+
+    // Frame ranges:
+    let frame_count = frame_info.frame_count.unwrap_or(i32::MAX);
+    if controls.thumbnail_t < 0 || controls.thumbnail_t >= frame_count {
+        error(
+            "thumbnail_t",
+            format!("must be within [0, {})", frame_count),
+        );
+    }
+    if controls.highlight_start_t < 0 || controls.highlight_start_t >= frame_count {
+        error(
+            "highlight_start_t",
+            format!("must be within [0, {})", frame_count),
+        );
+    }
+    if controls.highlight_end_t < 0 || controls.highlight_end_t >= frame_count {
+        error(
+            "highlight_end_t",
+            format!("must be within [0, {})", frame_count),
+        );
+    }
+    if controls.highlight_start_t >= controls.highlight_end_t {
+        error(
+            "highlight_end_t",
+            "must be after highlight_start_t".to_string(),
+        );
+    } else if controls.highlight_end_t - controls.highlight_start_t > max_highlight_frames {
+        error(
+            "highlight_end_t",
+            format!(
+                "highlight may be at most {} frames long",
+                max_highlight_frames
+            ),
+        );
+    }
+    errors
+}
+
+// A strat demonstrated by a video, in addition to its primary strat (`video.room_id`/`strat_id`).
+#[derive(Serialize, Deserialize, Debug, Clone, PartialEq, Eq)]
+struct StratLink {
+    room_id: i32,
+    strat_id: i32,
+}
+
+const MAX_ADDITIONAL_STRATS: usize = 20;
+
+async fn validate_strat_links(
+    db_client: &deadpool_postgres::Client,
+    links: &[StratLink],
+) -> Result<Vec<FieldError>> {
+    let mut errors = vec![];
+    if links.len() > MAX_ADDITIONAL_STRATS {
+        errors.push(FieldError {
+            field: "additional_strats",
+            message: format!("at most {} are allowed", MAX_ADDITIONAL_STRATS),
+        });
+    }
+    let sql = "SELECT 1 FROM strat WHERE room_id = $1 AND strat_id = $2";
+    let stmt = db_client.prepare_cached(sql).await?;
+    for link in links {
+        let found = db_client
+            .query_opt(&stmt, &[&link.room_id, &link.strat_id])
+            .await?
+            .is_some();
+        if !found {
+            errors.push(FieldError {
+                field: "additional_strats",
+                message: format!(
+                    "strat {} in room {} does not exist",
+                    link.strat_id, link.room_id
+                ),
+            });
+        }
+    }
+    Ok(errors)
+}
+
+// Update the `video_strat` links of a video: the primary link follows the video's own
+// `room_id`/`strat_id`, while the additional links are replaced only if given.
+async fn update_video_strats(
+    db: &impl deadpool_postgres::GenericClient,
+    video_id: i32,
+    additional_strats: Option<&[StratLink]>,
+) -> Result<()> {
+    let sql = if additional_strats.is_some() {
+        "DELETE FROM video_strat WHERE video_id = $1"
+    } else {
+        "DELETE FROM video_strat WHERE video_id = $1 AND is_primary"
+    };
+    let stmt = db.prepare_cached(sql).await?;
+    db.execute(&stmt, &[&video_id]).await?;
+
+    let sql = r#"
+        INSERT INTO video_strat (video_id, room_id, strat_id, is_primary)
+        VALUES ($1, $2, $3, false)
+        ON CONFLICT DO NOTHING
+    "#;
+    let stmt = db.prepare_cached(sql).await?;
+    for link in additional_strats.unwrap_or_default() {
+        db.execute(&stmt, &[&video_id, &link.room_id, &link.strat_id])
+            .await?;
+    }
+
+    let sql = r#"
+        INSERT INTO video_strat (video_id, room_id, strat_id, is_primary)
+        SELECT id, room_id, strat_id, true
+        FROM video
+        WHERE id = $1 AND room_id IS NOT NULL AND strat_id IS NOT NULL
+        ON CONFLICT (video_id, room_id, strat_id) DO UPDATE SET is_primary = true
+    "#;
+    let stmt = db.prepare_cached(sql).await?;
+    db.execute(&stmt, &[&video_id]).await?;
+    Ok(())
+}
+
+async fn get_additional_strats(
+    db: &impl deadpool_postgres::GenericClient,
+    video_id: i32,
+) -> Result<Vec<StratLink>> {
+    let sql = r#"
+        SELECT room_id, strat_id
+        FROM video_strat
+        WHERE video_id = $1 AND NOT is_primary
+        ORDER BY room_id, strat_id
+    "#;
+    let stmt = db.prepare_cached(sql).await?;
+    let rows = db.query(&stmt, &[&video_id]).await?;
+    Ok(rows
+        .iter()
+        .map(|row| StratLink {
+            room_id: row.get("room_id"),
+            strat_id: row.get("strat_id"),
+        })
+        .collect())
+}
+
+const MAX_VIDEO_TAGS: usize = 20;
+
+async fn validate_video_tags(
+    db_client: &deadpool_postgres::Client,
+    tag_ids: &[i32],
+) -> Result<Vec<FieldError>> {
+    let mut errors = vec![];
+    if tag_ids.len() > MAX_VIDEO_TAGS {
+        errors.push(FieldError {
+            field: "tags",
+            message: format!("at most {} are allowed", MAX_VIDEO_TAGS),
+        });
+    }
+    let sql = "SELECT tag_id FROM tag WHERE tag_id = ANY($1)";
+    let stmt = db_client.prepare_cached(sql).await?;
+    let found: Vec<i32> = db_client
+        .query(&stmt, &[&tag_ids])
+        .await?
+        .iter()
+        .map(|row| row.get("tag_id"))
+        .collect();
+    for tag_id in tag_ids {
+        if !found.contains(tag_id) {
+            errors.push(FieldError {
+                field: "tags",
+                message: format!("tag {} does not exist", tag_id),
+            });
+        }
+    }
+    Ok(errors)
+}
+
+// Replace the tags of a video. Tags which no longer exist (e.g. when reverting to an old
+// revision) are skipped.
+async fn update_video_tags(
+    db: &impl deadpool_postgres::GenericClient,
+    video_id: i32,
+    tag_ids: &[i32],
+) -> Result<()> {
+    let sql = "DELETE FROM video_tag WHERE video_id = $1";
+    let stmt = db.prepare_cached(sql).await?;
+    db.execute(&stmt, &[&video_id]).await?;
+
+    let sql = r#"
+        INSERT INTO video_tag (video_id, tag_id)
+        SELECT $1, tag_id FROM tag WHERE tag_id = ANY($2)
+        ON CONFLICT DO NOTHING
+    "#;
+    let stmt = db.prepare_cached(sql).await?;
+    db.execute(&stmt, &[&video_id, &tag_ids]).await?;
+    Ok(())
+}
+
+async fn get_video_tags(
+    db: &impl deadpool_postgres::GenericClient,
+    video_id: i32,
+) -> Result<Vec<i32>> {
+    let sql = "SELECT tag_id FROM video_tag WHERE video_id = $1 ORDER BY tag_id";
+    let stmt = db.prepare_cached(sql).await?;
+    let rows = db.query(&stmt, &[&video_id]).await?;
+    Ok(rows.iter().map(|row| row.get("tag_id")).collect())
+}
+
+struct StratReferences {
+    room_id: Option<i32>,
+    from_node_id: Option<i32>,
+    to_node_id: Option<i32>,
+    strat_id: Option<i32>,
+}
+
+// Check that the room, nodes and strat exist in the sm-json-data tables and are consistent with
+// each other. Missing from/to nodes are filled in from the strat's link, as `update_node_ids` in
+// sm-json-data-updater does for existing videos.
+async fn resolve_strat_references(
+    db_client: &deadpool_postgres::Client,
+    refs: &mut StratReferences,
+) -> Result<Vec<FieldError>> {
+    let mut errors = vec![];
+    let room_id = match refs.room_id {
+        Some(room_id) => room_id,
+        None => {
+            for (field, value) in [
+                ("from_node_id", refs.from_node_id),
+                ("to_node_id", refs.to_node_id),
+                ("strat_id", refs.strat_id),
+            ] {
+                if value.is_some() {
+                    errors.push(FieldError {
+                        field,
+                        message: "requires room_id".to_string(),
+                    });
+                }
+            }
+            return Ok(errors);
+        }
+    };
+
+    let sql = "SELECT 1 FROM room WHERE room_id = $1";
+    let stmt = db_client.prepare_cached(sql).await?;
+    if db_client.query_opt(&stmt, &[&room_id]).await?.is_none() {
+        errors.push(FieldError {
+            field: "room_id",
+            message: format!("unknown room {}", room_id),
+        });
+        return Ok(errors);
+    }
+
+    if let Some(strat_id) = refs.strat_id {
+        let sql = "SELECT from_node_id, to_node_id FROM strat WHERE room_id = $1 AND strat_id = $2";
+        let stmt = db_client.prepare_cached(sql).await?;
+        match db_client.query_opt(&stmt, &[&room_id, &strat_id]).await? {
+            None => errors.push(FieldError {
+                field: "strat_id",
+                message: format!("unknown strat {} in room {}", strat_id, room_id),
+            }),
+            Some(row) => {
+                let strat_from_node_id: Option<i32> = row.get("from_node_id");
+                let strat_to_node_id: Option<i32> = row.get("to_node_id");
+                for (field, value, strat_value) in [
+                    ("from_node_id", &mut refs.from_node_id, strat_from_node_id),
+                    ("to_node_id", &mut refs.to_node_id, strat_to_node_id),
+                ] {
+                    // Strats without a recorded node can't be checked against (or fill in) it:
+                    let Some(strat_value) = strat_value else {
+                        continue;
+                    };
+                    match *value {
+                        None => *value = Some(strat_value),
+                        Some(v) if v != strat_value => errors.push(FieldError {
+                            field,
+                            message: format!(
+                                "node {} does not match strat {} (expected node {})",
+                                v, strat_id, strat_value
+                            ),
+                        }),
+                        Some(_) => {}
+                    }
+                }
+            }
+        }
+    }
+
+    let sql = "SELECT 1 FROM node WHERE room_id = $1 AND node_id = $2";
+    let stmt = db_client.prepare_cached(sql).await?;
+    for (field, value) in [
+        ("from_node_id", refs.from_node_id),
+        ("to_node_id", refs.to_node_id),
+    ] {
+        if let Some(node_id) = value {
+            if db_client
+                .query_opt(&stmt, &[&room_id, &node_id])
+                .await?
+                .is_none()
+            {
+                errors.push(FieldError {
+                    field,
+                    message: format!("unknown node {} in room {}", node_id, room_id),
+                });
+            }
+        }
+    }
+    Ok(errors)
+}
+
+// Encoding tasks for the outputs that depend on the crop/frame controls (thumbnail and highlight).
+fn controls_encoding_tasks(
+    video_id: i32,
+    num_parts: i32,
+    controls: &VideoControls,
+) -> Vec<EncodingTask> {
+    vec![
+        EncodingTask::ThumbnailImage {
+            video_id,
+            num_parts,
+            crop_center_x: controls.crop_center_x,
+            crop_center_y: controls.crop_center_y,
+            crop_size: controls.crop_size,
+            frame_number: controls.thumbnail_t,
+        },
+        EncodingTask::HighlightAnimation {
+            video_id,
+            num_parts,
+            crop_center_x: controls.crop_center_x,
+            crop_center_y: controls.crop_center_y,
+            crop_size: controls.crop_size,
+            start_frame_number: controls.highlight_start_t,
+            end_frame_number: controls.highlight_end_t,
+        },
+    ]
+}
+
+// Send messages to RabbitMQ to trigger processes to perform the given encoding tasks.
+async fn publish_encoding_tasks(app_data: &AppData, tasks: Vec<EncodingTask>) -> Result<()> {
+    let db_client = app_data.db.get().await?;
+    let mq = app_data.mq.get().await?;
+    let channel = mq.create_channel().await?;
+    let props = lapin::BasicProperties::default().with_delivery_mode(2); // persistent delivery
+    for task in tasks {
+        let msg = create_encoding_job(&db_client, task).await?;
+        let res = channel
+            .basic_publish(
+                "",
+                &app_data.args.rabbit_queue,
+                lapin::options::BasicPublishOptions::default(),
+                &serde_json::to_vec(&msg)?,
+                props.clone(),
+            )
+            .await;
+        if let Err(e) = res {
+            // Nothing will pick up the job, so it mustn't be left Queued:
+            if let Some(job_id) = msg.job_id {
+                let error = format!("publishing task: {}", e);
+                finish_encoding_job(
+                    &db_client,
+                    job_id,
+                    EncodingJobStatus::Failed,
+                    Some(&error),
+                    None,
+                )
+                .await?;
+            }
+            return Err(e.into());
+        }
+    }
+    Ok(())
+}
+
+#[derive(Serialize, Deserialize, strum::EnumString, Debug)]
+enum RevisionAction {
+    // Snapshot of a video's state taken before its first edit, for videos submitted before
+    // revision history was recorded.
+    Initial,
+    Submit,
+    Edit,
+    Delete,
+    Restore,
+    Revert,
+    Review,
+}
+
+// The user-editable state of a video, as recorded in each `video_revision`.
+#[derive(Serialize, Deserialize)]
+struct VideoSnapshot {
+    status: String,
+    room_id: Option<i32>,
+    from_node_id: Option<i32>,
+    to_node_id: Option<i32>,
+    strat_id: Option<i32>,
+    note: String,
+    dev_note: String,
+    crop_size: Option<i32>,
+    crop_center_x: Option<i32>,
+    crop_center_y: Option<i32>,
+    thumbnail_t: Option<i32>,
+    highlight_start_t: Option<i32>,
+    highlight_end_t: Option<i32>,
+    priority: Option<i32>,
+    // Missing from revisions recorded before annotations were added:
+    #[serde(default)]
+    annotations: Vec<VideoAnnotation>,
+    // Missing (and so left unchanged by a revert) from revisions recorded before videos could be
+    // linked to multiple strats:
+    #[serde(default)]
+    additional_strats: Option<Vec<StratLink>>,
+    // Missing (and so left unchanged by a revert) from revisions recorded before tags were added:
+    #[serde(default)]
+    tags: Option<Vec<i32>>,
+}
+
+impl VideoSnapshot {
+    fn controls(&self) -> Option<VideoControls> {
+        Some(VideoControls {
+            crop_size: self.crop_size?,
+            crop_center_x: self.crop_center_x?,
+            crop_center_y: self.crop_center_y?,
+            thumbnail_t: self.thumbnail_t?,
+            highlight_start_t: self.highlight_start_t?,
+            highlight_end_t: self.highlight_end_t?,
+        })
+    }
+}
+
+async fn get_video_snapshot(
+    db: &impl deadpool_postgres::GenericClient,
+    video_id: i32,
+) -> Result<VideoSnapshot> {
+    let sql = r#"
+        SELECT
+            status,
+            room_id,
+            from_node_id,
+            to_node_id,
+            strat_id,
+            note,
+            dev_note,
+            crop_size,
+            crop_center_x,
+            crop_center_y,
+            thumbnail_t,
+            highlight_start_t,
+            highlight_end_t,
+            priority,
+            annotations
+        FROM video
+        WHERE id = $1
+    "#;
+    let stmt = db.prepare_cached(sql).await?;
+    let row = db.query_one(&stmt, &[&video_id]).await?;
+    Ok(VideoSnapshot {
+        status: row.get("status"),
+        room_id: row.get("room_id"),
+        from_node_id: row.get("from_node_id"),
+        to_node_id: row.get("to_node_id"),
+        strat_id: row.get("strat_id"),
+        note: row.get("note"),
+        dev_note: row.get("dev_note"),
+        crop_size: row.get("crop_size"),
+        crop_center_x: row.get("crop_center_x"),
+        crop_center_y: row.get("crop_center_y"),
+        thumbnail_t: row.get("thumbnail_t"),
+        highlight_start_t: row.get("highlight_start_t"),
+        highlight_end_t: row.get("highlight_end_t"),
+        priority: row.get("priority"),
+        annotations: serde_json::from_value(row.get("annotations"))?,
+        additional_strats: Some(get_additional_strats(db, video_id).await?),
+        tags: Some(get_video_tags(db, video_id).await?),
+    })
+}
+
+// Lock the video row until the end of the transaction, so that concurrent changes to its
+// revision history are serialized.
+async fn lock_video(db: &impl deadpool_postgres::GenericClient, video_id: i32) -> Result<()> {
+    let sql = "SELECT 1 FROM video WHERE id = $1 FOR UPDATE";
+    let stmt = db.prepare_cached(sql).await?;
+    db.query_opt(&stmt, &[&video_id]).await?;
+    Ok(())
+}
+
+// Record the current state of the video as a new revision in its history. This must be called in
+// a transaction.
+async fn record_video_revision(
+    db: &impl deadpool_postgres::GenericClient,
+    video_id: i32,
+    account_id: i32,
+    action: RevisionAction,
+) -> Result<i32> {
+    lock_video(db, video_id).await?;
+    let snapshot = serde_json::to_value(get_video_snapshot(db, video_id).await?)?;
+    let sql = r#"
+        INSERT INTO video_revision (video_id, revision, action, account_id, data)
+        SELECT $1, COALESCE(MAX(revision), 0) + 1, $2, $3, $4
+        FROM video_revision
+        WHERE video_id = $1
+        RETURNING revision
+    "#;
+    let stmt = db.prepare_cached(sql).await?;
+    let action_str = format!("{:?}", action);
+    let row = db
+        .query_one(&stmt, &[&video_id, &action_str, &account_id, &snapshot])
+        .await?;
+    let revision: i32 = row.get("revision");
+    info!(
+        "Recorded video revision: id={}, revision={}, action={}",
+        video_id, revision, action_str
+    );
+    Ok(revision)
+}
+
+// Videos submitted before revision history was recorded get a snapshot of their state before
+// the first change, so that the change itself shows up in the history.
+async fn ensure_initial_revision(
+    db: &impl deadpool_postgres::GenericClient,
+    video_id: i32,
+    account_id: i32,
+) -> Result<()> {
+    lock_video(db, video_id).await?;
+    let sql = "SELECT 1 FROM video_revision WHERE video_id = $1 LIMIT 1";
+    let stmt = db.prepare_cached(sql).await?;
+    if db.query_opt(&stmt, &[&video_id]).await?.is_none() {
+        record_video_revision(db, video_id, account_id, RevisionAction::Initial).await?;
+    }
+    Ok(())
+}
+
+#[derive(Deserialize, Debug)]
+struct SubmitVideoRequest {
+    video_id: i32,
+    room_id: Option<i32>,
+    from_node_id: Option<i32>,
+    to_node_id: Option<i32>,
+    strat_id: Option<i32>,
+    note: String,
+    dev_note: String,
+    crop_size: i32,
+    crop_center_x: i32,
+    crop_center_y: i32,
+    thumbnail_t: i32,
+    highlight_start_t: i32,
+    highlight_end_t: i32,
+    copyright_waiver: bool,
+    #[serde(default)]
+    annotations: Vec<VideoAnnotation>,
+    // Strats demonstrated in addition to the primary one:
+    #[serde(default)]
+    additional_strats: Vec<StratLink>,
+    // IDs of tags for the video:
+    #[serde(default)]
+    tags: Vec<i32>,
+}
+
+async fn try_submit_video(
+    req_json: web::Bytes,
+    app_data: web::Data<AppData>,
+    account_info: &AccountInfo,
+) -> Result<()> {
+    info!("submit_video: {}", std::str::from_utf8(&req_json)?);
+    let mut req: SubmitVideoRequest = serde_json::from_slice(&req_json)?;
+
+    if !req.copyright_waiver {
+        bail!("copyright_waiver not checked");
+    }
+
+    let mut db_client = app_data.db.get().await.unwrap();
+    let frame_info = get_video_frame_info(&db_client, req.video_id).await?;
+    let num_parts = frame_info.num_parts;
+    let controls = VideoControls {
+        crop_size: req.crop_size,
+        crop_center_x: req.crop_center_x,
+        crop_center_y: req.crop_center_y,
+        thumbnail_t: req.thumbnail_t,
+        highlight_start_t: req.highlight_start_t,
+        highlight_end_t: req.highlight_end_t,
+    };
+    let mut errors =
+        validate_video_controls(&controls, &frame_info, app_data.args.max_highlight_frames);
+    errors.extend(validate_annotations(&req.annotations, &frame_info));
+    let mut refs = StratReferences {
+        room_id: req.room_id,
+        from_node_id: req.from_node_id,
+        to_node_id: req.to_node_id,
+        strat_id: req.strat_id,
+    };
+    errors.extend(resolve_strat_references(&db_client, &mut refs).await?);
+    errors.extend(validate_strat_links(&db_client, &req.additional_strats).await?);
+    errors.extend(validate_video_tags(&db_client, &req.tags).await?);
+    if !errors.is_empty() {
+        return Err(ValidationErrors { errors }.into());
+    }
+    req.from_node_id = refs.from_node_id;
+    req.to_node_id = refs.to_node_id;
+
+    let status: &'static str = if req.room_id.is_some()
+        && req.from_node_id.is_some()
+        && req.to_node_id.is_some()
+        && req.strat_id.is_some()
+    {
+        "Complete"
+    } else {
+        "Incomplete"
+    };
+
+    let sql = r#"
+        UPDATE video
+        SET status = $15,
+            version = version + 1,
+            updated_ts=current_timestamp,
+            submitted_ts=current_timestamp,
+            room_id=$2,
+            from_node_id=$3,
+            to_node_id=$4,
+            strat_id=$5,
+            note=$6,
+            dev_note=$7,
+            crop_size=$8,
+            crop_center_x=$9,
+            crop_center_y=$10,
+            thumbnail_t=$11,
+            highlight_start_t=$12,
+            highlight_end_t=$13,
+            annotations=$16
+        WHERE id=$14 AND created_account_id=$1 AND next_part_num = num_parts AND status != 'Deleted'
+    "#;
+    let annotations_json = serde_json::to_value(&req.annotations)?;
+    let txn = db_client.transaction().await?;
+    let stmt = txn.prepare_cached(sql).await?;
+    let cnt = txn
+        .execute(
+            &stmt,
+            &[
+                &account_info.id,
+                &req.room_id,
+                &req.from_node_id,
+                &req.to_node_id,
+                &req.strat_id,
+                &req.note,
+                &req.dev_note,
+                &req.crop_size,
+                &req.crop_center_x,
+                &req.crop_center_y,
+                &req.thumbnail_t,
+                &req.highlight_start_t,
+                &req.highlight_end_t,
+                &req.video_id,
+                &status,
+                &annotations_json,
+            ],
+        )
+        .await?;
+    if cnt == 1 {
+        info!("Submitted video: id={}", req.video_id);
+    } else {
+        bail!(
+            "Unexpected update row count: {} (upload may be incomplete?)",
+            cnt
+        );
+    }
+    update_video_strats(&txn, req.video_id, Some(&req.additional_strats)).await?;
+    update_video_tags(&txn, req.video_id, &req.tags).await?;
+    record_video_revision(&txn, req.video_id, account_info.id, RevisionAction::Submit).await?;
+    txn.commit().await?;
+
+    // Trigger processes to encode the thumbnail image, animated highlight, and full video.
+    let mut tasks = controls_encoding_tasks(req.video_id, num_parts, &controls);
+    tasks.push(EncodingTask::FullVideo {
+        video_id: req.video_id,
+        num_parts,
+    });
+    if !req.annotations.is_empty() {
+        tasks.push(EncodingTask::Chapters {
+            video_id: req.video_id,
+        });
+    }
+    publish_encoding_tasks(&app_data, tasks).await?;
+
+    // Set the user account to active so it will show in the user listing:
+    let sql = "UPDATE account SET active = TRUE WHERE id = $1";
+    let stmt = db_client.prepare_cached(sql).await?;
+    let cnt = db_client.execute(&stmt, &[&account_info.id]).await?;
+    if cnt != 1 {
+        bail!("Error updating account 'active'");
+    }
+
+    Ok(())
+}
+
+#[post("/submit-video")]
+async fn submit_video(
+    req_json: web::Bytes,
+    app_data: web::Data<AppData>,
+    http_req: HttpRequest,
+) -> impl Responder {
+    let account_info = match authenticate(app_data.clone(), &http_req, TokenScope::Upload).await {
+        Ok(ai) => ai,
+        Err(e) => {
+            error!("Failed authentication: {}", e);
+            return HttpResponse::Unauthorized().body("Unauthorized");
+        }
+    };
+
+    match try_submit_video(req_json, app_data.clone(), &account_info).await {
+        Ok(_) => {}
+        Err(e) if e.is::<ValidationErrors>() => {
+            error!("Rejected video submission: {}", e);
+            return HttpResponse::BadRequest().json(e.downcast_ref::<ValidationErrors>());
+        }
+        Err(e) => {
+            error!("Failed to submit video: {}", e);
+            return HttpResponse::InternalServerError().body("Failed to submit video");
+        }
+    }
+
+    HttpResponse::Ok().body("")
+}
+
+#[derive(Deserialize, Debug)]
+struct EditVideoRequest {
+    video_id: i32,
+    room_id: Option<i32>,
+    from_node_id: Option<i32>,
+    to_node_id: Option<i32>,
+    strat_id: Option<i32>,
+    note: String,
+    dev_note: String,
+    crop_size: i32,
+    crop_center_x: i32,
+    crop_center_y: i32,
+    thumbnail_t: i32,
+    highlight_start_t: i32,
+    highlight_end_t: i32,
+    status: VideoStatus,
+    priority: Option<i32>,
+    controls_updated: bool,
+    // Version of the video that the edit is based on, from `GetVideoResponse`.
+    version: i32,
+    // Replaces the video's annotations, if present (otherwise they are left unchanged):
+    annotations: Option<Vec<VideoAnnotation>>,
+    // Replaces the strats demonstrated in addition to the primary one, if present:
+    additional_strats: Option<Vec<StratLink>>,
+    // Replaces the video's tags, if present:
+    tags: Option<Vec<i32>>,
+}
+
+// The edit was based on a stale version of the video, which has since been changed by someone else.
+#[derive(Debug)]
+struct EditConflict {
+    current: GetVideoResponse,
+}
+
+impl std::fmt::Display for EditConflict {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        write!(
+            f,
+            "video was modified concurrently (current version {})",
+            self.current.version
+        )
+    }
+}
+
+impl std::error::Error for EditConflict {}
+
+async fn try_edit_video(
+    req_json: web::Bytes,
+    app_data: web::Data<AppData>,
+    account_info: &AccountInfo,
+) -> Result<()> {
+    info!("edit_video: {}", std::str::from_utf8(&req_json)?);
+    let mut req: EditVideoRequest = serde_json::from_slice(&req_json)?;
+
+    if req.status == VideoStatus::Deleted {
+        bail!("Videos must be deleted using the delete endpoint");
+    }
+
+    let mut db_client = app_data.db.get().await.unwrap();
+    let sql = r#"
+        SELECT
+            status,
+            created_account_id 
+        FROM video WHERE id=$1
+    "#;
+    let stmt = db_client.prepare_cached(sql).await?;
+    let row = db_client.query_one(&stmt, &[&req.video_id]).await?;
+    let status_str: String = row.get("status");
+    let status = VideoStatus::try_from(status_str.as_str())?;
+    let created_account_id: i32 = row.get("created_account_id");
+    if status == VideoStatus::Approved && !account_info.can(Capability::ApproveVideos) {
+        // It would be more "correct" to return 403 here (and 404 in case the row doesn't exist).
+        bail!("Not authorized to edit Approved video");
+    }
+    if created_account_id != account_info.id && !account_info.can(Capability::EditAnyVideo) {
+        bail!("Not authorized to edit video by different creator");
+    }
+
+    let frame_info = get_video_frame_info(&db_client, req.video_id).await?;
+    let num_parts = frame_info.num_parts;
+    let controls = VideoControls {
+        crop_size: req.crop_size,
+        crop_center_x: req.crop_center_x,
+        crop_center_y: req.crop_center_y,
+        thumbnail_t: req.thumbnail_t,
+        highlight_start_t: req.highlight_start_t,
+        highlight_end_t: req.highlight_end_t,
+    };
+    let mut errors =
+        validate_video_controls(&controls, &frame_info, app_data.args.max_highlight_frames);
+    if let Some(annotations) = &req.annotations {
+        errors.extend(validate_annotations(annotations, &frame_info));
+    }
+    if req.status == VideoStatus::Approved && status != VideoStatus::Approved {
+        // Approval goes through review, so that there is a record of who approved the video and why.
+        errors.push(FieldError {
+            field: "status",
+            message: "videos must be approved through review".to_string(),
+        });
+    }
+    let mut refs = StratReferences {
+        room_id: req.room_id,
+        from_node_id: req.from_node_id,
+        to_node_id: req.to_node_id,
+        strat_id: req.strat_id,
+    };
+    errors.extend(resolve_strat_references(&db_client, &mut refs).await?);
+    if let Some(links) = &req.additional_strats {
+        errors.extend(validate_strat_links(&db_client, links).await?);
+    }
+    if let Some(tags) = &req.tags {
+        errors.extend(validate_video_tags(&db_client, tags).await?);
+    }
+    if !errors.is_empty() {
+        return Err(ValidationErrors { errors }.into());
+    }
+    req.from_node_id = refs.from_node_id;
+    req.to_node_id = refs.to_node_id;
+
+    let sql = r#"
+        UPDATE video
+        SET updated_account_id=$2,
+            updated_ts=current_timestamp,
+            status=$3,
+            room_id=$4,
+            from_node_id=$5,
+            to_node_id=$6,
+            strat_id=$7,
+            note=$8,
+            dev_note=$9,
+            crop_size=$10,
+            crop_center_x=$11,
+            crop_center_y=$12,
+            thumbnail_t=$13,
+            highlight_start_t=$14,
+            highlight_end_t=$15,
+            priority=$16,
+            annotations=COALESCE($18, annotations),
+            version=version + 1
+        WHERE id=$1 AND version=$17 AND status != 'Deleted'
+    "#;
+    let annotations_json = req
+        .annotations
+        .as_ref()
+        .map(serde_json::to_value)
+        .transpose()?;
+    let txn = db_client.transaction().await?;
+    ensure_initial_revision(&txn, req.video_id, account_info.id).await?;
+    let stmt = txn.prepare_cached(sql).await?;
+    let status_str: String = format!("{:?}", req.status);
+    let cnt = txn
+        .execute(
+            &stmt,
+            &[
+                &req.video_id,
+                &account_info.id,
+                &status_str,
+                &req.room_id,
+                &req.from_node_id,
+                &req.to_node_id,
+                &req.strat_id,
+                &req.note,
+                &req.dev_note,
+                &req.crop_size,
+                &req.crop_center_x,
+                &req.crop_center_y,
+                &req.thumbnail_t,
+                &req.highlight_start_t,
+                &req.highlight_end_t,
+                &req.priority,
+                &req.version,
+                &annotations_json,
+            ],
+        )
+        .await?;
+    if cnt == 0 {
+        drop(txn);
+        match get_video_info(&db_client, req.video_id).await? {
+            Some(current) if current.status != VideoStatus::Deleted => {
+                return Err(EditConflict { current }.into())
+            }
+            _ => bail!("Video not found: {}", req.video_id),
+        }
+    }
+    update_video_strats(&txn, req.video_id, req.additional_strats.as_deref()).await?;
+    if let Some(tags) = &req.tags {
+        update_video_tags(&txn, req.video_id, tags).await?;
+    }
+    record_video_revision(&txn, req.video_id, account_info.id, RevisionAction::Edit).await?;
+    txn.commit().await?;
+    info!("Edited video");
+
+    let mut tasks = vec![];
+    if req.controls_updated {
+        // Trigger processes to encode the thumbnail image and animated highlight.
+        // The full video cannot change, so no need to encode it again.
+        tasks.extend(controls_encoding_tasks(req.video_id, num_parts, &controls));
+    }
+    if req.annotations.is_some() {
+        tasks.push(EncodingTask::Chapters {
+            video_id: req.video_id,
+        });
+    }
+    if !tasks.is_empty() {
+        publish_encoding_tasks(&app_data, tasks).await?;
+    }
+    Ok(())
+}
+
+#[post("/edit-video")]
+async fn edit_video(
+    req_json: web::Bytes,
+    app_data: web::Data<AppData>,
+    http_req: HttpRequest,
+) -> impl Responder {
+    let account_info = match authenticate(app_data.clone(), &http_req, TokenScope::Editor).await {
+        Ok(ai) => ai,
+        Err(e) => {
+            error!("Failed authentication: {}", e);
+            return HttpResponse::Unauthorized().body("Unauthorized");
+        }
+    };
+
+    match try_edit_video(req_json, app_data.clone(), &account_info).await {
+        Ok(_) => {}
+        Err(e) if e.is::<ValidationErrors>() => {
+            error!("Rejected video edit: {}", e);
+            return HttpResponse::BadRequest().json(e.downcast_ref::<ValidationErrors>());
+        }
+        Err(e) if e.is::<EditConflict>() => {
+            error!("Rejected video edit: {}", e);
+            let conflict = e.downcast_ref::<EditConflict>().unwrap();
+            return HttpResponse::Conflict().json(&conflict.current);
+        }
+        Err(e) => {
+            error!("Failed to edit video: {}", e);
+            return HttpResponse::InternalServerError().body("Failed to edit video");
+        }
+    }
+
+    HttpResponse::Ok().body("")
+}
+
+#[derive(Deserialize)]
+struct DeleteVideoRequest {
+    video_id: i32,
+}
+
+#[delete("/")]
+async fn delete_video(
+    req: web::Query<DeleteVideoRequest>,
+    app_data: web::Data<AppData>,
+    http_req: HttpRequest,
+) -> actix_web::Result<impl Responder> {
+    let account_info = match authenticate(app_data.clone(), &http_req, TokenScope::Editor).await {
+        Ok(ai) => ai,
+        Err(e) => {
+            error!("Failed authentication: {}", e);
+            return Err(actix_web::error::ErrorUnauthorized("Authentication failed"));
+        }
+    };
+
+    // Check that the user is authorized to delete this video:
+    let mut db_client = app_data.db.get().await.unwrap();
+    let sql = r#"
+        SELECT 
+            status,
+            created_account_id,
+            permanent
+        FROM video WHERE id=$1
+    "#;
+    let stmt = db_client.prepare_cached(sql).await.unwrap();
+    let row = db_client
+        .query_one(&stmt, &[&req.video_id])
+        .await
+        .map_err(actix_web::error::ErrorNotFound)?;
+    let status_str: String = row.get("status");
+    let status = VideoStatus::try_from(status_str.as_str())
+        .map_err(actix_web::error::ErrorInternalServerError)?;
+    let created_account_id: i32 = row.get("created_account_id");
+    let permanent: bool = row.get("permanent");
+    if status == VideoStatus::Deleted {
+        return Err(actix_web::error::ErrorNotFound("video is already deleted"));
+    }
+    if permanent {
+        return Err(actix_web::error::ErrorForbidden(
+            "video is permanent and may not be deleted",
+        ));
+    }
+    if !account_info.can(Capability::DeleteAnyVideo) {
+        // Other users are only authorized to delete their own videos, and only ones not yet Approved.
+        if status == VideoStatus::Approved {
+            return Err(actix_web::error::ErrorForbidden(
+                "Not authorized to delete Approved video",
+            ));
+        }
+        if created_account_id != account_info.id {
+            return Err(actix_web::error::ErrorForbidden(
+                "not permitted to delete video by other owner",
+            ));
+        }
+    }
+
+    // The video is moved to the trash, from which an Editor can restore it until it is purged.
+    let sql = r#"
+        UPDATE video
+        SET status_before_delete=status,
+            status='Deleted',
+            deleted_ts=current_timestamp,
+            deleted_account_id=$2,
+            version=version + 1
+        WHERE id=$1 AND status != 'Deleted'
+    "#;
+    let txn = db_client
+        .transaction()
+        .await
+        .map_err(actix_web::error::ErrorInternalServerError)?;
+    ensure_initial_revision(&txn, req.video_id, account_info.id)
+        .await
+        .map_err(actix_web::error::ErrorInternalServerError)?;
+    let stmt = txn
+        .prepare_cached(sql)
+        .await
+        .map_err(actix_web::error::ErrorInternalServerError)?;
+    let cnt = txn
+        .execute(&stmt, &[&req.video_id, &account_info.id])
+        .await
+        .map_err(actix_web::error::ErrorInternalServerError)?;
+    if cnt != 1 {
+        return Err(actix_web::error::ErrorInternalServerError(format!(
+            "Unexpected deleted row count: {}",
+            cnt
+        )));
+    }
+    record_video_revision(&txn, req.video_id, account_info.id, RevisionAction::Delete)
+        .await
+        .map_err(actix_web::error::ErrorInternalServerError)?;
+    txn.commit()
+        .await
+        .map_err(actix_web::error::ErrorInternalServerError)?;
+    info!("Deleted video: id={}", req.video_id);
+
+    Ok(HttpResponse::Ok().body(""))
+}
+
+#[derive(Serialize)]
+struct DeletedVideoListing {
+    id: i32,
+    created_user_id: i32,
+    deleted_user_id: Option<i32>,
+    deleted_ts: i64,
+    purge_ts: i64,
+    status_before_delete: Option<VideoStatus>,
+    room_name: Option<String>,
+    strat_name: Option<String>,
+    note: String,
+}
+
+async fn try_list_deleted_videos(app_data: &AppData) -> Result<Vec<DeletedVideoListing>> {
+    let db = app_data.db.get().await?;
+    let sql = r#"
+        SELECT
+            v.id,
+            v.created_account_id,
+            v.deleted_account_id,
+            v.deleted_ts,
+            v.status_before_delete,
+            r.name AS room_name,
+            s.name AS strat_name,
+            v.note
+        FROM video v
+        LEFT JOIN room r ON r.room_id = v.room_id
+        LEFT JOIN strat s ON s.room_id = v.room_id AND s.strat_id = v.strat_id
+        WHERE v.status = 'Deleted'
+        ORDER BY v.deleted_ts DESC
+    "#;
+    let stmt = db.prepare_cached(sql).await?;
+    let rows = db.query(&stmt, &[]).await?;
+    let retention = chrono::Duration::days(app_data.args.deleted_video_retention_days as i64);
+    let mut out = vec![];
+    for row in rows {
+        let deleted_ts: chrono::DateTime<chrono::offset::Utc> = row.get("deleted_ts");
+        let status_before_delete: Option<String> = row.get("status_before_delete");
+        out.push(DeletedVideoListing {
+            id: row.get("id"),
+            created_user_id: row.get("created_account_id"),
+            deleted_user_id: row.get("deleted_account_id"),
+            deleted_ts: deleted_ts.timestamp_millis(),
+            purge_ts: (deleted_ts + retention).timestamp_millis(),
+            status_before_delete: status_before_delete
+                .map(|x| VideoStatus::try_from(x.as_str()))
+                .transpose()?,
+            room_name: row.get("room_name"),
+            strat_name: row.get("strat_name"),
+            note: row.get("note"),
+        });
+    }
+    Ok(out)
+}
+
+#[get("/list-deleted-videos")]
+async fn list_deleted_videos(
+    app_data: web::Data<AppData>,
+    http_req: HttpRequest,
+) -> actix_web::Result<impl Responder> {
+    let _ = authorize(
+        app_data.clone(),
+        &http_req,
+        TokenScope::Editor,
+        Capability::DeleteAnyVideo,
+    )
+    .await?;
+
+    let out = try_list_deleted_videos(&app_data)
+        .await
+        .map_err(|e| actix_web::error::InternalError::new(e, StatusCode::INTERNAL_SERVER_ERROR))?;
+    Ok(web::Json(out))
+}
+
+#[derive(Deserialize)]
+struct RestoreVideoRequest {
+    video_id: i32,
+}
+
+async fn try_restore_video(
+    req: &RestoreVideoRequest,
+    app_data: &AppData,
+    account_info: &AccountInfo,
+) -> Result<bool> {
+    let mut db_client = app_data.db.get().await?;
+    let sql = r#"
+        UPDATE video
+        SET status=COALESCE(status_before_delete, 'Incomplete'),
+            status_before_delete=NULL,
+            deleted_ts=NULL,
+            deleted_account_id=NULL,
+            updated_account_id=$2,
+            updated_ts=current_timestamp,
+            version=version + 1
+        WHERE id=$1 AND status = 'Deleted'
+    "#;
+    let txn = db_client.transaction().await?;
+    let stmt = txn.prepare_cached(sql).await?;
+    let cnt = txn
+        .execute(&stmt, &[&req.video_id, &account_info.id])
+        .await?;
+    if cnt == 0 {
+        return Ok(false);
+    }
+    record_video_revision(&txn, req.video_id, account_info.id, RevisionAction::Restore).await?;
+    txn.commit().await?;
+    info!("Restored video: id={}", req.video_id);
+    Ok(true)
+}
+
+#[post("/restore-video")]
+async fn restore_video(
+    req: web::Json<RestoreVideoRequest>,
+    app_data: web::Data<AppData>,
+    http_req: HttpRequest,
+) -> actix_web::Result<impl Responder> {
+    let account_info = authorize(
+        app_data.clone(),
+        &http_req,
+        TokenScope::Editor,
+        Capability::DeleteAnyVideo,
+    )
+    .await?;
+
+    match try_restore_video(&req, &app_data, &account_info).await {
+        Ok(true) => Ok(HttpResponse::Ok().body("")),
+        Ok(false) => Err(actix_web::error::ErrorNotFound("deleted video not found")),
+        Err(e) => {
+            error!("Failed to restore video: {}", e);
+            Err(actix_web::error::ErrorInternalServerError(
+                "Failed to restore video",
+            ))
+        }
+    }
+}
+
+#[derive(Deserialize)]
+struct SetVideoPermanentRequest {
+    video_id: i32,
+    permanent: bool,
+}
+
+async fn try_set_video_permanent(
+    req: &SetVideoPermanentRequest,
+    app_data: &AppData,
+    account_info: &AccountInfo,
+) -> Result<bool> {
+    let mut db_client = app_data.db.get().await?;
+    let txn = db_client.transaction().await?;
+    let sql = r#"
+        UPDATE video
+        SET permanent=$2,
+            updated_account_id=$3,
+            updated_ts=current_timestamp,
+            version=version + 1
+        WHERE id=$1 AND status != 'Deleted'
+    "#;
+    let stmt = txn.prepare_cached(sql).await?;
+    let n = txn
+        .execute(&stmt, &[&req.video_id, &req.permanent, &account_info.id])
+        .await?;
+    if n == 0 {
+        return Ok(false);
+    }
+    record_audit_event(
+        &txn,
+        account_info.id,
+        AuditAction::SetVideoPermanent,
+        None,
+        serde_json::json!({"video_id": req.video_id, "permanent": req.permanent}),
+    )
+    .await?;
+    txn.commit().await?;
+    Ok(true)
+}
+
+#[post("/set-video-permanent")]
+async fn set_video_permanent(
+    req: web::Json<SetVideoPermanentRequest>,
+    app_data: web::Data<AppData>,
+    http_req: HttpRequest,
+) -> actix_web::Result<impl Responder> {
+    let account_info = authorize(
+        app_data.clone(),
+        &http_req,
+        TokenScope::Editor,
+        Capability::ManagePermanent,
+    )
+    .await?;
+
+    match try_set_video_permanent(&req, &app_data, &account_info).await {
+        Ok(true) => Ok(HttpResponse::Ok().body("")),
+        Ok(false) => Err(actix_web::error::ErrorNotFound("video not found")),
+        Err(e) => {
+            error!("Failed to set video permanent flag: {}", e);
+            Err(actix_web::error::ErrorInternalServerError(
+                "Failed to set video permanent flag",
+            ))
+        }
+    }
+}
+
+async fn delete_object_if_exists(app_data: &AppData, key: &str) -> Result<()> {
+    let path = object_store::path::Path::parse(key)?;
+    match app_data.video_store.delete(&path).await {
+        Ok(()) | Err(object_store::Error::NotFound { .. }) => Ok(()),
+        Err(e) => Err(e.into()),
+    }
+}
+
+// Stop showing a purged video as the example video for a tech or notable strat. (Settings are kept
+// for a video which is only deleted, so that they apply again if it is restored, but it isn't
+// listed meanwhile.)
+async fn clear_video_settings(
+    db: &impl deadpool_postgres::GenericClient,
+    video_id: i32,
+) -> Result<()> {
+    let sql = "UPDATE tech_setting SET video_id = NULL WHERE video_id = $1";
+    let stmt = db.prepare_cached(sql).await?;
+    db.execute(&stmt, &[&video_id]).await?;
+    let sql = "UPDATE notable_setting SET video_id = NULL WHERE video_id = $1";
+    let stmt = db.prepare_cached(sql).await?;
+    db.execute(&stmt, &[&video_id]).await?;
+    Ok(())
+}
+
+// Permanently remove a deleted video: its objects in storage (and CDN cache) first, then
+// its database rows, so that a failure part-way is retried on the next purge.
+async fn purge_video(app_data: &AppData, video_id: i32, num_parts: i32) -> Result<()> {
+    let prefix = &app_data.args.video_storage_prefix;
+    for part_num in 0..num_parts {
+        let key = format!("{}avi-xz/{}-{}.avi.xz", prefix, video_id, part_num);
+        delete_object_if_exists(app_data, &key).await?;
+    }
+    for key in [
+        format!("png/{}.png", video_id),
+        format!("webp/{}.webp", video_id),
+        format!("mp4/{}.mp4", video_id),
+        format!("mp4/{}.vtt", video_id),
+    ] {
+        delete_object_if_exists(app_data, &format!("{}{}", prefix, key)).await?;
+        app_data.cache_invalidator.invalidate(&key).await?;
+    }
+
+    let mut db_client = app_data.db.get().await?;
+    let txn = db_client.transaction().await?;
+    let sql = "DELETE FROM upload_part WHERE video_id = $1";
+    let stmt = txn.prepare_cached(sql).await?;
+    txn.execute(&stmt, &[&video_id]).await?;
+    let sql = "DELETE FROM upload_session WHERE video_id = $1";
+    let stmt = txn.prepare_cached(sql).await?;
+    txn.execute(&stmt, &[&video_id]).await?;
+    let sql = "DELETE FROM video_strat WHERE video_id = $1";
+    let stmt = txn.prepare_cached(sql).await?;
+    txn.execute(&stmt, &[&video_id]).await?;
+    let sql = "DELETE FROM video_tag WHERE video_id = $1";
+    let stmt = txn.prepare_cached(sql).await?;
+    txn.execute(&stmt, &[&video_id]).await?;
+    let sql = "DELETE FROM encoding_job WHERE video_id = $1";
+    let stmt = txn.prepare_cached(sql).await?;
+    txn.execute(&stmt, &[&video_id]).await?;
+    let sql = "DELETE FROM video_comment WHERE video_id = $1";
+    let stmt = txn.prepare_cached(sql).await?;
+    txn.execute(&stmt, &[&video_id]).await?;
+    let sql = "DELETE FROM video_review WHERE video_id = $1";
+    let stmt = txn.prepare_cached(sql).await?;
+    txn.execute(&stmt, &[&video_id]).await?;
+    clear_video_settings(&txn, video_id).await?;
+    let sql = "DELETE FROM video WHERE id = $1 AND status = 'Deleted'";
+    let stmt = txn.prepare_cached(sql).await?;
+    let cnt = txn.execute(&stmt, &[&video_id]).await?;
+    if cnt != 1 {
+        bail!("Unexpected purged row count: {}", cnt);
+    }
+    txn.commit().await?;
+    info!("Purged deleted video: id={}", video_id);
+    Ok(())
+}
+
+async fn purge_deleted_videos(app_data: &AppData) -> Result<()> {
+    // The lock is held by this connection's session, so it is released even if the server dies.
+    let lock_db = app_data.db.get().await?;
+    let stmt = lock_db
+        .prepare_cached("SELECT pg_try_advisory_lock($1)")
+        .await?;
+    let locked: bool = lock_db.query_one(&stmt, &[&PURGE_LOCK_ID]).await?.get(0);
+    if !locked {
+        info!("Skipping purge, as another server is purging");
+        return Ok(());
+    }
+    let result = purge_deleted_videos_locked(app_data).await;
+    let stmt = lock_db
+        .prepare_cached("SELECT pg_advisory_unlock($1)")
+        .await?;
+    lock_db.query_one(&stmt, &[&PURGE_LOCK_ID]).await?;
+    result
+}
+
+async fn purge_deleted_videos_locked(app_data: &AppData) -> Result<()> {
+    let db = app_data.db.get().await?;
+    let sql = r#"
+        SELECT id, num_parts
+        FROM video
+        WHERE status = 'Deleted'
+          AND deleted_ts < current_timestamp - make_interval(days => $1)
+        ORDER BY id
+    "#;
+    let stmt = db.prepare_cached(sql).await?;
+    let rows = db
+        .query(&stmt, &[&app_data.args.deleted_video_retention_days])
+        .await?;
+    drop(db);
+    for row in rows {
+        let video_id: i32 = row.get("id");
+        let num_parts: i32 = row.get("num_parts");
+        if let Err(e) = purge_video(app_data, video_id, num_parts).await {
+            error!("Failed to purge video {}: {}", video_id, e);
+        }
+    }
+    Ok(())
+}
+
+#[derive(Deserialize)]
+struct VideoHistoryRequest {
+    video_id: i32,
+}
+
+#[derive(Serialize)]
+struct FieldChange {
+    field: String,
+    old_value: serde_json::Value,
+    new_value: serde_json::Value,
+}
+
+#[derive(Serialize)]
+struct VideoRevisionListing {
+    revision: i32,
+    action: RevisionAction,
+    user_id: i32,
+    username: Option<String>,
+    created_ts: i64,
+    changes: Vec<FieldChange>,
+}
+
+async fn try_get_video_history(
+    req: &VideoHistoryRequest,
+    app_data: &AppData,
+) -> Result<Vec<VideoRevisionListing>> {
+    let db = app_data.db.get().await?;
+    let sql = r#"
+        SELECT
+            r.revision,
+            r.action,
+            r.account_id,
+            a.username,
+            r.created_ts,
+            r.data
+        FROM video_revision r
+        LEFT JOIN account a ON a.id = r.account_id
+        WHERE r.video_id = $1
+        ORDER BY r.revision
+    "#;
+    let stmt = db.prepare_cached(sql).await?;
+    let rows = db.query(&stmt, &[&req.video_id]).await?;
+
+    // Each revision stores a full snapshot, so changes are found by comparing against the
+    // previous revision's snapshot field by field.
+    let mut prev_data = serde_json::Map::new();
+    let mut out = vec![];
+    for row in rows {
+        let action_str: String = row.get("action");
+        let created_ts: chrono::DateTime<chrono::offset::Utc> = row.get("created_ts");
+        let data: serde_json::Value = row.get("data");
+        let data = match data {
+            serde_json::Value::Object(m) => m,
+            _ => bail!("Unexpected revision data: {}", data),
+        };
+        let mut changes = vec![];
+        for (field, new_value) in &data {
+            let old_value = prev_data
+                .get(field)
+                .cloned()
+                .unwrap_or(serde_json::Value::Null);
+            if &old_value != new_value {
+                changes.push(FieldChange {
+                    field: field.clone(),
+                    old_value,
+                    new_value: new_value.clone(),
+                });
+            }
+        }
+        out.push(VideoRevisionListing {
+            revision: row.get("revision"),
+            action: RevisionAction::from_str(&action_str)?,
+            user_id: row.get("account_id"),
+            username: row.get("username"),
+            created_ts: created_ts.timestamp_millis(),
+            changes,
+        });
+        prev_data = data;
+    }
+    Ok(out)
+}
+
+#[get("/video-history")]
+async fn video_history(
+    req: web::Query<VideoHistoryRequest>,
+    app_data: web::Data<AppData>,
+    http_req: HttpRequest,
+) -> actix_web::Result<impl Responder> {
+    // Revisions include dev notes and status changes, so they are only shown to signed-in users.
+    authorize(
+        app_data.clone(),
+        &http_req,
+        TokenScope::ReadOnly,
+        Capability::UploadVideos,
+    )
+    .await?;
+    let out = try_get_video_history(&req, &app_data)
+        .await
+        .map_err(actix_web::error::ErrorInternalServerError)?;
+    Ok(web::Json(out))
+}
+
+#[derive(Deserialize)]
+struct RevertVideoRequest {
+    video_id: i32,
+    revision: i32,
+    // Version of the video that the revert is based on, from `GetVideoResponse`.
+    version: i32,
+}
+
+// Restore the fields of a video from an earlier revision, other than its status (which changes
+// only through editing and review, so that approvals are always recorded). Returns false if the
+// video or revision does not exist.
+async fn try_revert_video(
+    req: &RevertVideoRequest,
+    app_data: &AppData,
+    account_info: &AccountInfo,
+) -> Result<bool> {
+    let mut db_client = app_data.db.get().await?;
+    let sql = "SELECT data FROM video_revision WHERE video_id = $1 AND revision = $2";
+    let stmt = db_client.prepare_cached(sql).await?;
+    let Some(row) = db_client
+        .query_opt(&stmt, &[&req.video_id, &req.revision])
+        .await?
+    else {
+        return Ok(false);
+    };
+    let mut target: VideoSnapshot = serde_json::from_value(row.get("data"))?;
+    let Some(current_video) = get_video_info(&db_client, req.video_id).await? else {
+        return Ok(false);
+    };
+    let current = get_video_snapshot(&db_client, req.video_id).await?;
+    let frame_info = get_video_frame_info(&db_client, req.video_id).await?;
+
+    // The earlier revision is checked as if it were submitted as an edit now, since the frame
+    // limits, sm-json-data references and tags may have changed since it was recorded.
+    let mut errors = vec![];
+    if current_video.status == VideoStatus::Deleted {
+        errors.push(FieldError {
+            field: "video_id",
+            message: "deleted video must be restored before reverting".to_string(),
+        });
+    }
+    if let Some(controls) = target.controls() {
+        errors.extend(validate_video_controls(
+            &controls,
+            &frame_info,
+            app_data.args.max_highlight_frames,
+        ));
+    }
+    errors.extend(validate_annotations(&target.annotations, &frame_info));
+    let mut refs = StratReferences {
+        room_id: target.room_id,
+        from_node_id: target.from_node_id,
+        to_node_id: target.to_node_id,
+        strat_id: target.strat_id,
+    };
+    errors.extend(resolve_strat_references(&db_client, &mut refs).await?);
+    if let Some(links) = &target.additional_strats {
+        errors.extend(validate_strat_links(&db_client, links).await?);
+    }
+    if let Some(tags) = &target.tags {
+        errors.extend(validate_video_tags(&db_client, tags).await?);
     }
-
-    let db_client = app_data.db.get().await.unwrap();
-    let sql = "SELECT num_parts FROM video WHERE id=$1";
-    let stmt = db_client.prepare_cached(&sql).await?;
-    let result = db_client.query_one(&stmt, &[&req.video_id]).await?;
-    let num_parts: i32 = result.get(0);
+    if !errors.is_empty() {
+        return Err(ValidationErrors { errors }.into());
+    }
+    target.from_node_id = refs.from_node_id;
+    target.to_node_id = refs.to_node_id;
 
     let sql = r#"
         UPDATE video
-        SET status = $15,
+        SET updated_account_id=$2,
             updated_ts=current_timestamp,
-            submitted_ts=current_timestamp,
-            room_id=$2,
-            from_node_id=$3,
-            to_node_id=$4,
-            strat_id=$5,
-            note=$6,
-            dev_note=$7,
-            crop_size=$8,
-            crop_center_x=$9,
-            crop_center_y=$10,
-            thumbnail_t=$11,
-            highlight_start_t=$12,
-            highlight_end_t=$13
-        WHERE id=$14 AND created_account_id=$1 AND next_part_num = num_parts
+            room_id=$3,
+            from_node_id=$4,
+            to_node_id=$5,
+            strat_id=$6,
+            note=$7,
+            dev_note=$8,
+            crop_size=$9,
+            crop_center_x=$10,
+            crop_center_y=$11,
+            thumbnail_t=$12,
+            highlight_start_t=$13,
+            highlight_end_t=$14,
+            priority=$15,
+            annotations=$16,
+            version=version + 1
+        WHERE id=$1 AND version=$17 AND status != 'Deleted'
     "#;
-    let stmt = db_client.prepare_cached(sql).await?;
-    let cnt = db_client
+    let annotations_json = serde_json::to_value(&target.annotations)?;
+    let txn = db_client.transaction().await?;
+    ensure_initial_revision(&txn, req.video_id, account_info.id).await?;
+    let stmt = txn.prepare_cached(sql).await?;
+    let cnt = txn
         .execute(
             &stmt,
             &[
-                &account_info.id,
-                &req.room_id,
-                &req.from_node_id,
-                &req.to_node_id,
-                &req.strat_id,
-                &req.note,
-                &req.dev_note,
-                &req.crop_size,
-                &req.crop_center_x,
-                &req.crop_center_y,
-                &req.thumbnail_t,
-                &req.highlight_start_t,
-                &req.highlight_end_t,
                 &req.video_id,
-                &status,
+                &account_info.id,
+                &target.room_id,
+                &target.from_node_id,
+                &target.to_node_id,
+                &target.strat_id,
+                &target.note,
+                &target.dev_note,
+                &target.crop_size,
+                &target.crop_center_x,
+                &target.crop_center_y,
+                &target.thumbnail_t,
+                &target.highlight_start_t,
+                &target.highlight_end_t,
+                &target.priority,
+                &annotations_json,
+                &req.version,
             ],
         )
         .await?;
-    if cnt == 1 {
-        info!("Submitted video: id={}", req.video_id);
-    } else {
-        bail!(
-            "Unexpected update row count: {} (upload may be incomplete?)",
-            cnt
-        );
+    if cnt == 0 {
+        drop(txn);
+        match get_video_info(&db_client, req.video_id).await? {
+            Some(current) if current.status != VideoStatus::Deleted => {
+                return Err(EditConflict { current }.into())
+            }
+            _ => return Ok(false),
+        }
     }
+    update_video_strats(&txn, req.video_id, target.additional_strats.as_deref()).await?;
+    if let Some(tags) = &target.tags {
+        update_video_tags(&txn, req.video_id, tags).await?;
+    }
+    record_video_revision(&txn, req.video_id, account_info.id, RevisionAction::Revert).await?;
+    txn.commit().await?;
+    info!(
+        "Reverted video id={} to revision {}",
+        req.video_id, req.revision
+    );
 
-    // Send messages to RabbitMQ to trigger processes to encode the thumbnail image, animated highlight, and full video.
-    let mq = app_data.mq.get().await?;
-    let channel = mq.create_channel().await?;
-    let props = lapin::BasicProperties::default().with_delivery_mode(2); // persistent delivery
+    // Re-encode the thumbnail and highlight if the reverted controls differ from the current ones.
+    let controls_changed = current.crop_size != target.crop_size
+        || current.crop_center_x != target.crop_center_x
+        || current.crop_center_y != target.crop_center_y
+        || current.thumbnail_t != target.thumbnail_t
+        || current.highlight_start_t != target.highlight_start_t
+        || current.highlight_end_t != target.highlight_end_t;
+    let mut tasks = vec![];
+    if let (true, Some(controls)) = (controls_changed, target.controls()) {
+        tasks.extend(controls_encoding_tasks(
+            req.video_id,
+            frame_info.num_parts,
+            &controls,
+        ));
+    }
+    if current.annotations != target.annotations {
+        tasks.push(EncodingTask::Chapters {
+            video_id: req.video_id,
+        });
+    }
+    if !tasks.is_empty() {
+        publish_encoding_tasks(app_data, tasks).await?;
+    }
+    Ok(true)
+}
 
-    let thumbnail_task = EncodingTask::ThumbnailImage {
-        video_id: req.video_id,
-        num_parts,
-        crop_center_x: req.crop_center_x,
-        crop_center_y: req.crop_center_y,
-        crop_size: req.crop_size,
-        frame_number: req.thumbnail_t,
-    };
-    channel
-        .basic_publish(
-            "",
-            &app_data.args.rabbit_queue,
-            lapin::options::BasicPublishOptions::default(),
-            &serde_json::to_vec(&thumbnail_task)?,
-            props.clone(),
-        )
+#[post("/revert-video")]
+async fn revert_video(
+    req: web::Json<RevertVideoRequest>,
+    app_data: web::Data<AppData>,
+    http_req: HttpRequest,
+) -> actix_web::Result<impl Responder> {
+    let account_info = authorize(
+        app_data.clone(),
+        &http_req,
+        TokenScope::Editor,
+        Capability::EditAnyVideo,
+    )
+    .await?;
+
+    match try_revert_video(&req, &app_data, &account_info).await {
+        Ok(true) => Ok(HttpResponse::Ok().body("")),
+        Ok(false) => Err(actix_web::error::ErrorNotFound("video revision not found")),
+        Err(e) if e.is::<ValidationErrors>() => {
+            error!("Rejected video revert: {}", e);
+            Ok(HttpResponse::BadRequest().json(e.downcast_ref::<ValidationErrors>()))
+        }
+        Err(e) if e.is::<EditConflict>() => {
+            error!("Rejected video revert: {}", e);
+            let conflict = e.downcast_ref::<EditConflict>().unwrap();
+            Ok(HttpResponse::Conflict().json(&conflict.current))
+        }
+        Err(e) => {
+            error!("Failed to revert video: {}", e);
+            Err(actix_web::error::ErrorInternalServerError(
+                "Failed to revert video",
+            ))
+        }
+    }
+}
+
+#[derive(Serialize, Deserialize, Debug, Clone, Copy, PartialEq, Eq, strum::EnumString)]
+enum ReviewAction {
+    Assign,
+    Approve,
+    Reject,
+    RequestChanges,
+}
+
+impl ReviewAction {
+    // Status that a video awaiting review is given by this decision.
+    fn new_status(self) -> Option<VideoStatus> {
+        match self {
+            ReviewAction::Assign => None,
+            ReviewAction::Approve => Some(VideoStatus::Approved),
+            ReviewAction::Reject => Some(VideoStatus::Disabled),
+            ReviewAction::RequestChanges => Some(VideoStatus::Incomplete),
+        }
+    }
+}
+
+// Videos are reviewed by someone other than their uploader.
+#[derive(Debug)]
+struct SelfReview;
+
+impl std::fmt::Display for SelfReview {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        write!(f, "videos cannot be reviewed by their uploader")
+    }
+}
+
+impl std::error::Error for SelfReview {}
+
+// Whether the video exists and was uploaded by the given account.
+async fn is_video_uploader(
+    db: &impl deadpool_postgres::GenericClient,
+    video_id: i32,
+    account_id: i32,
+) -> Result<bool> {
+    let sql = "SELECT 1 FROM video WHERE id = $1 AND created_account_id = $2";
+    let stmt = db.prepare_cached(sql).await?;
+    Ok(db
+        .query_opt(&stmt, &[&video_id, &account_id])
+        .await?
+        .is_some())
+}
+
+#[derive(Deserialize)]
+struct AssignReviewerRequest {
+    video_id: i32,
+    // Reviewer to assign, or none to unassign:
+    reviewer_id: Option<i32>,
+}
+
+async fn try_assign_reviewer(
+    req: &AssignReviewerRequest,
+    app_data: &AppData,
+    account_info: &AccountInfo,
+) -> Result<bool> {
+    let mut db_client = app_data.db.get().await?;
+    if let Some(reviewer_id) = req.reviewer_id {
+        let sql = "SELECT permission FROM account WHERE id = $1 AND NOT disabled";
+        let stmt = db_client.prepare_cached(sql).await?;
+        let can_review = match db_client.query_opt(&stmt, &[&reviewer_id]).await? {
+            Some(row) => {
+                let permission_str: String = row.get("permission");
+                Permission::from_str(&permission_str)?
+                    .capabilities()
+                    .contains(&Capability::ApproveVideos)
+            }
+            None => false,
+        };
+        if !can_review {
+            return Err(ValidationErrors {
+                errors: vec![FieldError {
+                    field: "reviewer_id",
+                    message: "must be an active account which can approve videos".to_string(),
+                }],
+            }
+            .into());
+        }
+    }
+
+    let txn = db_client.transaction().await?;
+    let sql = r#"
+        UPDATE video
+        SET reviewer_account_id=$2
+        WHERE id=$1 AND status != 'Deleted' AND created_account_id IS DISTINCT FROM $2
+    "#;
+    let stmt = txn.prepare_cached(sql).await?;
+    let cnt = txn
+        .execute(&stmt, &[&req.video_id, &req.reviewer_id])
         .await?;
+    if cnt == 0 {
+        if let Some(reviewer_id) = req.reviewer_id {
+            if is_video_uploader(&txn, req.video_id, reviewer_id).await? {
+                return Err(SelfReview.into());
+            }
+        }
+        return Ok(false);
+    }
+    let sql = r#"
+        INSERT INTO video_review (video_id, account_id, action, assigned_account_id)
+        VALUES ($1, $2, 'Assign', $3)
+    "#;
+    let stmt = txn.prepare_cached(sql).await?;
+    txn.execute(&stmt, &[&req.video_id, &account_info.id, &req.reviewer_id])
+        .await?;
+    txn.commit().await?;
+    info!(
+        "Assigned reviewer: video_id={}, reviewer_id={:?}",
+        req.video_id, req.reviewer_id
+    );
+    Ok(true)
+}
 
-    let highlight_task = EncodingTask::HighlightAnimation {
-        video_id: req.video_id,
-        num_parts,
-        crop_center_x: req.crop_center_x,
-        crop_center_y: req.crop_center_y,
-        crop_size: req.crop_size,
-        start_frame_number: req.highlight_start_t,
-        end_frame_number: req.highlight_end_t,
-    };
-    channel
-        .basic_publish(
-            "",
-            &app_data.args.rabbit_queue,
-            lapin::options::BasicPublishOptions::default(),
-            &serde_json::to_vec(&highlight_task)?,
-            props.clone(),
+#[post("/assign-reviewer")]
+async fn assign_reviewer(
+    req: web::Json<AssignReviewerRequest>,
+    app_data: web::Data<AppData>,
+    http_req: HttpRequest,
+) -> actix_web::Result<impl Responder> {
+    let account_info = authorize(
+        app_data.clone(),
+        &http_req,
+        TokenScope::Editor,
+        Capability::ApproveVideos,
+    )
+    .await?;
+
+    match try_assign_reviewer(&req, &app_data, &account_info).await {
+        Ok(true) => Ok(HttpResponse::Ok().body("")),
+        Ok(false) => Err(actix_web::error::ErrorNotFound("video not found")),
+        Err(e) if e.is::<ValidationErrors>() => {
+            Ok(HttpResponse::BadRequest().json(e.downcast_ref::<ValidationErrors>()))
+        }
+        Err(e) if e.is::<SelfReview>() => Err(actix_web::error::ErrorForbidden(e.to_string())),
+        Err(e) => {
+            error!("Failed to assign reviewer: {}", e);
+            Err(actix_web::error::ErrorInternalServerError(
+                "Failed to assign reviewer",
+            ))
+        }
+    }
+}
+
+#[derive(Deserialize)]
+struct ReviewVideoRequest {
+    video_id: i32,
+    // Version of the video that was reviewed, which must still be current:
+    version: i32,
+    decision: ReviewAction,
+    comment: String,
+}
+
+async fn try_review_video(
+    req: &ReviewVideoRequest,
+    app_data: &AppData,
+    account_info: &AccountInfo,
+) -> Result<()> {
+    let mut errors = vec![];
+    let new_status = req.decision.new_status();
+    if new_status.is_none() {
+        errors.push(FieldError {
+            field: "decision",
+            message: "must be Approve, Reject, or RequestChanges".to_string(),
+        });
+    }
+    if req.comment.trim().is_empty() {
+        errors.push(FieldError {
+            field: "comment",
+            message: "is required".to_string(),
+        });
+    }
+    if !errors.is_empty() {
+        return Err(ValidationErrors { errors }.into());
+    }
+    let new_status_str = format!("{:?}", new_status.unwrap());
+
+    let mut db_client = app_data.db.get().await?;
+    let txn = db_client.transaction().await?;
+    ensure_initial_revision(&txn, req.video_id, account_info.id).await?;
+    let sql = r#"
+        UPDATE video
+        SET status=$2,
+            updated_account_id=$3,
+            updated_ts=current_timestamp,
+            version=version + 1
+        WHERE id=$1 AND version=$4 AND status = 'Complete' AND created_account_id != $3
+    "#;
+    let stmt = txn.prepare_cached(sql).await?;
+    let cnt = txn
+        .execute(
+            &stmt,
+            &[
+                &req.video_id,
+                &new_status_str,
+                &account_info.id,
+                &req.version,
+            ],
         )
         .await?;
+    if cnt == 0 {
+        drop(txn);
+        if is_video_uploader(&db_client, req.video_id, account_info.id).await? {
+            return Err(SelfReview.into());
+        }
+        match get_video_info(&db_client, req.video_id).await? {
+            Some(current) if current.version != req.version => {
+                return Err(EditConflict { current }.into())
+            }
+            Some(_) => {
+                return Err(ValidationErrors {
+                    errors: vec![FieldError {
+                        field: "video_id",
+                        message: "video is not awaiting review".to_string(),
+                    }],
+                }
+                .into())
+            }
+            None => bail!("Video not found: {}", req.video_id),
+        }
+    }
+    let sql = r#"
+        INSERT INTO video_review (video_id, account_id, action, comment)
+        VALUES ($1, $2, $3, $4)
+    "#;
+    let stmt = txn.prepare_cached(sql).await?;
+    let action_str = format!("{:?}", req.decision);
+    txn.execute(
+        &stmt,
+        &[&req.video_id, &account_info.id, &action_str, &req.comment],
+    )
+    .await?;
+    record_video_revision(&txn, req.video_id, account_info.id, RevisionAction::Review).await?;
+    txn.commit().await?;
+    info!(
+        "Reviewed video: id={}, decision={}, reviewer={}",
+        req.video_id, action_str, account_info.id
+    );
+    Ok(())
+}
 
-    let full_video_task = EncodingTask::FullVideo {
-        video_id: req.video_id,
-        num_parts,
+#[post("/review-video")]
+async fn review_video(
+    req: web::Json<ReviewVideoRequest>,
+    app_data: web::Data<AppData>,
+    http_req: HttpRequest,
+) -> actix_web::Result<impl Responder> {
+    let account_info = authorize(
+        app_data.clone(),
+        &http_req,
+        TokenScope::Editor,
+        Capability::ApproveVideos,
+    )
+    .await?;
+
+    match try_review_video(&req, &app_data, &account_info).await {
+        Ok(()) => Ok(HttpResponse::Ok().body("")),
+        Err(e) if e.is::<ValidationErrors>() => {
+            Ok(HttpResponse::BadRequest().json(e.downcast_ref::<ValidationErrors>()))
+        }
+        Err(e) if e.is::<EditConflict>() => {
+            let conflict = e.downcast_ref::<EditConflict>().unwrap();
+            Ok(HttpResponse::Conflict().json(&conflict.current))
+        }
+        Err(e) if e.is::<SelfReview>() => Err(actix_web::error::ErrorForbidden(e.to_string())),
+        Err(e) => {
+            error!("Failed to review video: {}", e);
+            Err(actix_web::error::ErrorInternalServerError(
+                "Failed to review video",
+            ))
+        }
+    }
+}
+
+#[derive(Deserialize)]
+struct VideoReviewsRequest {
+    video_id: i32,
+}
+
+#[derive(Serialize)]
+struct VideoReviewListing {
+    id: i32,
+    account_id: i32,
+    username: Option<String>,
+    created_ts: i64,
+    action: ReviewAction,
+    assigned_account_id: Option<i32>,
+    comment: String,
+}
+
+async fn try_list_video_reviews(
+    req: &VideoReviewsRequest,
+    app_data: &AppData,
+) -> Result<Vec<VideoReviewListing>> {
+    let db = app_data.db.get().await?;
+    let sql = r#"
+        SELECT
+            r.id,
+            r.account_id,
+            a.username,
+            r.created_ts,
+            r.action,
+            r.assigned_account_id,
+            r.comment
+        FROM video_review r
+        LEFT JOIN account a ON a.id = r.account_id
+        WHERE r.video_id = $1
+        ORDER BY r.id
+    "#;
+    let stmt = db.prepare_cached(sql).await?;
+    let rows = db.query(&stmt, &[&req.video_id]).await?;
+    let mut out = vec![];
+    for row in rows {
+        let created_ts: chrono::DateTime<chrono::offset::Utc> = row.get("created_ts");
+        let action_str: String = row.get("action");
+        out.push(VideoReviewListing {
+            id: row.get("id"),
+            account_id: row.get("account_id"),
+            username: row.get("username"),
+            created_ts: created_ts.timestamp_millis(),
+            action: ReviewAction::from_str(&action_str)?,
+            assigned_account_id: row.get("assigned_account_id"),
+            comment: row.get("comment"),
+        });
+    }
+    Ok(out)
+}
+
+#[get("/video-reviews")]
+async fn video_reviews(
+    req: web::Query<VideoReviewsRequest>,
+    app_data: web::Data<AppData>,
+    http_req: HttpRequest,
+) -> actix_web::Result<impl Responder> {
+    // Review comments are feedback for contributors, so they are only shown to signed-in users.
+    authorize(
+        app_data.clone(),
+        &http_req,
+        TokenScope::ReadOnly,
+        Capability::UploadVideos,
+    )
+    .await?;
+    let out = try_list_video_reviews(&req, &app_data)
+        .await
+        .map_err(actix_web::error::ErrorInternalServerError)?;
+    Ok(web::Json(out))
+}
+
+#[derive(Deserialize)]
+struct EncodingJobsRequest {
+    video_id: i32,
+}
+
+#[derive(Serialize)]
+struct EncodingJobListing {
+    id: i32,
+    task_type: String,
+    status: EncodingJobStatus,
+    attempts: i32,
+    worker_id: Option<String>,
+    error: Option<String>,
+    // Last lines of ffmpeg's output from the latest attempt, if it failed while encoding:
+    stderr_tail: Option<String>,
+    created_ts: i64,
+    started_ts: Option<i64>,
+    finished_ts: Option<i64>,
+}
+
+async fn try_list_encoding_jobs(
+    req: &EncodingJobsRequest,
+    app_data: &AppData,
+    account_info: &AccountInfo,
+) -> Result<Option<Vec<EncodingJobListing>>> {
+    let db = app_data.db.get().await?;
+    let sql = "SELECT created_account_id FROM video WHERE id = $1";
+    let stmt = db.prepare_cached(sql).await?;
+    let Some(row) = db.query_opt(&stmt, &[&req.video_id]).await? else {
+        return Ok(None);
     };
-    channel
-        .basic_publish(
-            "",
-            &app_data.args.rabbit_queue,
-            lapin::options::BasicPublishOptions::default(),
-            &serde_json::to_vec(&full_video_task)?,
-            props.clone(),
-        )
-        .await?;
+    let created_account_id: i32 = row.get("created_account_id");
+    if created_account_id != account_info.id && !account_info.can(Capability::EditAnyVideo) {
+        return Ok(None);
+    }
 
-    // Set the user account to active so it will show in the user listing:
-    let sql = "UPDATE account SET active = TRUE WHERE id = $1";
-    let stmt = db_client.prepare_cached(sql).await?;
-    let cnt = db_client.execute(&stmt, &[&account_info.id]).await?;
-    if cnt != 1 {
-        bail!("Error updating account 'active'");
+    let sql = r#"
+        SELECT
+            id,
+            task_type,
+            status,
+            attempts,
+            worker_id,
+            error,
+            stderr_tail,
+            created_ts,
+            started_ts,
+            finished_ts
+        FROM encoding_job
+        WHERE video_id = $1
+        ORDER BY id DESC
+    "#;
+    let stmt = db.prepare_cached(sql).await?;
+    let rows = db.query(&stmt, &[&req.video_id]).await?;
+    let mut out = vec![];
+    for row in rows {
+        let created_ts: chrono::DateTime<chrono::offset::Utc> = row.get("created_ts");
+        let started_ts: Option<chrono::DateTime<chrono::offset::Utc>> = row.get("started_ts");
+        let finished_ts: Option<chrono::DateTime<chrono::offset::Utc>> = row.get("finished_ts");
+        let status_str: String = row.get("status");
+        out.push(EncodingJobListing {
+            id: row.get("id"),
+            task_type: row.get("task_type"),
+            status: EncodingJobStatus::from_str(&status_str)?,
+            attempts: row.get("attempts"),
+            worker_id: row.get("worker_id"),
+            error: row.get("error"),
+            stderr_tail: row.get("stderr_tail"),
+            created_ts: created_ts.timestamp_millis(),
+            started_ts: started_ts.map(|t| t.timestamp_millis()),
+            finished_ts: finished_ts.map(|t| t.timestamp_millis()),
+        });
+    }
+    Ok(Some(out))
+}
+
+// Encoding jobs for a video, most recent first, visible to its uploader and to editors.
+#[get("/encoding-jobs")]
+async fn list_encoding_jobs(
+    req: web::Query<EncodingJobsRequest>,
+    app_data: web::Data<AppData>,
+    http_req: HttpRequest,
+) -> actix_web::Result<impl Responder> {
+    let account_info = authorize(
+        app_data.clone(),
+        &http_req,
+        TokenScope::Upload,
+        Capability::UploadVideos,
+    )
+    .await?;
+    let out = try_list_encoding_jobs(&req, &app_data, &account_info)
+        .await
+        .map_err(actix_web::error::ErrorInternalServerError)?
+        .ok_or_else(|| actix_web::error::ErrorNotFound("video not found"))?;
+    Ok(web::Json(out))
+}
+
+const MAX_COMMENT_LENGTH: usize = 10000;
+
+#[derive(Deserialize)]
+struct VideoCommentsRequest {
+    video_id: i32,
+}
+
+#[derive(Serialize)]
+struct VideoCommentListing {
+    id: i32,
+    parent_id: Option<i32>,
+    account_id: i32,
+    username: Option<String>,
+    created_ts: i64,
+    edited_ts: Option<i64>,
+    // Frame of the video that the comment refers to, if any:
+    frame_number: Option<i32>,
+    deleted: bool,
+    // Markdown source, and the rendered HTML (which is safe to insert into the page):
+    body: String,
+    body_html: String,
+}
+
+async fn try_list_video_comments(
+    req: &VideoCommentsRequest,
+    app_data: &AppData,
+) -> Result<Vec<VideoCommentListing>> {
+    let db = app_data.db.get().await?;
+    let sql = r#"
+        SELECT
+            c.id,
+            c.parent_id,
+            c.account_id,
+            a.username,
+            c.created_ts,
+            c.edited_ts,
+            c.frame_number,
+            c.deleted_ts IS NOT NULL AS deleted,
+            c.body
+        FROM video_comment c
+        JOIN video v ON v.id = c.video_id
+        LEFT JOIN account a ON a.id = c.account_id
+        WHERE c.video_id = $1 AND v.status != 'Deleted'
+        ORDER BY c.id
+    "#;
+    let stmt = db.prepare_cached(sql).await?;
+    let rows = db.query(&stmt, &[&req.video_id]).await?;
+    let mut out = vec![];
+    for row in rows {
+        let created_ts: chrono::DateTime<chrono::offset::Utc> = row.get("created_ts");
+        let edited_ts: Option<chrono::DateTime<chrono::offset::Utc>> = row.get("edited_ts");
+        let body: String = row.get("body");
+        out.push(VideoCommentListing {
+            id: row.get("id"),
+            parent_id: row.get("parent_id"),
+            account_id: row.get("account_id"),
+            username: row.get("username"),
+            created_ts: created_ts.timestamp_millis(),
+            edited_ts: edited_ts.map(|t| t.timestamp_millis()),
+            frame_number: row.get("frame_number"),
+            deleted: row.get("deleted"),
+            body_html: render_markdown(&body),
+            body,
+        });
     }
-
-    Ok(())
+    Ok(out)
 }
 
-#[post("/submit-video")]
-async fn submit_video(
-    req_json: web::Bytes,
+// Comments are public, like the videos they are on (so none are listed for deleted videos).
+#[get("/video-comments")]
+async fn list_video_comments(
+    req: web::Query<VideoCommentsRequest>,
     app_data: web::Data<AppData>,
-    auth: BasicAuth,
-) -> impl Responder {
-    let account_info = match authenticate(app_data.clone(), &auth).await {
-        Ok(ai) => ai,
-        Err(e) => {
-            error!("Failed authentication: {}", e);
-            return HttpResponse::Unauthorized().body("Unauthorized");
-        }
-    };
+) -> actix_web::Result<impl Responder> {
+    let out = try_list_video_comments(&req, &app_data)
+        .await
+        .map_err(actix_web::error::ErrorInternalServerError)?;
+    Ok(web::Json(out))
+}
 
-    match try_submit_video(req_json, app_data.clone(), &account_info).await {
-        Ok(_) => {}
-        Err(e) => {
-            error!("Failed to submit video: {}", e);
-            return HttpResponse::InternalServerError().body("Failed to submit video");
+fn validate_comment(
+    body: &str,
+    frame_number: Option<i32>,
+    frame_count: Option<i32>,
+) -> Vec<FieldError> {
+    let mut errors = vec![];
+    if body.trim().is_empty() {
+        errors.push(FieldError {
+            field: "body",
+            message: "is required".to_string(),
+        });
+    } else if body.chars().count() > MAX_COMMENT_LENGTH {
+        errors.push(FieldError {
+            field: "body",
+            message: format!("must be at most {} characters", MAX_COMMENT_LENGTH),
+        });
+    }
+    if let Some(frame_number) = frame_number {
+        if frame_number < 0 || frame_count.is_some_and(|n| frame_number >= n) {
+            errors.push(FieldError {
+                field: "frame_number",
+                message: "must be a frame of the video".to_string(),
+            });
         }
     }
-
-    HttpResponse::Ok().body("")
+    errors
 }
 
-#[derive(Deserialize, Debug)]
-struct EditVideoRequest {
+#[derive(Deserialize)]
+struct CreateVideoCommentRequest {
     video_id: i32,
-    room_id: Option<i32>,
-    from_node_id: Option<i32>,
-    to_node_id: Option<i32>,
-    strat_id: Option<i32>,
-    note: String,
-    dev_note: String,
-    crop_size: i32,
-    crop_center_x: i32,
-    crop_center_y: i32,
-    thumbnail_t: i32,
-    highlight_start_t: i32,
-    highlight_end_t: i32,
-    status: VideoStatus,
-    priority: Option<i32>,
-    controls_updated: bool,
+    // Comment being replied to, if any:
+    parent_id: Option<i32>,
+    body: String,
+    frame_number: Option<i32>,
 }
 
-async fn try_edit_video(
-    req_json: web::Bytes,
-    app_data: web::Data<AppData>,
-    account_info: &AccountInfo,
-) -> Result<()> {
-    info!("edit_video: {}", std::str::from_utf8(&req_json)?);
-    let req: EditVideoRequest = serde_json::from_slice(&req_json)?;
+#[derive(Serialize)]
+struct CreateVideoCommentResponse {
+    id: i32,
+}
 
-    let db_client = app_data.db.get().await.unwrap();
-    match account_info.permission {
-        Permission::Editor => {
-            // Editors are authorized to edit any video, so no check needed.
-        }
-        Permission::Default => {
-            if req.status == VideoStatus::Approved {
-                bail!("Not authorized to set this video as Approved");
-            }
+// Returns the ID of the new comment, or None if the video doesn't exist (or is deleted).
+async fn try_create_video_comment(
+    req: &CreateVideoCommentRequest,
+    app_data: &AppData,
+    account_info: &AccountInfo,
+) -> Result<Option<i32>> {
+    let db_client = app_data.db.get().await?;
+    let sql = "SELECT frame_count FROM video WHERE id = $1 AND status != 'Deleted'";
+    let stmt = db_client.prepare_cached(sql).await?;
+    let frame_count: Option<i32> = match db_client.query_opt(&stmt, &[&req.video_id]).await? {
+        Some(row) => row.get("frame_count"),
+        None => return Ok(None),
+    };
 
-            let sql = r#"
-                SELECT
-                    status,
-                    created_account_id 
-                FROM video WHERE id=$1
-            "#;
-            let stmt = db_client.prepare_cached(&sql).await?;
-            let row = db_client.query_one(&stmt, &[&req.video_id]).await?;
-            let status_str: String = row.get("status");
-            let status = VideoStatus::try_from(status_str.as_str())?;
-            let created_account_id: i32 = row.get("created_account_id");
-            if status == VideoStatus::Approved {
-                // It would be more "correct" to return 403 here (and 404 in case the row doesn't exist).
-                bail!("Not authorized to edit Approved video");
-            }
-            if created_account_id != account_info.id {
-                bail!("Not authorized to edit video by different creator");
-            }
+    let mut errors = validate_comment(&req.body, req.frame_number, frame_count);
+    if let Some(parent_id) = req.parent_id {
+        let sql = r#"
+            SELECT 1 FROM video_comment
+            WHERE id = $1 AND video_id = $2 AND deleted_ts IS NULL
+        "#;
+        let stmt = db_client.prepare_cached(sql).await?;
+        if db_client
+            .query_opt(&stmt, &[&parent_id, &req.video_id])
+            .await?
+            .is_none()
+        {
+            errors.push(FieldError {
+                field: "parent_id",
+                message: "must be an existing comment on the same video".to_string(),
+            });
         }
     }
-
-    let sql = "SELECT num_parts FROM video WHERE id=$1";
-    let stmt = db_client.prepare_cached(&sql).await?;
-    let result = db_client.query_one(&stmt, &[&req.video_id]).await?;
-    let num_parts: i32 = result.get(0);
+    if !errors.is_empty() {
+        return Err(ValidationErrors { errors }.into());
+    }
 
     let sql = r#"
-        UPDATE video
-        SET updated_account_id=$2,
-            updated_ts=current_timestamp,
-            status=$3,
-            room_id=$4,
-            from_node_id=$5,
-            to_node_id=$6,
-            strat_id=$7,
-            note=$8,
-            dev_note=$9,
-            crop_size=$10,
-            crop_center_x=$11,
-            crop_center_y=$12,
-            thumbnail_t=$13,
-            highlight_start_t=$14,
-            highlight_end_t=$15,
-            priority=$16
-        WHERE id=$1
+        INSERT INTO video_comment (video_id, parent_id, account_id, body, frame_number)
+        VALUES ($1, $2, $3, $4, $5)
+        RETURNING id
     "#;
     let stmt = db_client.prepare_cached(sql).await?;
-    let status_str: String = format!("{:?}", req.status);
-    let _ = db_client
-        .execute(
+    let row = db_client
+        .query_one(
             &stmt,
             &[
                 &req.video_id,
+                &req.parent_id,
                 &account_info.id,
-                &status_str,
-                &req.room_id,
-                &req.from_node_id,
-                &req.to_node_id,
-                &req.strat_id,
-                &req.note,
-                &req.dev_note,
-                &req.crop_size,
-                &req.crop_center_x,
-                &req.crop_center_y,
-                &req.thumbnail_t,
-                &req.highlight_start_t,
-                &req.highlight_end_t,
-                &req.priority,
+                &req.body,
+                &req.frame_number,
             ],
         )
         .await?;
-    info!("Edited video");
-
-    if req.controls_updated {
-        // Send messages to RabbitMQ to trigger processes to encode the thumbnail image and animated highlight.
-        // The full video cannot change, so no need to encode it again.
-        let mq = app_data.mq.get().await?;
-        let channel = mq.create_channel().await?;
-        let props = lapin::BasicProperties::default().with_delivery_mode(2); // persistent delivery
-
-        let thumbnail_task = EncodingTask::ThumbnailImage {
-            video_id: req.video_id,
-            num_parts,
-            crop_center_x: req.crop_center_x,
-            crop_center_y: req.crop_center_y,
-            crop_size: req.crop_size,
-            frame_number: req.thumbnail_t,
-        };
-        channel
-            .basic_publish(
-                "",
-                &app_data.args.rabbit_queue,
-                lapin::options::BasicPublishOptions::default(),
-                &serde_json::to_vec(&thumbnail_task)?,
-                props.clone(),
-            )
-            .await?;
-
-        let highlight_task = EncodingTask::HighlightAnimation {
-            video_id: req.video_id,
-            num_parts,
-            crop_center_x: req.crop_center_x,
-            crop_center_y: req.crop_center_y,
-            crop_size: req.crop_size,
-            start_frame_number: req.highlight_start_t,
-            end_frame_number: req.highlight_end_t,
-        };
-        channel
-            .basic_publish(
-                "",
-                &app_data.args.rabbit_queue,
-                lapin::options::BasicPublishOptions::default(),
-                &serde_json::to_vec(&highlight_task)?,
-                props.clone(),
-            )
-            .await?;
-    }
-    Ok(())
+    let id: i32 = row.get("id");
+    info!(
+        "Created comment: id={}, video_id={}, account_id={}",
+        id, req.video_id, account_info.id
+    );
+    Ok(Some(id))
 }
 
-#[post("/edit-video")]
-async fn edit_video(
-    req_json: web::Bytes,
+#[post("/video-comments")]
+async fn create_video_comment(
+    req: web::Json<CreateVideoCommentRequest>,
     app_data: web::Data<AppData>,
-    auth: BasicAuth,
-) -> impl Responder {
-    let account_info = match authenticate(app_data.clone(), &auth).await {
-        Ok(ai) => ai,
-        Err(e) => {
-            error!("Failed authentication: {}", e);
-            return HttpResponse::Unauthorized().body("Unauthorized");
+    http_req: HttpRequest,
+) -> actix_web::Result<impl Responder> {
+    let account_info = authorize(
+        app_data.clone(),
+        &http_req,
+        TokenScope::Upload,
+        Capability::UploadVideos,
+    )
+    .await?;
+
+    match try_create_video_comment(&req, &app_data, &account_info).await {
+        Ok(Some(id)) => Ok(HttpResponse::Ok().json(CreateVideoCommentResponse { id })),
+        Ok(None) => Err(actix_web::error::ErrorNotFound("video not found")),
+        Err(e) if e.is::<ValidationErrors>() => {
+            Ok(HttpResponse::BadRequest().json(e.downcast_ref::<ValidationErrors>()))
         }
-    };
-
-    match try_edit_video(req_json, app_data.clone(), &account_info).await {
-        Ok(_) => {}
         Err(e) => {
-            error!("Failed to edit video: {}", e);
-            return HttpResponse::InternalServerError().body("Failed to edit video");
+            error!("Failed to create comment: {}", e);
+            Err(actix_web::error::ErrorInternalServerError(
+                "Failed to create comment",
+            ))
         }
     }
+}
 
-    HttpResponse::Ok().body("")
+struct CommentInfo {
+    account_id: i32,
+    frame_count: Option<i32>,
+}
+
+// Look up a comment which has not been deleted, on a video which has not been deleted.
+async fn get_comment_info(app_data: &AppData, comment_id: i32) -> Result<Option<CommentInfo>> {
+    let db_client = app_data.db.get().await?;
+    let sql = r#"
+        SELECT c.account_id, v.frame_count
+        FROM video_comment c
+        JOIN video v ON v.id = c.video_id
+        WHERE c.id = $1 AND c.deleted_ts IS NULL AND v.status != 'Deleted'
+    "#;
+    let stmt = db_client.prepare_cached(sql).await?;
+    Ok(db_client
+        .query_opt(&stmt, &[&comment_id])
+        .await?
+        .map(|row| CommentInfo {
+            account_id: row.get("account_id"),
+            frame_count: row.get("frame_count"),
+        }))
 }
 
 #[derive(Deserialize)]
-struct DeleteVideoRequest {
-    video_id: i32,
+struct EditVideoCommentRequest {
+    id: i32,
+    body: String,
+    frame_number: Option<i32>,
 }
 
-#[delete("/")]
-async fn delete_video(
-    req: web::Query<DeleteVideoRequest>,
+#[post("/edit-video-comment")]
+async fn edit_video_comment(
+    req: web::Json<EditVideoCommentRequest>,
     app_data: web::Data<AppData>,
-    auth: BasicAuth,
+    http_req: HttpRequest,
 ) -> actix_web::Result<impl Responder> {
-    let account_info = match authenticate(app_data.clone(), &auth).await {
-        Ok(ai) => ai,
-        Err(e) => {
-            error!("Failed authentication: {}", e);
-            return Err(actix_web::error::ErrorUnauthorized("Authentication failed"));
-        }
-    };
+    let account_info = authorize(
+        app_data.clone(),
+        &http_req,
+        TokenScope::Upload,
+        Capability::UploadVideos,
+    )
+    .await?;
+
+    let comment = get_comment_info(&app_data, req.id)
+        .await
+        .map_err(actix_web::error::ErrorInternalServerError)?
+        .ok_or_else(|| actix_web::error::ErrorNotFound("comment not found"))?;
+    // Comments can only be edited by their author (though moderators can delete them).
+    if comment.account_id != account_info.id {
+        return Err(actix_web::error::ErrorForbidden(
+            "not permitted to edit comment by other author",
+        ));
+    }
+    let errors = validate_comment(&req.body, req.frame_number, comment.frame_count);
+    if !errors.is_empty() {
+        return Ok(HttpResponse::BadRequest().json(ValidationErrors { errors }));
+    }
 
-    // Check that the user is authorized to delete this video:
-    let db_client = app_data.db.get().await.unwrap();
+    let db_client = app_data
+        .db
+        .get()
+        .await
+        .map_err(actix_web::error::ErrorInternalServerError)?;
     let sql = r#"
-        SELECT 
-            status,
-            created_account_id,
-            permanent
-        FROM video WHERE id=$1
+        UPDATE video_comment
+        SET body=$2, frame_number=$3, edited_ts=current_timestamp
+        WHERE id=$1 AND deleted_ts IS NULL
     "#;
-    let stmt = db_client.prepare_cached(&sql).await.unwrap();
-    let row = db_client
-        .query_one(&stmt, &[&req.video_id])
+    let stmt = db_client
+        .prepare_cached(sql)
         .await
-        .map_err(|e| actix_web::error::ErrorNotFound(e))?;
-    let status_str: String = row.get("status");
-    let status = VideoStatus::try_from(status_str.as_str())
-        .map_err(|e| actix_web::error::ErrorInternalServerError(e))?;
-    let created_account_id: i32 = row.get("created_account_id");
-    let permanent: bool = row.get("permanent");
-    if permanent {
+        .map_err(actix_web::error::ErrorInternalServerError)?;
+    db_client
+        .execute(&stmt, &[&req.id, &req.body, &req.frame_number])
+        .await
+        .map_err(actix_web::error::ErrorInternalServerError)?;
+    info!("Edited comment: id={}", req.id);
+    Ok(HttpResponse::Ok().body(""))
+}
+
+#[derive(Deserialize)]
+struct DeleteVideoCommentRequest {
+    id: i32,
+}
+
+#[delete("/video-comments")]
+async fn delete_video_comment(
+    req: web::Query<DeleteVideoCommentRequest>,
+    app_data: web::Data<AppData>,
+    http_req: HttpRequest,
+) -> actix_web::Result<impl Responder> {
+    let account_info = authorize(
+        app_data.clone(),
+        &http_req,
+        TokenScope::Upload,
+        Capability::UploadVideos,
+    )
+    .await?;
+
+    let comment = get_comment_info(&app_data, req.id)
+        .await
+        .map_err(actix_web::error::ErrorInternalServerError)?
+        .ok_or_else(|| actix_web::error::ErrorNotFound("comment not found"))?;
+    if comment.account_id != account_info.id && !account_info.can(Capability::EditAnyVideo) {
         return Err(actix_web::error::ErrorForbidden(
-            "video is permanent and may not be deleted",
+            "not permitted to delete comment by other author",
         ));
     }
-    match account_info.permission {
-        Permission::Editor => {
-            // Editors are authorized to delete any non-permanent video, so no check needed.
+
+    // The row is kept (without its text) so that replies to it remain in place in the thread.
+    let db_client = app_data
+        .db
+        .get()
+        .await
+        .map_err(actix_web::error::ErrorInternalServerError)?;
+    let sql = r#"
+        UPDATE video_comment
+        SET body='', frame_number=NULL, deleted_ts=current_timestamp, deleted_account_id=$2
+        WHERE id=$1 AND deleted_ts IS NULL
+    "#;
+    let stmt = db_client
+        .prepare_cached(sql)
+        .await
+        .map_err(actix_web::error::ErrorInternalServerError)?;
+    db_client
+        .execute(&stmt, &[&req.id, &account_info.id])
+        .await
+        .map_err(actix_web::error::ErrorInternalServerError)?;
+    info!(
+        "Deleted comment: id={}, account_id={}",
+        req.id, account_info.id
+    );
+    Ok(HttpResponse::Ok().body(""))
+}
+
+#[derive(Serialize)]
+struct TagListing {
+    tag_id: i32,
+    name: String,
+    description: String,
+    video_count: i64,
+}
+
+// All tags, each with the number of videos which have it among those matching the filters (i.e.
+// the number of videos which would be listed by also filtering on the tag, when matching all tags).
+async fn try_list_tags(filters: &VideoFilters, app_data: &AppData) -> Result<Vec<TagListing>> {
+    let tag_ids = parse_tag_list(filters.tags.as_deref().unwrap_or(""))?;
+    let mut param_values: Vec<&(dyn ToSql + Sync)> = vec![];
+    let (from_sql, where_sql) = video_filter_sql(filters, &tag_ids, &mut param_values);
+    let sql = format!(
+        r#"
+        SELECT
+            t.tag_id,
+            t.name,
+            t.description,
+            COALESCE(c.video_count, 0) AS video_count
+        FROM tag t
+        LEFT JOIN (
+            SELECT vt.tag_id, COUNT(*) AS video_count
+            FROM video_tag vt
+            JOIN (SELECT v.id {} {}) fv ON fv.id = vt.video_id
+            GROUP BY vt.tag_id
+        ) c ON c.tag_id = t.tag_id
+        ORDER BY t.name
+        "#,
+        from_sql, where_sql
+    );
+    let db_client = app_data.db.get().await?;
+    let stmt = db_client.prepare_cached(&sql).await?;
+    let rows = db_client.query(&stmt, param_values.as_slice()).await?;
+    Ok(rows
+        .iter()
+        .map(|row| TagListing {
+            tag_id: row.get("tag_id"),
+            name: row.get("name"),
+            description: row.get("description"),
+            video_count: row.get("video_count"),
+        })
+        .collect())
+}
+
+#[get("/tags")]
+async fn list_tags(
+    filters: web::Query<VideoFilters>,
+    app_data: web::Data<AppData>,
+) -> actix_web::Result<impl Responder> {
+    match try_list_tags(&filters, &app_data).await {
+        Ok(out) => Ok(HttpResponse::Ok().json(out)),
+        Err(e) if e.is::<ValidationErrors>() => {
+            Ok(HttpResponse::BadRequest().json(e.downcast_ref::<ValidationErrors>()))
+        }
+        Err(e) => {
+            Err(actix_web::error::InternalError::new(e, StatusCode::INTERNAL_SERVER_ERROR).into())
+        }
+    }
+}
+
+#[derive(Deserialize, Debug)]
+struct TagUpdate {
+    // Existing tag to update, or None to create a new one:
+    tag_id: Option<i32>,
+    name: String,
+    #[serde(default)]
+    description: String,
+}
+
+#[derive(Serialize)]
+struct TagUpdateResponse {
+    tag_id: i32,
+}
+
+fn validate_tag(tag_update: &TagUpdate) -> Vec<FieldError> {
+    let mut errors = vec![];
+    let name = tag_update.name.trim();
+    if name.is_empty() {
+        errors.push(FieldError {
+            field: "name",
+            message: "must not be empty".to_string(),
+        });
+    } else if name.chars().count() > 100 {
+        errors.push(FieldError {
+            field: "name",
+            message: "must be at most 100 characters".to_string(),
+        });
+    } else if name.contains(',') {
+        errors.push(FieldError {
+            field: "name",
+            message: "must not contain commas".to_string(),
+        });
+    }
+    if tag_update.description.chars().count() > 1000 {
+        errors.push(FieldError {
+            field: "description",
+            message: "must be at most 1000 characters".to_string(),
+        });
+    }
+    errors
+}
+
+fn tag_exists_error() -> ValidationErrors {
+    ValidationErrors {
+        errors: vec![FieldError {
+            field: "name",
+            message: "a tag with this name already exists".to_string(),
+        }],
+    }
+}
+
+// Returns the ID of the created or updated tag, or None if the tag to update was not found.
+async fn try_update_tag(
+    app_data: &AppData,
+    tag_update: &TagUpdate,
+    account_info: &AccountInfo,
+) -> Result<Option<i32>> {
+    let errors = validate_tag(tag_update);
+    if !errors.is_empty() {
+        return Err(ValidationErrors { errors }.into());
+    }
+    let name = tag_update.name.trim();
+    let db_client = app_data.db.get().await?;
+
+    let sql = "SELECT tag_id FROM tag WHERE lower(name) = lower($1)";
+    let stmt = db_client.prepare_cached(sql).await?;
+    if let Some(row) = db_client.query_opt(&stmt, &[&name]).await? {
+        let existing_id: i32 = row.get("tag_id");
+        if Some(existing_id) != tag_update.tag_id {
+            return Err(tag_exists_error().into());
         }
-        Permission::Default => {
-            // Other users are only authorized to delete their own videos, and only ones not yet Approved.
-            if status == VideoStatus::Approved {
-                return Err(actix_web::error::ErrorForbidden(
-                    "Not authorized to delete Approved video",
-                ));
+    }
+
+    // The check above is repeated by a unique index, in case of a concurrent update.
+    let tag_id = match tag_update.tag_id {
+        Some(tag_id) => {
+            let sql = "UPDATE tag SET name = $2, description = $3 WHERE tag_id = $1";
+            let stmt = db_client.prepare_cached(sql).await?;
+            let cnt = match db_client
+                .execute(&stmt, &[&tag_id, &name, &tag_update.description])
+                .await
+            {
+                Ok(cnt) => cnt,
+                Err(e) if is_unique_violation(&e) => return Err(tag_exists_error().into()),
+                Err(e) => return Err(e.into()),
+            };
+            if cnt != 1 {
+                return Ok(None);
             }
-            if created_account_id != account_info.id {
-                return Err(actix_web::error::ErrorForbidden(
-                    "not permitted to delete video by other owner",
-                ));
+            tag_id
+        }
+        None => {
+            let sql = r#"
+                INSERT INTO tag (name, description, created_account_id)
+                VALUES ($1, $2, $3)
+                RETURNING tag_id
+            "#;
+            let stmt = db_client.prepare_cached(sql).await?;
+            match db_client
+                .query_one(&stmt, &[&name, &tag_update.description, &account_info.id])
+                .await
+            {
+                Ok(row) => row.get("tag_id"),
+                Err(e) if is_unique_violation(&e) => return Err(tag_exists_error().into()),
+                Err(e) => return Err(e.into()),
             }
         }
+    };
+    info!(
+        "Updated tag: id={}, name={:?}, account_id={}",
+        tag_id, name, account_info.id
+    );
+    Ok(Some(tag_id))
+}
+
+#[post("/tags")]
+async fn update_tag(
+    req: web::Json<TagUpdate>,
+    app_data: web::Data<AppData>,
+    http_req: HttpRequest,
+) -> actix_web::Result<impl Responder> {
+    let account_info = authorize(
+        app_data.clone(),
+        &http_req,
+        TokenScope::Editor,
+        Capability::ManageTags,
+    )
+    .await?;
+    match try_update_tag(&app_data, &req, &account_info).await {
+        Ok(Some(tag_id)) => Ok(HttpResponse::Ok().json(TagUpdateResponse { tag_id })),
+        Ok(None) => Err(actix_web::error::ErrorNotFound("tag not found")),
+        Err(e) if e.is::<ValidationErrors>() => {
+            Ok(HttpResponse::BadRequest().json(e.downcast_ref::<ValidationErrors>()))
+        }
+        Err(e) => {
+            error!("Failed to update tag: {}", e);
+            Err(actix_web::error::ErrorInternalServerError(
+                "Failed to update tag",
+            ))
+        }
     }
+}
 
-    let sql = "DELETE FROM video WHERE id=$1";
-    let stmt = db_client
-        .prepare_cached(&sql)
-        .await
-        .map_err(|e| actix_web::error::ErrorInternalServerError(e))?;
-    let cnt = db_client
-        .execute(&stmt, &[&req.video_id])
-        .await
-        .map_err(|e| actix_web::error::ErrorInternalServerError(e))?;
-    if cnt != 1 {
-        return Err(actix_web::error::ErrorInternalServerError(format!(
-            "Unexpected deleted row count: {}",
-            cnt
-        )));
+#[derive(Deserialize)]
+struct DeleteTagRequest {
+    tag_id: i32,
+}
+
+async fn try_delete_tag(
+    app_data: &AppData,
+    tag_id: i32,
+    account_info: &AccountInfo,
+) -> Result<bool> {
+    let mut db_client = app_data.db.get().await?;
+    let txn = db_client.transaction().await?;
+    let sql = "DELETE FROM video_tag WHERE tag_id = $1";
+    let stmt = txn.prepare_cached(sql).await?;
+    let cnt_videos = txn.execute(&stmt, &[&tag_id]).await?;
+    let sql = "DELETE FROM tag WHERE tag_id = $1";
+    let stmt = txn.prepare_cached(sql).await?;
+    if txn.execute(&stmt, &[&tag_id]).await? != 1 {
+        return Ok(false);
     }
+    txn.commit().await?;
+    info!(
+        "Deleted tag: id={}, removed from {} videos, account_id={}",
+        tag_id, cnt_videos, account_info.id
+    );
+    Ok(true)
+}
 
+#[delete("/tags")]
+async fn delete_tag(
+    req: web::Query<DeleteTagRequest>,
+    app_data: web::Data<AppData>,
+    http_req: HttpRequest,
+) -> actix_web::Result<impl Responder> {
+    let account_info = authorize(
+        app_data.clone(),
+        &http_req,
+        TokenScope::Editor,
+        Capability::ManageTags,
+    )
+    .await?;
+    if !try_delete_tag(&app_data, req.tag_id, &account_info)
+        .await
+        .map_err(actix_web::error::ErrorInternalServerError)?
+    {
+        return Err(actix_web::error::ErrorNotFound("tag not found"));
+    }
     Ok(HttpResponse::Ok().body(""))
 }
 
@@ -904,13 +5431,13 @@ async fn download(app_data: &AppData, object_path: &str) -> actix_web::Result<Ve
         .video_store
         .get(
             &object_store::path::Path::parse(object_path)
-                .map_err(|e| actix_web::error::ErrorInternalServerError(e))?,
+                .map_err(actix_web::error::ErrorInternalServerError)?,
         )
         .await
-        .map_err(|e| actix_web::error::ErrorInternalServerError(e))?
+        .map_err(actix_web::error::ErrorInternalServerError)?
         .bytes()
         .await
-        .map_err(|e| actix_web::error::ErrorInternalServerError(e))?;
+        .map_err(actix_web::error::ErrorInternalServerError)?;
     info!("decompressing & recompressing {}", object_path);
     let uncompressed = async_compression::tokio::bufread::XzDecoder::new(&*xz_data);
     let buf_uncompressed = tokio::io::BufReader::new(uncompressed);
@@ -923,9 +5450,9 @@ async fn download(app_data: &AppData, object_path: &str) -> actix_web::Result<Ve
 async fn download_video(
     req: web::Query<DownloadVideoRequest>,
     app_data: web::Data<AppData>,
-    auth: BasicAuth,
+    http_req: HttpRequest,
 ) -> actix_web::Result<impl Responder> {
-    let _ = match authenticate(app_data.clone(), &auth).await {
+    let _ = match authenticate(app_data.clone(), &http_req, TokenScope::ReadOnly).await {
         Ok(ai) => ai,
         Err(e) => {
             error!("Failed authentication: {}", e);
@@ -947,7 +5474,7 @@ async fn download_video(
         block_on(download(&app_data, &object_path_clone)).unwrap()
     })
     .await
-    .map_err(|e| actix_web::error::ErrorInternalServerError(e))?;
+    .map_err(actix_web::error::ErrorInternalServerError)?;
     info!("responding with recompressed {}", object_path);
     Ok(HttpResponse::Ok().body(output))
 }
@@ -989,22 +5516,71 @@ enum ListVideosSortBy {
     LogicOrder,
 }
 
+// Filters on the videos listed by /list-videos, which /tags also accepts (for its video counts).
 #[derive(Deserialize)]
-struct ListVideosRequest {
+struct VideoFilters {
     room_id: Option<i32>,
     from_node_id: Option<i32>,
     to_node_id: Option<i32>,
     strat_id: Option<i32>,
     user_id: Option<i32>,
     video_id: Option<i32>,
-    status_list: String,
+    // Comma-separated statuses (by default, all except Deleted):
+    status_list: Option<String>,
     notes: Option<String>,
+    review_filter: Option<ReviewFilter>,
+    // Only include videos assigned to this reviewer:
+    reviewer_id: Option<i32>,
+    // Comma-separated tag IDs, matched according to `tag_match`:
+    tags: Option<String>,
+    tag_match: Option<TagMatch>,
+}
+
+// Ordering and paging of /list-videos, whose query string also contains the `VideoFilters`.
+#[derive(Deserialize)]
+struct ListVideosRequest {
     sort_by: ListVideosSortBy,
     limit: Option<i64>,
     offset: Option<i64>,
     include_count: Option<bool>,
 }
 
+#[derive(Deserialize, Clone, Copy, Default)]
+enum TagMatch {
+    // Videos with all of the given tags:
+    #[default]
+    All,
+    // Videos with at least one of the given tags:
+    Any,
+}
+
+fn parse_tag_list(tags: &str) -> Result<Vec<i32>> {
+    let mut tag_ids: Vec<i32> = vec![];
+    for s in tags.split(',').filter(|s| !s.is_empty()) {
+        let Ok(tag_id) = s.parse::<i32>() else {
+            return Err(ValidationErrors {
+                errors: vec![FieldError {
+                    field: "tags",
+                    message: format!("invalid tag ID: {}", s),
+                }],
+            }
+            .into());
+        };
+        if !tag_ids.contains(&tag_id) {
+            tag_ids.push(tag_id);
+        }
+    }
+    Ok(tag_ids)
+}
+
+#[derive(Deserialize)]
+enum ReviewFilter {
+    // Complete videos, which are in the queue for review:
+    AwaitingReview,
+    // Videos sent back to the uploader by a reviewer, and not yet resubmitted:
+    ChangesRequested,
+}
+
 #[derive(Serialize, Deserialize, strum::EnumString, Debug, Eq, PartialEq)]
 enum VideoStatus {
     Pending,
@@ -1013,6 +5589,7 @@ enum VideoStatus {
     Approved,
     Postponed,
     Disabled,
+    Deleted,
 }
 
 #[derive(Serialize)]
@@ -1034,6 +5611,7 @@ struct VideoListing {
     to_node_name: Option<String>,
     strat_name: Option<String>,
     priority: Option<i32>,
+    tags: Vec<i32>,
 }
 
 #[derive(Serialize)]
@@ -1042,10 +5620,144 @@ struct ListVideosResponse {
     total_count: Option<i64>,
 }
 
+// The FROM and WHERE clauses selecting the videos (as `v`) which match the filters, adding their
+// parameters to `param_values`. Videos match the room/node/strat filters through any of their
+// linked strats (in `video_strat`), or through their own columns for videos without a strat yet.
+// The matching link (preferring the primary one) is joined as `ls`, with its room as `lr`.
+fn video_filter_sql<'a>(
+    filters: &'a VideoFilters,
+    tag_ids: &'a Vec<i32>,
+    param_values: &mut Vec<&'a (dyn ToSql + Sync)>,
+) -> (String, String) {
+    let mut sql_filters: Vec<String> = vec![];
+    let mut link_filters: Vec<String> = vec![];
+    let mut primary_filters: Vec<String> = vec![];
+    let strat_filters = [
+        (&filters.room_id, "vs.room_id", "v.room_id"),
+        (&filters.from_node_id, "ss.from_node_id", "v.from_node_id"),
+        (&filters.to_node_id, "ss.to_node_id", "v.to_node_id"),
+        (&filters.strat_id, "vs.strat_id", "v.strat_id"),
+    ];
+    for (value, link_column, primary_column) in strat_filters {
+        if let Some(value) = value {
+            let n = param_values.len() + 1;
+            link_filters.push(format!("AND {} = ${}", link_column, n));
+            primary_filters.push(format!("{} = ${}", primary_column, n));
+            param_values.push(value);
+        }
+    }
+    if !primary_filters.is_empty() {
+        sql_filters.push(format!(
+            "(ls.strat_id IS NOT NULL OR ({}))",
+            primary_filters.join(" AND ")
+        ));
+    }
+
+    let from_sql = format!(
+        r#"
+        FROM video v
+        LEFT JOIN room r ON r.room_id = v.room_id
+        LEFT JOIN node f ON f.room_id = v.room_id AND f.node_id = v.from_node_id
+        LEFT JOIN node t ON t.room_id = v.room_id AND t.node_id = v.to_node_id
+        LEFT JOIN strat s ON s.room_id = v.room_id AND s.strat_id = v.strat_id
+        LEFT JOIN LATERAL (
+            SELECT vs.room_id, vs.strat_id, ss.from_node_id, ss.to_node_id
+            FROM video_strat vs
+            JOIN strat ss ON ss.room_id = vs.room_id AND ss.strat_id = vs.strat_id
+            WHERE vs.video_id = v.id {}
+            ORDER BY vs.is_primary DESC, vs.room_id, vs.strat_id
+            LIMIT 1
+        ) ls ON TRUE
+        LEFT JOIN room lr ON lr.room_id = COALESCE(ls.room_id, v.room_id)
+        "#,
+        link_filters.join(" ")
+    );
+
+    sql_filters.push("submitted_ts IS NOT NULL".to_string());
+    if let Some(video_id) = &filters.video_id {
+        sql_filters.push(format!("v.id = ${}", param_values.len() + 1));
+        param_values.push(video_id);
+    }
+    if let Some(notes) = &filters.notes {
+        sql_filters.push(format!(
+            "(s.name ILIKE '%' || ${} || '%'
+             OR v.note ILIKE '%' || ${} || '%' 
+             OR v.dev_note ILIKE '%' || ${} || '%')",
+            param_values.len() + 1,
+            param_values.len() + 1,
+            param_values.len() + 1
+        ));
+        param_values.push(notes);
+    }
+    if let Some(user_id) = &filters.user_id {
+        sql_filters.push(format!(
+            "v.created_account_id = ${}",
+            param_values.len() + 1
+        ));
+        param_values.push(user_id);
+    }
+    match filters.review_filter {
+        Some(ReviewFilter::AwaitingReview) => {
+            sql_filters.push("v.status = 'Complete'".to_string());
+        }
+        Some(ReviewFilter::ChangesRequested) => {
+            sql_filters.push(
+                "v.status = 'Incomplete' AND (
+                    SELECT action FROM video_review
+                    WHERE video_id = v.id AND action != 'Assign'
+                    ORDER BY id DESC LIMIT 1
+                ) = 'RequestChanges'"
+                    .to_string(),
+            );
+        }
+        None => {}
+    }
+    if let Some(reviewer_id) = &filters.reviewer_id {
+        sql_filters.push(format!(
+            "v.reviewer_account_id = ${}",
+            param_values.len() + 1
+        ));
+        param_values.push(reviewer_id);
+    }
+    if !tag_ids.is_empty() {
+        sql_filters.push(match filters.tag_match.unwrap_or_default() {
+            TagMatch::All => format!(
+                "NOT EXISTS (
+                    SELECT 1 FROM unnest(${}::integer[]) AS t(tag_id)
+                    WHERE NOT EXISTS (
+                        SELECT 1 FROM video_tag vt WHERE vt.video_id = v.id AND vt.tag_id = t.tag_id
+                    )
+                )",
+                param_values.len() + 1
+            ),
+            TagMatch::Any => format!(
+                "EXISTS (SELECT 1 FROM video_tag vt WHERE vt.video_id = v.id AND vt.tag_id = ANY(${}))",
+                param_values.len() + 1
+            ),
+        });
+        param_values.push(tag_ids);
+    }
+    if let Some(status_list) = &filters.status_list {
+        sql_filters.push(format!(
+            "v.status = ANY(regexp_split_to_array(${},','))",
+            param_values.len() + 1
+        ));
+        param_values.push(status_list);
+    }
+    sql_filters.push("v.status != 'Deleted'".to_string());
+    let where_sql = format!("WHERE {}\n", sql_filters.join(" AND "));
+    (from_sql, where_sql)
+}
+
 async fn try_list_videos(
+    filters: &VideoFilters,
     req: &ListVideosRequest,
     app_data: &AppData,
 ) -> Result<ListVideosResponse> {
+    let tag_ids = parse_tag_list(filters.tags.as_deref().unwrap_or(""))?;
+    let mut param_values: Vec<&(dyn ToSql + Sync)> = vec![];
+    let (from_sql, where_sql) = video_filter_sql(filters, &tag_ids, &mut param_values);
+
     let mut sql_parts: Vec<String> = vec![];
     sql_parts.push(format!(
         r#"
@@ -1067,70 +5779,17 @@ async fn try_list_videos(
             r.name as room_name,
             f.name as from_node_name,
             t.name as to_node_name,
-            s.name as strat_name
-        FROM video v
-        LEFT JOIN room r ON r.room_id = v.room_id
-        LEFT JOIN node f ON f.room_id = v.room_id AND f.node_id = v.from_node_id
-        LEFT JOIN node t ON t.room_id = v.room_id AND t.node_id = v.to_node_id
-        LEFT JOIN strat s ON s.room_id = v.room_id AND s.strat_id = v.strat_id
+            s.name as strat_name,
+            ARRAY(SELECT tag_id FROM video_tag WHERE video_id = v.id ORDER BY tag_id) AS tags
         "#,
         if req.include_count.unwrap_or(false) {
             "COUNT(*) OVER() AS total_count,"
         } else {
             ""
-        }
-    ));
-
-    let mut sql_filters: Vec<String> = vec![];
-    let mut param_values: Vec<&(dyn ToSql + Sync)> = vec![];
-
-    sql_filters.push("submitted_ts IS NOT NULL".to_string());
-    if req.room_id.is_some() {
-        sql_filters.push(format!("v.room_id = ${}", param_values.len() + 1));
-        param_values.push(req.room_id.as_ref().unwrap());
-    }
-    if req.from_node_id.is_some() {
-        sql_filters.push(format!("v.from_node_id = ${}", param_values.len() + 1));
-        param_values.push(req.from_node_id.as_ref().unwrap());
-    }
-    if req.to_node_id.is_some() {
-        sql_filters.push(format!("v.to_node_id = ${}", param_values.len() + 1));
-        param_values.push(req.to_node_id.as_ref().unwrap());
-    }
-    if req.strat_id.is_some() {
-        sql_filters.push(format!("v.strat_id = ${}", param_values.len() + 1));
-        param_values.push(req.strat_id.as_ref().unwrap());
-    }
-    if req.video_id.is_some() {
-        sql_filters.push(format!("v.id = ${}", param_values.len() + 1));
-        param_values.push(req.video_id.as_ref().unwrap());
-    }
-    if req.notes.is_some() {
-        sql_filters.push(format!(
-            "(s.name ILIKE '%' || ${} || '%'
-             OR v.note ILIKE '%' || ${} || '%' 
-             OR v.dev_note ILIKE '%' || ${} || '%')",
-            param_values.len() + 1,
-            param_values.len() + 1,
-            param_values.len() + 1
-        ));
-        param_values.push(req.notes.as_ref().unwrap());
-    }
-    if req.user_id.is_some() {
-        sql_filters.push(format!(
-            "v.created_account_id = ${}",
-            param_values.len() + 1
-        ));
-        param_values.push(req.user_id.as_ref().unwrap());
-    }
-    sql_filters.push(format!(
-        "v.status = ANY(regexp_split_to_array(${},','))",
-        param_values.len() + 1
+        },
     ));
-    param_values.push(&req.status_list);
-    if sql_filters.len() > 0 {
-        sql_parts.push(format!("WHERE {}\n", sql_filters.join(" AND ")));
-    }
+    sql_parts.push(from_sql);
+    sql_parts.push(where_sql);
 
     match req.sort_by {
         ListVideosSortBy::SubmittedTimestamp => {
@@ -1143,17 +5802,24 @@ async fn try_list_videos(
             sql_parts.push("ORDER BY v.updated_ts DESC, v.id\n".to_string());
         }
         ListVideosSortBy::LogicOrder => {
-            sql_parts.push("ORDER BY r.area_id, r.name, v.from_node_id, v.to_node_id, v.strat_id, v.priority, v.id\n".to_string());
+            sql_parts.push(
+                "ORDER BY lr.area_id, lr.name,
+                    COALESCE(ls.from_node_id, v.from_node_id),
+                    COALESCE(ls.to_node_id, v.to_node_id),
+                    COALESCE(ls.strat_id, v.strat_id),
+                    v.priority, v.id\n"
+                    .to_string(),
+            );
         }
     }
 
-    if req.limit.is_some() {
+    if let Some(limit) = &req.limit {
         sql_parts.push(format!("LIMIT ${}\n", param_values.len() + 1));
-        param_values.push(req.limit.as_ref().unwrap());
+        param_values.push(limit);
     }
-    if req.offset.is_some() {
+    if let Some(offset) = &req.offset {
         sql_parts.push(format!("OFFSET ${}\n", param_values.len() + 1));
-        param_values.push(req.offset.as_ref().unwrap());
+        param_values.push(offset);
     }
 
     let sql = sql_parts.join("");
@@ -1189,6 +5855,7 @@ async fn try_list_videos(
             to_node_name: row.get("to_node_name"),
             strat_name: row.get("strat_name"),
             priority: row.get("priority"),
+            tags: row.get("tags"),
         });
     }
 
@@ -1200,13 +5867,19 @@ async fn try_list_videos(
 
 #[get("/list-videos")]
 async fn list_videos(
+    filters: web::Query<VideoFilters>,
     req: web::Query<ListVideosRequest>,
     app_data: web::Data<AppData>,
 ) -> actix_web::Result<impl Responder> {
-    let out = try_list_videos(&req, &app_data)
-        .await
-        .map_err(|e| actix_web::error::InternalError::new(e, StatusCode::INTERNAL_SERVER_ERROR))?;
-    Ok(web::Json(out))
+    match try_list_videos(&filters, &req, &app_data).await {
+        Ok(out) => Ok(HttpResponse::Ok().json(out)),
+        Err(e) if e.is::<ValidationErrors>() => {
+            Ok(HttpResponse::BadRequest().json(e.downcast_ref::<ValidationErrors>()))
+        }
+        Err(e) => {
+            Err(actix_web::error::InternalError::new(e, StatusCode::INTERNAL_SERVER_ERROR).into())
+        }
+    }
 }
 
 #[derive(Deserialize)]
@@ -1214,7 +5887,7 @@ struct GetVideoRequest {
     video_id: i32,
 }
 
-#[derive(Serialize)]
+#[derive(Serialize, Debug)]
 struct GetVideoResponse {
     num_parts: i32,
     room_id: Option<i32>,
@@ -1232,13 +5905,18 @@ struct GetVideoResponse {
     status: VideoStatus,
     permanent: bool,
     priority: Option<i32>,
+    version: i32,
+    // Reviewer assigned to the video, if any:
+    reviewer_id: Option<i32>,
+    annotations: Vec<VideoAnnotation>,
+    additional_strats: Vec<StratLink>,
+    tags: Vec<i32>,
 }
 
-#[get("/get-video")]
-async fn get_video(
-    req: web::Query<GetVideoRequest>,
-    app_data: web::Data<AppData>,
-) -> actix_web::Result<impl Responder> {
+async fn get_video_info(
+    db: &deadpool_postgres::Client,
+    video_id: i32,
+) -> Result<Option<GetVideoResponse>> {
     let sql = r#"
         SELECT 
             num_parts,
@@ -1256,25 +5934,21 @@ async fn get_video(
             highlight_end_t,
             status,
             permanent,
-            priority
+            priority,
+            version,
+            reviewer_account_id,
+            annotations
         FROM video
         WHERE id = $1
     "#;
-    let db =
-        app_data.db.get().await.map_err(|e| {
-            actix_web::error::InternalError::new(e, StatusCode::INTERNAL_SERVER_ERROR)
-        })?;
-    let stmt = db
-        .prepare_cached(&sql)
-        .await
-        .map_err(|e| actix_web::error::InternalError::new(e, StatusCode::INTERNAL_SERVER_ERROR))?;
-    let row = db
-        .query_one(&stmt, &[&req.video_id])
-        .await
-        .map_err(|e| actix_web::error::InternalError::new(e, StatusCode::NOT_FOUND))?;
+    let stmt = db.prepare_cached(sql).await?;
+    let row = match db.query_opt(&stmt, &[&video_id]).await? {
+        Some(row) => row,
+        None => return Ok(None),
+    };
 
     let status_str: String = row.get("status");
-    let response = GetVideoResponse {
+    Ok(Some(GetVideoResponse {
         num_parts: row.get("num_parts"),
         room_id: row.get("room_id"),
         from_node_id: row.get("from_node_id"),
@@ -1282,7 +5956,7 @@ async fn get_video(
         strat_id: row.get("strat_id"),
         note: row.get("note"),
         dev_note: row.get("dev_note"),
-        status: VideoStatus::try_from(status_str.as_str()).unwrap(),
+        status: VideoStatus::try_from(status_str.as_str())?,
         crop_size: row.get("crop_size"),
         crop_center_x: row.get("crop_center_x"),
         crop_center_y: row.get("crop_center_y"),
@@ -1291,8 +5965,30 @@ async fn get_video(
         highlight_end_t: row.get("highlight_end_t"),
         permanent: row.get("permanent"),
         priority: row.get("priority"),
-    };
-    Ok(web::Json(response))
+        version: row.get("version"),
+        reviewer_id: row.get("reviewer_account_id"),
+        annotations: serde_json::from_value(row.get("annotations"))?,
+        additional_strats: get_additional_strats(db, video_id).await?,
+        tags: get_video_tags(db, video_id).await?,
+    }))
+}
+
+#[get("/get-video")]
+async fn get_video(
+    req: web::Query<GetVideoRequest>,
+    app_data: web::Data<AppData>,
+) -> actix_web::Result<impl Responder> {
+    let db =
+        app_data.db.get().await.map_err(|e| {
+            actix_web::error::InternalError::new(e, StatusCode::INTERNAL_SERVER_ERROR)
+        })?;
+    match get_video_info(&db, req.video_id).await {
+        Ok(Some(response)) if response.status != VideoStatus::Deleted => Ok(web::Json(response)),
+        Ok(_) => Err(actix_web::error::ErrorNotFound("video not found")),
+        Err(e) => {
+            Err(actix_web::error::InternalError::new(e, StatusCode::INTERNAL_SERVER_ERROR).into())
+        }
+    }
 }
 
 #[derive(Serialize)]
@@ -1460,9 +6156,10 @@ async fn try_list_tech(app_data: &AppData) -> Result<Vec<TechListing>> {
         t.tech_id,
         t.name,
         s.difficulty,
-        s.video_id
+        v.id AS video_id
       FROM tech t
       LEFT JOIN tech_setting s ON s.tech_id = t.tech_id
+      LEFT JOIN video v ON v.id = s.video_id AND v.status != 'Deleted'
       ORDER BY tech_id"#,
         )
         .await?;
@@ -1534,21 +6231,17 @@ async fn try_update_tech(app_data: &AppData, tech_update: &TechUpdate) -> Result
 async fn update_tech(
     app_data: web::Data<AppData>,
     tech_updates: web::Json<Vec<TechUpdate>>,
-    auth: BasicAuth,
+    http_req: HttpRequest,
 ) -> impl Responder {
-    let account_info = match authenticate(app_data.clone(), &auth).await {
-        Ok(ai) => ai,
-        Err(e) => {
-            error!("Failed authentication: {}", e);
-            return HttpResponse::Unauthorized().body("Failed authentication");
-        }
-    };
-
-    match account_info.permission {
-        Permission::Editor => {}
-        Permission::Default => {
-            return HttpResponse::Unauthorized().body("Unauthorized");
-        }
+    if let Err(e) = authorize(
+        app_data.clone(),
+        &http_req,
+        TokenScope::Editor,
+        Capability::CurateTech,
+    )
+    .await
+    {
+        return HttpResponse::from(e);
     }
 
     for tech in &tech_updates.0 {
@@ -1580,10 +6273,11 @@ async fn try_list_notables(app_data: &AppData) -> Result<Vec<NotableListing>> {
         n.notable_id,
         n.name,
         s.difficulty,
-        s.video_id
+        v.id AS video_id
       FROM notable n
       LEFT JOIN room r on r.room_id = n.room_id
       LEFT JOIN notable_setting s ON s.room_id = n.room_id AND s.notable_id = n.notable_id
+      LEFT JOIN video v ON v.id = s.video_id AND v.status != 'Deleted'
       ORDER BY r.area_id, room_name, notable_id"#,
         )
         .await?;
@@ -1661,21 +6355,17 @@ async fn try_update_notable(app_data: &AppData, notable_update: &NotableUpdate)
 async fn update_notables(
     app_data: web::Data<AppData>,
     notable_updates: web::Json<Vec<NotableUpdate>>,
-    auth: BasicAuth,
+    http_req: HttpRequest,
 ) -> impl Responder {
-    let account_info = match authenticate(app_data.clone(), &auth).await {
-        Ok(ai) => ai,
-        Err(e) => {
-            error!("Failed authentication: {}", e);
-            return HttpResponse::Unauthorized().body("Failed authentication");
-        }
-    };
-
-    match account_info.permission {
-        Permission::Editor => {}
-        Permission::Default => {
-            return HttpResponse::Unauthorized().body("Unauthorized");
-        }
+    if let Err(e) = authorize(
+        app_data.clone(),
+        &http_req,
+        TokenScope::Editor,
+        Capability::CurateTech,
+    )
+    .await
+    {
+        return HttpResponse::from(e);
     }
 
     for notable in &notable_updates.0 {
@@ -1698,14 +6388,26 @@ async fn try_auto_pick_notable_videos(app_data: &AppData) -> Result<Vec<NotableV
     let stmt = db
         .prepare_cached(
             r#"
-        WITH vids AS (
+        WITH links AS (
+            SELECT id AS video_id, room_id, strat_id, true AS is_primary
+            FROM video
+            WHERE status != 'Deleted' AND room_id IS NOT NULL AND strat_id IS NOT NULL
+            UNION
+            SELECT vs.video_id, vs.room_id, vs.strat_id, vs.is_primary
+            FROM video_strat vs
+            JOIN video v ON v.id = vs.video_id AND v.status != 'Deleted'
+        ),
+        vids AS (
             SELECT 
                 s.room_id,
                 s.notable_id,
-                v.id AS video_id,
-                ROW_NUMBER() OVER(PARTITION BY s.room_id, s.notable_id ORDER BY v.id) AS rn
+                l.video_id,
+                ROW_NUMBER() OVER(
+                    PARTITION BY s.room_id, s.notable_id
+                    ORDER BY l.is_primary DESC, l.video_id
+                ) AS rn
             FROM notable_strat s
-            JOIN video v ON v.room_id = s.room_id AND v.strat_id = s.strat_id
+            JOIN links l ON l.room_id = s.room_id AND l.strat_id = s.strat_id
         )
         SELECT
             room_id,
@@ -1754,18 +6456,49 @@ async fn main() {
 
     let app_data = actix_web::web::Data::new(build_app_data().await);
 
+    let purge_app_data = app_data.clone();
+    actix_web::rt::spawn(async move {
+        loop {
+            if let Err(e) = purge_deleted_videos(&purge_app_data).await {
+                error!("Failed to purge deleted videos: {}", e);
+            }
+            actix_web::rt::time::sleep(PURGE_INTERVAL).await;
+        }
+    });
+
     HttpServer::new(move || {
         App::new()
             .app_data(app_data.clone())
+            .wrap(RateLimit)
             .wrap(Compress::default())
             .wrap(Logger::default())
             .service(home)
             .service(video_html)
             .service(sign_in)
-            .service(upload_video)
+            .service(sign_out)
+            .service(create_api_token)
+            .service(list_api_tokens)
+            .service(revoke_api_token)
+            .service(list_accounts)
+            .service(create_account)
+            .service(update_account)
+            .service(reset_token)
+            .service(regenerate_token)
+            .service(list_audit_log)
+            .service(create_invite_code)
+            .service(list_invite_codes)
+            .service(revoke_invite_code)
+            .service(register)
+            .service(create_upload_session)
+            .service(upload_session_status)
+            .service(upload_part)
+            .service(finalize_upload_session)
             .service(submit_video)
             .service(list_users)
             .service(list_videos)
+            .service(list_tags)
+            .service(update_tag)
+            .service(delete_tag)
             .service(list_rooms_by_area)
             .service(list_nodes)
             .service(list_strats)
@@ -1777,6 +6510,19 @@ async fn main() {
             .service(get_video)
             .service(edit_video)
             .service(delete_video)
+            .service(list_deleted_videos)
+            .service(restore_video)
+            .service(set_video_permanent)
+            .service(video_history)
+            .service(revert_video)
+            .service(assign_reviewer)
+            .service(review_video)
+            .service(video_reviews)
+            .service(list_encoding_jobs)
+            .service(list_video_comments)
+            .service(create_video_comment)
+            .service(edit_video_comment)
+            .service(delete_video_comment)
             .service(download_video)
             .service(actix_files::Files::new("/js", "../js"))
             .service(actix_files::Files::new("/css", "../css"))
@@ -1789,3 +6535,153 @@ async fn main() {
     .await
     .unwrap();
 }
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn frame_info() -> VideoFrameInfo {
+        VideoFrameInfo {
+            num_parts: 1,
+            frame_count: Some(1000),
+            frame_width: Some(256),
+            frame_height: Some(224),
+        }
+    }
+
+    fn controls() -> VideoControls {
+        VideoControls {
+            crop_size: 64,
+            crop_center_x: 128,
+            crop_center_y: 112,
+            thumbnail_t: 10,
+            highlight_start_t: 100,
+            highlight_end_t: 200,
+        }
+    }
+
+    fn error_fields(controls: &VideoControls, frame_info: &VideoFrameInfo) -> Vec<&'static str> {
+        validate_video_controls(controls, frame_info, 300)
+            .iter()
+            .map(|e| e.field)
+            .collect()
+    }
+
+    #[test]
+    fn valid_controls() {
+        assert!(error_fields(&controls(), &frame_info()).is_empty());
+        // The crop box may touch every edge of the frame:
+        let c = VideoControls {
+            crop_size: 224,
+            crop_center_x: 112,
+            crop_center_y: 112,
+            ..controls()
+        };
+        assert!(error_fields(&c, &frame_info()).is_empty());
+        let c = VideoControls {
+            crop_center_x: 256 - 112,
+            ..c
+        };
+        assert!(error_fields(&c, &frame_info()).is_empty());
+    }
+
+    #[test]
+    fn crop_box_out_of_frame() {
+        let c = VideoControls {
+            crop_size: 0,
+            ..controls()
+        };
+        assert_eq!(error_fields(&c, &frame_info()), vec!["crop_size"]);
+        let c = VideoControls {
+            crop_size: 225,
+            ..controls()
+        };
+        assert_eq!(error_fields(&c, &frame_info()), vec!["crop_size"]);
+        let c = VideoControls {
+            crop_center_x: 31,
+            crop_center_y: 31,
+            ..controls()
+        };
+        assert_eq!(
+            error_fields(&c, &frame_info()),
+            vec!["crop_center_x", "crop_center_y"]
+        );
+        let c = VideoControls {
+            crop_center_x: 256 - 31,
+            crop_center_y: 224 - 31,
+            ..controls()
+        };
+        assert_eq!(
+            error_fields(&c, &frame_info()),
+            vec!["crop_center_x", "crop_center_y"]
+        );
+    }
+
+    #[test]
+    fn frames_out_of_range() {
+        let c = VideoControls {
+            thumbnail_t: 1000,
+            highlight_start_t: -1,
+            ..controls()
+        };
+        assert_eq!(
+            error_fields(&c, &frame_info()),
+            vec!["thumbnail_t", "highlight_start_t"]
+        );
+        let c = VideoControls {
+            highlight_start_t: 900,
+            highlight_end_t: 1000,
+            ..controls()
+        };
+        assert_eq!(error_fields(&c, &frame_info()), vec!["highlight_end_t"]);
+    }
+
+    #[test]
+    fn highlight_range() {
+        let c = VideoControls {
+            highlight_start_t: 200,
+            highlight_end_t: 200,
+            ..controls()
+        };
+        assert_eq!(error_fields(&c, &frame_info()), vec!["highlight_end_t"]);
+        let c = VideoControls {
+            highlight_start_t: 100,
+            highlight_end_t: 401,
+            ..controls()
+        };
+        assert_eq!(error_fields(&c, &frame_info()), vec!["highlight_end_t"]);
+        let c = VideoControls {
+            highlight_end_t: 400,
+            ..c
+        };
+        assert!(error_fields(&c, &frame_info()).is_empty());
+    }
+
+    #[test]
+    fn without_frame_metadata() {
+        // Videos uploaded before AVI indexing only get the checks that don't need frame metadata:
+        let legacy = VideoFrameInfo {
+            num_parts: 1,
+            frame_count: None,
+            frame_width: None,
+            frame_height: None,
+        };
+        let c = VideoControls {
+            crop_size: 1000,
+            crop_center_x: 500,
+            crop_center_y: 500,
+            thumbnail_t: 5000,
+            ..controls()
+        };
+        assert!(error_fields(&c, &legacy).is_empty());
+        let c = VideoControls {
+            crop_center_x: 10,
+            highlight_start_t: 300,
+            ..c
+        };
+        assert_eq!(
+            error_fields(&c, &legacy),
+            vec!["crop_center_x", "highlight_end_t"]
+        );
+    }
+}
@@ -154,9 +154,9 @@ fn get_area(room_json: &serde_json::Value) -> String {
     let area = room_json["area"].as_str().unwrap().to_string();
     let sub_area = room_json["subarea"].as_str().unwrap_or("").to_string();
     let sub_sub_area = room_json["subsubarea"].as_str().unwrap_or("").to_string();
-    let full_area = if sub_sub_area != "" {
+    let full_area = if !sub_sub_area.is_empty() {
         format!("{} {} {}", sub_sub_area, sub_area, area)
-    } else if sub_area != "" && sub_area != "Main" {
+    } else if !sub_area.is_empty() && sub_area != "Main" {
         format!("{} {}", sub_area, area)
     } else {
         area
@@ -234,68 +234,66 @@ fn load_sm_data_summary(git_repo: &Repository) -> Result<SMJsonDataSummary> {
 
     let region_pattern =
         git_repo.workdir().unwrap().to_str().unwrap().to_string() + "/region/**/*.json";
-    for entry in glob::glob(&region_pattern).unwrap() {
-        if let Ok(path) = entry {
-            let path_str = path.to_str().unwrap();
-            if path_str.contains("ceres") || path_str.contains("roomDiagrams") {
-                continue;
-            }
+    for path in glob::glob(&region_pattern).unwrap().flatten() {
+        let path_str = path.to_str().unwrap();
+        if path_str.contains("ceres") || path_str.contains("roomDiagrams") {
+            continue;
+        }
 
-            let room_str = fs::read_to_string(path).unwrap();
-            let room_json: serde_json::Value = serde_json::from_str(&room_str).unwrap();
-            let room_id = room_json["id"].as_i64().unwrap() as i32;
-            let area_name = get_area(&room_json);
-            let area_id = area_map[&area_name];
-            rooms.push(RoomData {
+        let room_str = fs::read_to_string(path).unwrap();
+        let room_json: serde_json::Value = serde_json::from_str(&room_str).unwrap();
+        let room_id = room_json["id"].as_i64().unwrap() as i32;
+        let area_name = get_area(&room_json);
+        let area_id = area_map[&area_name];
+        rooms.push(RoomData {
+            room_id,
+            area_id,
+            name: room_json["name"].as_str().unwrap().to_string(),
+        });
+
+        for node_json in room_json["nodes"].as_array().unwrap() {
+            let node_id = node_json["id"].as_i64().unwrap() as i32;
+            let node_name = node_json["name"].as_str().unwrap().to_string();
+            nodes.push(NodeData {
                 room_id,
-                area_id,
-                name: room_json["name"].as_str().unwrap().to_string(),
+                node_id,
+                name: node_name,
             });
+        }
 
-            for node_json in room_json["nodes"].as_array().unwrap() {
-                let node_id = node_json["id"].as_i64().unwrap() as i32;
-                let node_name = node_json["name"].as_str().unwrap().to_string();
-                nodes.push(NodeData {
-                    room_id,
-                    node_id,
-                    name: node_name,
-                });
-            }
+        let mut notable_map: HashMap<String, i32> = HashMap::new();
+
+        for notable_json in room_json["notables"].as_array().unwrap() {
+            let notable_id = notable_json["id"].as_i64().unwrap() as i32;
+            let name = notable_json["name"].as_str().unwrap().to_string();
+            notable_map.insert(name.clone(), notable_id);
+            notables.push(NotableData {
+                room_id,
+                notable_id,
+                name,
+            });
+        }
 
-            let mut notable_map: HashMap<String, i32> = HashMap::new();
-
-            for notable_json in room_json["notables"].as_array().unwrap() {
-                let notable_id = notable_json["id"].as_i64().unwrap() as i32;
-                let name = notable_json["name"].as_str().unwrap().to_string();
-                notable_map.insert(name.clone(), notable_id);
-                notables.push(NotableData {
-                    room_id,
-                    notable_id,
-                    name,
-                });
+        for strat_json in room_json["strats"].as_array().unwrap() {
+            let strat_id = strat_json["id"].as_i64().unwrap_or(0) as i32;
+            if strat_id == 0 {
+                // Skip strats that don't yet have an ID assigned.
+                continue;
             }
+            let link = strat_json["link"].as_array().unwrap();
+            let from_node_id = link[0].as_i64().unwrap() as i32;
+            let to_node_id = link[1].as_i64().unwrap() as i32;
+            let strat_name = strat_json["name"].as_str().unwrap().to_string();
+            strats.push(StratData {
+                room_id,
+                strat_id,
+                from_node_id,
+                to_node_id,
+                name: strat_name,
+            });
 
-            for strat_json in room_json["strats"].as_array().unwrap() {
-                let strat_id = strat_json["id"].as_i64().unwrap_or(0) as i32;
-                if strat_id == 0 {
-                    // Skip strats that don't yet have an ID assigned.
-                    continue;
-                }
-                let link = strat_json["link"].as_array().unwrap();
-                let from_node_id = link[0].as_i64().unwrap() as i32;
-                let to_node_id = link[1].as_i64().unwrap() as i32;
-                let strat_name = strat_json["name"].as_str().unwrap().to_string();
-                strats.push(StratData {
-                    room_id,
-                    strat_id,
-                    from_node_id,
-                    to_node_id,
-                    name: strat_name,
-                });
-
-                for req in strat_json["requires"].as_array().unwrap() {
-                    process_requirement(req, room_id, strat_id, &notable_map, &mut notable_strats);
-                }
+            for req in strat_json["requires"].as_array().unwrap() {
+                process_requirement(req, room_id, strat_id, &notable_map, &mut notable_strats);
             }
         }
     }
@@ -307,7 +305,7 @@ fn load_sm_data_summary(git_repo: &Repository) -> Result<SMJsonDataSummary> {
 
     for tech_category in tech_json["techCategories"].as_array().unwrap() {
         for tech_json in tech_category["techs"].as_array().unwrap() {
-            process_tech_rec(&tech_json, &mut techs)
+            process_tech_rec(tech_json, &mut techs)
                 .with_context(|| format!("Processing tech {:?}", tech_json["name"].as_str()))?;
         }
     }
@@ -506,7 +504,8 @@ async fn update_node_ids(app_data: &AppData) -> Result<()> {
         UPDATE video AS v
         SET
             from_node_id = s.from_node_id,
-            to_node_id = s.to_node_id
+            to_node_id = s.to_node_id,
+            version = v.version + 1
         FROM strat AS s
         WHERE
             v.room_id = s.room_id
@@ -514,7 +513,7 @@ async fn update_node_ids(app_data: &AppData) -> Result<()> {
         AND (v.from_node_id != s.from_node_id
             OR v.to_node_id != s.to_node_id);
     "#;
-    let stmt = db.prepare_cached(&sql).await?;
+    let stmt = db.prepare_cached(sql).await?;
     let cnt = db.execute(&stmt, &[]).await?;
     info!("From/to node IDs updated in {} video(s)", cnt);
     Ok(())
@@ -543,12 +542,14 @@ async fn run_update(app_data: &AppData, fetch_updates: bool) -> Result<()> {
         anyhow::bail!("Update already in progress");
     }
 
-    let git_repo = app_data.git_repository.lock().unwrap();
-    if fetch_updates {
-        update_repo(&git_repo, &app_data.git_branch);
-    }
-    info!("Loading sm-json-data summary");
-    let summary = load_sm_data_summary(&git_repo)?;
+    let summary = {
+        let git_repo = app_data.git_repository.lock().unwrap();
+        if fetch_updates {
+            update_repo(&git_repo, &app_data.git_branch);
+        }
+        info!("Loading sm-json-data summary");
+        load_sm_data_summary(&git_repo)?
+    };
     let result = update_tables(app_data, &summary).await;
 
     app_data.update_in_progress.store(false, Ordering::Release);
@@ -590,7 +591,9 @@ fn build_app_data() -> AppData {
         )
         .unwrap();
 
-    let app_data = AppData {
+    
+
+    AppData {
         git_repository: Mutex::new(create_repo(
             &args.git_repo_url,
             &args.git_repo_branch,
@@ -599,9 +602,7 @@ fn build_app_data() -> AppData {
         git_branch: args.git_repo_branch,
         db: db_pool,
         update_in_progress: AtomicBool::new(false),
-    };
-
-    app_data
+    }
 }
 
 #[actix_web::main]
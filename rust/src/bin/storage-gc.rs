@@ -0,0 +1,472 @@
+use std::collections::{HashMap, HashSet};
+
+use anyhow::Result;
+use clap::Parser;
+use futures::StreamExt;
+use log::{error, info, warn};
+use map_rando_videos::create_object_store;
+use object_store::{path::Path, ObjectMeta, ObjectStore};
+
+// Reconciles object storage against the database: objects under the `avi-xz/`, `png/`, `webp/`
+// and `mp4/` prefixes which no longer belong to any video or active upload are reported (and
+// deleted, unless in dry-run mode), and videos whose expected objects are missing are reported.
+
+#[derive(Parser)]
+struct Args {
+    #[arg(long, env)]
+    postgres_host: String,
+    #[arg(long, env)]
+    postgres_db: String,
+    #[arg(long, env)]
+    postgres_user: String,
+    #[arg(long, env)]
+    postgres_password: String,
+    #[arg(long, env)]
+    video_storage_bucket_url: String,
+    #[arg(long, env)]
+    video_storage_prefix: String,
+    // Only report what would be deleted, without deleting anything:
+    #[arg(long, env)]
+    dry_run: bool,
+    // Objects modified more recently than this are never deleted, to avoid racing with
+    // uploads and encodes which are still in progress:
+    #[arg(long, env, default_value_t = 24)]
+    min_object_age_hours: i64,
+    // Unfinalized upload sessions older than this are considered abandoned:
+    #[arg(long, env, default_value_t = 7)]
+    abandoned_upload_days: i64,
+}
+
+struct AppData {
+    args: Args,
+    db: deadpool_postgres::Pool,
+    video_store: Box<dyn ObjectStore>,
+}
+
+struct VideoData {
+    num_parts: i32,
+    status: String,
+    submitted: bool,
+}
+
+struct UploadSessionData {
+    num_parts: i32,
+    abandoned: bool,
+}
+
+// The kinds of objects stored for each video, by prefix.
+const OBJECT_DIRS: [&str; 4] = ["avi-xz", "png", "webp", "mp4"];
+
+#[derive(Debug, PartialEq, Eq, Hash, Clone, Copy)]
+enum ObjectKey {
+    RawPart { video_id: i32, part_num: i32 },
+    // A part being uploaded, before it is copied into place (left behind if the server stopped
+    // meanwhile):
+    TempPart { video_id: i32, part_num: i32 },
+    Output { dir: &'static str, video_id: i32 },
+    // WebVTT chapters, stored next to the mp4 (only for videos with annotations):
+    Chapters { video_id: i32 },
+}
+
+fn parse_object_key(dir: &'static str, filename: &str) -> Option<ObjectKey> {
+    if let Some(stem) = filename.strip_suffix(".tmp").filter(|_| dir == "avi-xz") {
+        let (video_id, rest) = stem.split_once('-')?;
+        let (part_num, _) = rest.split_once('.')?;
+        Some(ObjectKey::TempPart {
+            video_id: video_id.parse().ok()?,
+            part_num: part_num.parse().ok()?,
+        })
+    } else if dir == "avi-xz" {
+        let (video_id, part_num) = filename.strip_suffix(".avi.xz")?.split_once('-')?;
+        Some(ObjectKey::RawPart {
+            video_id: video_id.parse().ok()?,
+            part_num: part_num.parse().ok()?,
+        })
+    } else if let Some(video_id) = filename.strip_suffix(".vtt").filter(|_| dir == "mp4") {
+        Some(ObjectKey::Chapters {
+            video_id: video_id.parse().ok()?,
+        })
+    } else {
+        let video_id = filename.strip_suffix(&format!(".{}", dir))?;
+        Some(ObjectKey::Output {
+            dir,
+            video_id: video_id.parse().ok()?,
+        })
+    }
+}
+
+async fn build_app_data() -> AppData {
+    let args = Args::parse();
+
+    // Create Postgres connection pool
+    let mut config = deadpool_postgres::Config::new();
+    config.host = Some(args.postgres_host.clone());
+    config.dbname = Some(args.postgres_db.clone());
+    config.user = Some(args.postgres_user.clone());
+    config.password = Some(args.postgres_password.clone());
+    let db_pool = config
+        .create_pool(
+            Some(deadpool_postgres::Runtime::Tokio1),
+            tokio_postgres::NoTls,
+        )
+        .unwrap();
+
+    // Get a test connection, to fail now in case we can't connect to the database.
+    let _ = db_pool.get().await.unwrap();
+
+    AppData {
+        video_store: create_object_store(&args.video_storage_bucket_url),
+        db: db_pool,
+        args,
+    }
+}
+
+async fn load_videos(
+    db: &impl deadpool_postgres::GenericClient,
+) -> Result<HashMap<i32, VideoData>> {
+    let sql = "SELECT id, num_parts, status, crop_size IS NOT NULL AS submitted FROM video";
+    let stmt = db.prepare_cached(sql).await?;
+    let rows = db.query(&stmt, &[]).await?;
+    let mut out = HashMap::new();
+    for row in rows {
+        out.insert(
+            row.get("id"),
+            VideoData {
+                num_parts: row.get("num_parts"),
+                status: row.get("status"),
+                submitted: row.get("submitted"),
+            },
+        );
+    }
+    info!("Loaded {} videos", out.len());
+    Ok(out)
+}
+
+async fn load_upload_sessions(
+    db: &impl deadpool_postgres::GenericClient,
+    app_data: &AppData,
+) -> Result<HashMap<i32, UploadSessionData>> {
+    let sql = r#"
+        SELECT
+            video_id,
+            num_parts,
+            created_ts < current_timestamp - make_interval(days => $1) AS abandoned
+        FROM upload_session
+        WHERE finalized_ts IS NULL
+    "#;
+    let stmt = db.prepare_cached(sql).await?;
+    let abandoned_upload_days = app_data.args.abandoned_upload_days as i32;
+    let rows = db.query(&stmt, &[&abandoned_upload_days]).await?;
+    let mut out = HashMap::new();
+    for row in rows {
+        out.insert(
+            row.get("video_id"),
+            UploadSessionData {
+                num_parts: row.get("num_parts"),
+                abandoned: row.get("abandoned"),
+            },
+        );
+    }
+    info!("Loaded {} unfinalized upload sessions", out.len());
+    Ok(out)
+}
+
+async fn list_objects(app_data: &AppData, dir: &str) -> Result<Vec<ObjectMeta>> {
+    let prefix = Path::parse(format!("{}{}", app_data.args.video_storage_prefix, dir))?;
+    let mut stream = app_data.video_store.list(Some(&prefix));
+    let mut out = vec![];
+    while let Some(meta) = stream.next().await {
+        out.push(meta?);
+    }
+    info!("Listed {} objects under {}", out.len(), prefix);
+    Ok(out)
+}
+
+// Whether the object belongs to an existing video or to an upload which may still be completed.
+fn is_object_referenced(
+    key: ObjectKey,
+    videos: &HashMap<i32, VideoData>,
+    sessions: &HashMap<i32, UploadSessionData>,
+) -> bool {
+    match key {
+        ObjectKey::RawPart { video_id, part_num } => {
+            let in_video = videos
+                .get(&video_id)
+                .is_some_and(|v| part_num < v.num_parts);
+            let in_session = sessions
+                .get(&video_id)
+                .is_some_and(|s| !s.abandoned && part_num < s.num_parts);
+            in_video || in_session
+        }
+        // Once old enough to be deleted, these are no longer being uploaded:
+        ObjectKey::TempPart { .. } => false,
+        ObjectKey::Output { video_id, .. } | ObjectKey::Chapters { video_id } => {
+            videos.contains_key(&video_id)
+        }
+    }
+}
+
+// Sessions are only deleted once none of their parts remain in storage (`remaining_part_video_ids`
+// lists those with parts which weren't deleted, e.g. as too recent), since afterwards nothing would
+// tie those objects to an upload.
+async fn delete_abandoned_sessions(
+    app_data: &AppData,
+    sessions: &HashMap<i32, UploadSessionData>,
+    remaining_part_video_ids: &HashSet<i32>,
+) -> Result<()> {
+    let mut db = app_data.db.get().await?;
+    for (&video_id, session) in sessions {
+        if !session.abandoned {
+            continue;
+        }
+        if remaining_part_video_ids.contains(&video_id) {
+            info!(
+                "Abandoned upload session (keeping until its parts are deleted): video_id={}",
+                video_id
+            );
+            continue;
+        }
+        info!("Abandoned upload session: video_id={}", video_id);
+        if app_data.args.dry_run {
+            continue;
+        }
+        let txn = db.transaction().await?;
+        let sql = "DELETE FROM upload_part WHERE video_id = $1";
+        let stmt = txn.prepare_cached(sql).await?;
+        txn.execute(&stmt, &[&video_id]).await?;
+        let sql = "DELETE FROM upload_session WHERE video_id = $1 AND finalized_ts IS NULL";
+        let stmt = txn.prepare_cached(sql).await?;
+        txn.execute(&stmt, &[&video_id]).await?;
+        txn.commit().await?;
+    }
+    Ok(())
+}
+
+async fn run(app_data: &AppData) -> Result<()> {
+    // Both are loaded from one snapshot, so that an upload finalized in between (its session no
+    // longer unfinalized, but its video not yet seen) can't make its parts look orphaned.
+    let (videos, sessions) = {
+        let mut db = app_data.db.get().await?;
+        let txn = db
+            .build_transaction()
+            .isolation_level(tokio_postgres::IsolationLevel::RepeatableRead)
+            .read_only(true)
+            .start()
+            .await?;
+        let videos = load_videos(&txn).await?;
+        let sessions = load_upload_sessions(&txn, app_data).await?;
+        txn.commit().await?;
+        (videos, sessions)
+    };
+    let min_modified =
+        chrono::Utc::now() - chrono::Duration::hours(app_data.args.min_object_age_hours);
+
+    let mut found_keys: HashSet<ObjectKey> = HashSet::new();
+    let mut remaining_part_video_ids: HashSet<i32> = HashSet::new();
+    let mut orphan_count = 0;
+    let mut orphan_bytes = 0;
+    let mut deleted_count = 0;
+    for dir in OBJECT_DIRS {
+        for meta in list_objects(app_data, dir).await? {
+            let key = meta
+                .location
+                .filename()
+                .and_then(|f| parse_object_key(dir, f));
+            let Some(key) = key else {
+                warn!("Unrecognized object: {}", meta.location);
+                continue;
+            };
+            found_keys.insert(key);
+            if is_object_referenced(key, &videos, &sessions) {
+                continue;
+            }
+            orphan_count += 1;
+            orphan_bytes += meta.size;
+            let deleted = if meta.last_modified > min_modified {
+                info!("Orphaned object (too recent to delete): {}", meta.location);
+                false
+            } else if app_data.args.dry_run {
+                info!("Orphaned object (dry run): {}", meta.location);
+                false
+            } else {
+                info!("Deleting orphaned object: {}", meta.location);
+                match app_data.video_store.delete(&meta.location).await {
+                    Ok(()) => {
+                        deleted_count += 1;
+                        true
+                    }
+                    Err(e) => {
+                        error!("Failed to delete {}: {}", meta.location, e);
+                        false
+                    }
+                }
+            };
+            if let (false, ObjectKey::RawPart { video_id, .. }) = (deleted, key) {
+                remaining_part_video_ids.insert(video_id);
+            }
+        }
+    }
+    info!(
+        "Found {} orphaned objects ({} bytes), deleted {}",
+        orphan_count, orphan_bytes, deleted_count
+    );
+
+    // Raw parts are needed by every video (including deleted ones, which may be restored),
+    // while encoded outputs are only expected once a video has been submitted.
+    let mut video_ids: Vec<&i32> = videos.keys().collect();
+    video_ids.sort();
+    let mut missing_count = 0;
+    for &video_id in video_ids {
+        let video = &videos[&video_id];
+        let mut missing: Vec<String> = vec![];
+        for part_num in 0..video.num_parts {
+            if !found_keys.contains(&ObjectKey::RawPart { video_id, part_num }) {
+                missing.push(format!("avi-xz/{}-{}.avi.xz", video_id, part_num));
+            }
+        }
+        if video.submitted && video.status != "Deleted" {
+            for dir in ["png", "webp", "mp4"] {
+                if !found_keys.contains(&ObjectKey::Output { dir, video_id }) {
+                    missing.push(format!("{}/{}.{}", dir, video_id, dir));
+                }
+            }
+        }
+        if !missing.is_empty() {
+            missing_count += 1;
+            warn!(
+                "Video {} ({}) is missing objects: {}",
+                video_id,
+                video.status,
+                missing.join(", ")
+            );
+        }
+    }
+    info!("Found {} videos with missing objects", missing_count);
+
+    delete_abandoned_sessions(app_data, &sessions, &remaining_part_video_ids).await?;
+    Ok(())
+}
+
+#[tokio::main]
+async fn main() -> Result<()> {
+    env_logger::Builder::from_env(env_logger::Env::default().default_filter_or("info"))
+        .format_timestamp_millis()
+        .init();
+
+    let app_data = build_app_data().await;
+    if app_data.args.dry_run {
+        info!("Dry run: no objects or upload sessions will be deleted");
+    }
+    run(&app_data).await
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn parse_object_keys() {
+        assert_eq!(
+            parse_object_key("avi-xz", "12-3.avi.xz"),
+            Some(ObjectKey::RawPart {
+                video_id: 12,
+                part_num: 3
+            })
+        );
+        assert_eq!(
+            parse_object_key("avi-xz", "12-3.0123456789abcdef.tmp"),
+            Some(ObjectKey::TempPart {
+                video_id: 12,
+                part_num: 3
+            })
+        );
+        assert_eq!(
+            parse_object_key("png", "12.png"),
+            Some(ObjectKey::Output {
+                dir: "png",
+                video_id: 12
+            })
+        );
+        assert_eq!(
+            parse_object_key("mp4", "12.mp4"),
+            Some(ObjectKey::Output {
+                dir: "mp4",
+                video_id: 12
+            })
+        );
+        assert_eq!(
+            parse_object_key("mp4", "12.vtt"),
+            Some(ObjectKey::Chapters { video_id: 12 })
+        );
+    }
+
+    #[test]
+    fn parse_unrecognized_object_keys() {
+        assert_eq!(parse_object_key("avi-xz", "12.avi.xz"), None);
+        assert_eq!(parse_object_key("avi-xz", "a-3.avi.xz"), None);
+        assert_eq!(parse_object_key("avi-xz", "12-3.avi"), None);
+        assert_eq!(parse_object_key("avi-xz", "12-3.tmp"), None);
+        assert_eq!(parse_object_key("png", "12.webp"), None);
+        assert_eq!(parse_object_key("png", "12.vtt"), None);
+        assert_eq!(parse_object_key("webp", "x.webp"), None);
+    }
+
+    fn video(num_parts: i32) -> VideoData {
+        VideoData {
+            num_parts,
+            status: "Approved".to_string(),
+            submitted: true,
+        }
+    }
+
+    fn session(num_parts: i32, abandoned: bool) -> UploadSessionData {
+        UploadSessionData {
+            num_parts,
+            abandoned,
+        }
+    }
+
+    #[test]
+    fn raw_parts_referenced_by_videos_and_active_sessions() {
+        let videos = HashMap::from([(1, video(2))]);
+        let sessions = HashMap::from([(2, session(3, false)), (3, session(3, true))]);
+        let part = |video_id, part_num| ObjectKey::RawPart { video_id, part_num };
+        assert!(is_object_referenced(part(1, 1), &videos, &sessions));
+        assert!(!is_object_referenced(part(1, 2), &videos, &sessions));
+        assert!(is_object_referenced(part(2, 2), &videos, &sessions));
+        assert!(!is_object_referenced(part(2, 3), &videos, &sessions));
+        // Parts of abandoned sessions and unknown videos are orphaned:
+        assert!(!is_object_referenced(part(3, 0), &videos, &sessions));
+        assert!(!is_object_referenced(part(4, 0), &videos, &sessions));
+    }
+
+    #[test]
+    fn outputs_referenced_only_by_videos() {
+        let videos = HashMap::from([(1, video(2))]);
+        let sessions = HashMap::from([(2, session(3, false))]);
+        for video_id in [1, 2] {
+            let referenced = video_id == 1;
+            let output = ObjectKey::Output {
+                dir: "mp4",
+                video_id,
+            };
+            assert_eq!(is_object_referenced(output, &videos, &sessions), referenced);
+            let chapters = ObjectKey::Chapters { video_id };
+            assert_eq!(
+                is_object_referenced(chapters, &videos, &sessions),
+                referenced
+            );
+        }
+    }
+
+    #[test]
+    fn temp_parts_never_referenced() {
+        let videos = HashMap::from([(1, video(2))]);
+        let sessions = HashMap::from([(1, session(2, false))]);
+        let key = ObjectKey::TempPart {
+            video_id: 1,
+            part_num: 0,
+        };
+        assert!(!is_object_referenced(key, &videos, &sessions));
+    }
+}
@@ -2,7 +2,8 @@ use clap::Parser;
 use lapin::{options::QueuePurgeOptions, Channel};
 use log::info;
 use anyhow::Result;
-use map_rando_videos::EncodingTask;
+use deadpool_postgres::GenericClient;
+use map_rando_videos::{encoding_job::create_encoding_job, EncodingTask};
 
 #[derive(Parser)]
 struct Args {
@@ -37,9 +38,15 @@ struct VideoData {
     thumbnail_t: i32,
     highlight_start_t: i32,
     highlight_end_t: i32,
+    has_annotations: bool,
 }
 
-async fn enqueue_video_tasks(channel: &Channel, queue: &str, video: &VideoData) -> Result<()> {
+async fn enqueue_video_tasks(
+    db: &impl GenericClient,
+    channel: &Channel,
+    queue: &str,
+    video: &VideoData,
+) -> Result<()> {
     info!("Processing video {}", video.video_id);
     let props = lapin::BasicProperties::default().with_delivery_mode(2); // persistent delivery
 
@@ -56,7 +63,7 @@ async fn enqueue_video_tasks(channel: &Channel, queue: &str, video: &VideoData)
             "",
             queue,
             lapin::options::BasicPublishOptions::default(),
-            &serde_json::to_vec(&thumbnail_task)?,
+            &serde_json::to_vec(&create_encoding_job(db, thumbnail_task).await?)?,
             props.clone(),
         )
         .await?;
@@ -75,7 +82,7 @@ async fn enqueue_video_tasks(channel: &Channel, queue: &str, video: &VideoData)
             "",
             queue,
             lapin::options::BasicPublishOptions::default(),
-            &serde_json::to_vec(&highlight_task)?,
+            &serde_json::to_vec(&create_encoding_job(db, highlight_task).await?)?,
             props.clone(),
         )
         .await?;
@@ -89,11 +96,26 @@ async fn enqueue_video_tasks(channel: &Channel, queue: &str, video: &VideoData)
             "",
             queue,
             lapin::options::BasicPublishOptions::default(),
-            &serde_json::to_vec(&full_video_task)?,
+            &serde_json::to_vec(&create_encoding_job(db, full_video_task).await?)?,
             props.clone(),
         )
         .await?;
 
+    if video.has_annotations {
+        let chapters_task = EncodingTask::Chapters {
+            video_id: video.video_id,
+        };
+        channel
+            .basic_publish(
+                "",
+                queue,
+                lapin::options::BasicPublishOptions::default(),
+                &serde_json::to_vec(&create_encoding_job(db, chapters_task).await?)?,
+                props.clone(),
+            )
+            .await?;
+    }
+
     Ok(())
 }
 
@@ -117,15 +139,19 @@ async fn build_app_data() -> AppData {
     let _ = db_pool.get().await.unwrap();
 
     // Create RabbitMQ connection pool
-    let mut cfg = deadpool_lapin::Config::default();
-    cfg.url = Some(args.rabbit_url.clone());
+    let cfg = deadpool_lapin::Config {
+        url: Some(args.rabbit_url.clone()),
+        ..Default::default()
+    };
     let mq_pool = cfg
         .create_pool(Some(deadpool_lapin::Runtime::Tokio1))
         .unwrap();
     let mq = mq_pool.get().await.unwrap();
     let channel = mq.create_channel().await.unwrap();
-    let mut opts = lapin::options::QueueDeclareOptions::default();
-    opts.durable = true;
+    let opts = lapin::options::QueueDeclareOptions {
+        durable: true,
+        ..Default::default()
+    };
     channel
         .queue_declare(
             &args.rabbit_queue,
@@ -149,7 +175,7 @@ async fn build_app_data() -> AppData {
 async fn main() -> Result<()> {
     let app_data = build_app_data().await;
 
-    let db = app_data.db.get().await?;
+    let mut db = app_data.db.get().await?;
     let sql = r#"
         SELECT
             id AS video_id,
@@ -159,15 +185,19 @@ async fn main() -> Result<()> {
             crop_center_y,
             thumbnail_t,
             highlight_start_t,
-            highlight_end_t
+            highlight_end_t,
+            annotations != '[]'::jsonb AS has_annotations
         FROM video
-        WHERE crop_size IS NOT NULL
+        WHERE crop_size IS NOT NULL AND status != 'Deleted'
         ORDER BY video_id
        "#;
-    let stmt = db.prepare(&sql).await?;
+    let stmt = db.prepare(sql).await?;
     let row_vec = db.query(&stmt, &[]).await?;
     info!("Retrieved metadata for {} videos", row_vec.len());
  
+    // Messages are only published once the channel transaction is committed, and the jobs are
+    // only recorded after that (so that no job is left Queued without a message).
+    let txn = db.transaction().await?;
     let mq = app_data.mq.get().await?;
     let channel = mq.create_channel().await?;
     channel.tx_select().await?;
@@ -181,10 +211,12 @@ async fn main() -> Result<()> {
             thumbnail_t: row.get("thumbnail_t"),
             highlight_start_t: row.get("highlight_start_t"),
             highlight_end_t:row.get("highlight_end_t"),
+            has_annotations: row.get("has_annotations"),
         };
-        enqueue_video_tasks(&channel, &app_data.args.rabbit_queue, &video).await?;
+        enqueue_video_tasks(&txn, &channel, &app_data.args.rabbit_queue, &video).await?;
     }
     channel.tx_commit().await?;
+    txn.commit().await?;
     info!("Successfully published messages to queue '{}' at {}", app_data.args.rabbit_queue, app_data.args.rabbit_url);
     Ok(())
 }
\ No newline at end of file
@@ -1,15 +1,26 @@
 use std::{
-    fs::File, io::Write, ops::Deref, process::{Command, Stdio}
+    collections::VecDeque, fs::File, io::{BufRead, BufReader, Write}, ops::Deref, path::PathBuf, process::{Child, Command, Stdio}
 };
 
-use anyhow::{anyhow, bail, Result};
+use anyhow::{bail, Result};
 use clap::Parser;
-use futures::{future::join_all, StreamExt};
-use lapin::options::BasicQosOptions;
+use futures::{future::join_all, TryStreamExt};
+use lapin::{options::BasicQosOptions, Channel};
 use log::{info, error};
-use map_rando_videos::{create_object_store, EncodingTask};
+use map_rando_videos::{
+    annotation::{webvtt_chapters, VideoAnnotation, DEFAULT_FRAME_RATE},
+    cache_invalidation::{create_cache_invalidator, CacheInvalidationArgs, CacheInvalidator},
+    create_object_store,
+    encoding_job::{
+        create_encoding_job, dead_letter_queue_name, declare_dead_letter_queue,
+        declare_retry_queue, finish_encoding_job, retry_delay_secs, retry_queue_name,
+        start_encoding_job, EncodingJobMessage, EncodingJobStatus,
+    },
+    object_cache::ObjectCache,
+    EncodingTask,
+};
 use object_store::{path::Path, ObjectStore, PutOptions};
-use tokio::io::{AsyncReadExt, AsyncWriteExt};
+use tokio::{io::{AsyncReadExt, AsyncWriteExt}, net::unix::pipe};
 
 #[derive(Parser)]
 struct Args {
@@ -29,47 +40,49 @@ struct Args {
     video_storage_bucket_url: String,
     #[arg(long, env)]
     ffmpeg_path: String,
+    #[command(flatten)]
+    cache_invalidation: CacheInvalidationArgs,
+    // Identifies this encoder in `encoding_job` rows (by default, the host name and process ID).
+    #[arg(long, env)]
+    worker_id: Option<String>,
+    // Attempts at a task before it is moved to the dead-letter queue:
+    #[arg(long, env, default_value_t = 5)]
+    max_attempts: u32,
+    // Delay before the first retry of a failed task, doubling with each further failure:
+    #[arg(long, env, default_value_t = 30)]
+    retry_base_delay_secs: u64,
+    #[arg(long, env, default_value_t = 3600)]
+    retry_max_delay_secs: u64,
+    // Number of tasks to process at once:
+    #[arg(long, env, default_value_t = 1, value_parser = clap::value_parser!(u16).range(1..))]
+    concurrency: u16,
+    // Directory in which each task gets its own working directory (by default, the system's
+    // temporary directory):
     #[arg(long, env)]
-    bunny_url: Option<String>,
+    work_dir: Option<PathBuf>,
+    // Directory for caching raw video parts, so that the tasks for a video download them only
+    // once. It must not be shared with other encoder processes. By default, a temporary directory
+    // is used (and removed on exit).
     #[arg(long, env)]
-    bunny_api_key: Option<String>,
+    cache_dir: Option<PathBuf>,
+    // Maximum total size of cached raw video parts (0 to disable the cache):
+    #[arg(long, env, default_value_t = 4096)]
+    cache_max_mb: u64,
 }
 
+// Number of lines of ffmpeg's stderr to keep for recording with a failed job.
+const STDERR_TAIL_LINES: usize = 30;
+
 struct AppData {
     args: Args,
+    worker_id: String,
     db: deadpool_postgres::Pool,
     mq: deadpool_lapin::Pool,
     video_store: Box<dyn ObjectStore>,
-    reqwest_client: reqwest::Client,
-}
-
-// Using Bunny-specific API to purge the cache is awkward, but currently 
-// there aren't other more appealing alternatives.
-async fn bunny_purge_file(path: &str, app_data: &AppData) -> Result<()> {
-    if app_data.args.bunny_url.is_none() || app_data.args.bunny_api_key.is_none() {
-        return Ok(());
-    }
-    let params = [
-        ("async", "false".to_string()),
-        ("url", format!("{}/{}", app_data.args.bunny_url.clone().unwrap(), path))
-    ];
-    let encoded_params = serde_urlencoded::to_string(params)?;
-    let req_url = format!("https://api.bunny.net/purge?{encoded_params}");
-    info!("Purging Bunny cache: {}", req_url);
-    let result = 
-        app_data.reqwest_client.get(req_url)
-        .header("AccessKey", app_data.args.bunny_api_key.clone().unwrap())
-        .send().await.map_err(|e| anyhow!("{:?}", e))?;
-    if !result.status().is_success() {
-        let status = result.status();
-        error!("Response body: {}", result.text().await?);
-        if status == 404 {
-            // Ignore 404 errors, as it can be normal in case this is a new file.
-            return Ok(());
-        }
-        bail!("Error purging Bunny cache: {:?}", status);
-    }
-    Ok(())
+    part_cache: ObjectCache,
+    // Holds the default cache directory, removing it when dropped:
+    _temp_cache_dir: Option<tempfile::TempDir>,
+    cache_invalidator: Box<dyn CacheInvalidator>,
 }
 
 async fn build_app_data() -> Result<AppData> {
@@ -89,13 +102,17 @@ async fn build_app_data() -> Result<AppData> {
         .unwrap();
 
     // Create RabbitMQ connection pool
-    let mut cfg = deadpool_lapin::Config::default();
-    cfg.url = Some(args.rabbit_url.clone());
+    let cfg = deadpool_lapin::Config {
+        url: Some(args.rabbit_url.clone()),
+        ..Default::default()
+    };
     let mq_pool = cfg.create_pool(Some(deadpool_lapin::Runtime::Tokio1))?;
     let mq = mq_pool.get().await?;
     let channel = mq.create_channel().await?;
-    let mut opts = lapin::options::QueueDeclareOptions::default();
-    opts.durable = true;
+    let opts = lapin::options::QueueDeclareOptions {
+        durable: true,
+        ..Default::default()
+    };
     channel
         .queue_declare(
             &args.rabbit_queue,
@@ -103,43 +120,114 @@ async fn build_app_data() -> Result<AppData> {
             lapin::types::FieldTable::default(),
         )
         .await?;
+    declare_dead_letter_queue(&channel, &args.rabbit_queue).await?;
+    for failed_attempts in 1..args.max_attempts {
+        let delay_secs = retry_delay_secs(
+            failed_attempts,
+            args.retry_base_delay_secs,
+            args.retry_max_delay_secs,
+        );
+        declare_retry_queue(&channel, &args.rabbit_queue, delay_secs).await?;
+    }
+
+    let worker_id = args.worker_id.clone().unwrap_or_else(|| {
+        let host = std::env::var("HOSTNAME").unwrap_or_else(|_| "video-encoder".to_string());
+        format!("{}-{}", host, std::process::id())
+    });
+    let (cache_dir, temp_cache_dir) = match &args.cache_dir {
+        Some(dir) => (dir.clone(), None),
+        None => {
+            let mut builder = tempfile::Builder::new();
+            builder.prefix("video-encoder-cache-");
+            let dir = match &args.work_dir {
+                Some(parent) => builder.tempdir_in(parent)?,
+                None => builder.tempdir()?,
+            };
+            (dir.path().to_owned(), Some(dir))
+        }
+    };
+    let part_cache = ObjectCache::new(cache_dir, args.cache_max_mb * 1024 * 1024)?;
 
     let app_data = AppData {
+        worker_id,
         db: db_pool,
         mq: mq_pool,
         video_store: create_object_store(&args.video_storage_bucket_url),
-        reqwest_client: reqwest::Client::default(),
+        part_cache,
+        _temp_cache_dir: temp_cache_dir,
+        cache_invalidator: create_cache_invalidator(&args.cache_invalidation)?,
         args,
     };
 
     Ok(app_data)
 }
 
-async fn create_input_pipes(app_data: &AppData, video_id: i32, num_parts: i32) -> Result<()> {
+// Each task gets its own working directory, which is removed (with everything in it) when the
+// returned `TempDir` is dropped, whether or not the task succeeds.
+fn create_work_dir(app_data: &AppData) -> Result<tempfile::TempDir> {
+    let mut builder = tempfile::Builder::new();
+    builder.prefix("video-encoder-");
+    let dir = match &app_data.args.work_dir {
+        Some(parent) => builder.tempdir_in(parent)?,
+        None => builder.tempdir()?,
+    };
+    Ok(dir)
+}
+
+async fn create_input_pipes(
+    app_data: &AppData,
+    work_dir: &std::path::Path,
+    video_id: i32,
+    num_parts: i32,
+) -> Result<()> {
     for part_num in 0..num_parts {
         let object_path = Path::parse(format!("avi-xz/{}-{}.avi.xz", video_id, part_num))?;
-        let compressed_input = app_data.video_store.get(&object_path).await?.bytes().await?;
-        std::fs::write(format!("/tmp/part-{}.avi.xz", part_num), &compressed_input)?;
-        let pipe_path = format!("/tmp/video-{}.pipe", part_num);
+        let part_path = work_dir.join(format!("part-{}.avi.xz", part_num));
+        app_data
+            .part_cache
+            .fetch(app_data.video_store.as_ref(), &object_path, &part_path)
+            .await?;
+        let pipe_path = work_dir.join(format!("video-{}.pipe", part_num));
         let _ = std::fs::remove_file(&pipe_path);
         unix_named_pipe::create(&pipe_path, Some(0o644))?;
     }
 
-    let mut manifest_file = File::create("/tmp/manifest.txt")?;
+    let mut manifest_file = File::create(work_dir.join("manifest.txt"))?;
     for i in 0..num_parts {
-        write!(manifest_file, "file '/tmp/video-{}.pipe'\n", i)?;
+        let pipe_path = work_dir.join(format!("video-{}.pipe", i));
+        writeln!(manifest_file, "file '{}'", pipe_path.display())?;
     }
 
     Ok(())
 }
 
-async fn feed_part_to_pipe(part_num: i32) -> Result<()> {
-    let input_filename = format!("/tmp/part-{}.avi.xz", part_num);
-    let compressed_input = tokio::fs::read(input_filename).await.unwrap();
+// How often to retry opening a pipe until ffmpeg has opened it for reading.
+const PIPE_OPEN_RETRY_INTERVAL: std::time::Duration = std::time::Duration::from_millis(50);
+// Error (errno) from a non-blocking open of a FIFO for writing while it has no reader:
+const ENXIO: i32 = 6;
+
+// Open the pipe for writing once ffmpeg has opened it for reading. A blocking open would wait on a
+// blocking thread, which can't be cancelled if ffmpeg exits without ever opening the pipe, so a
+// non-blocking open (which fails with ENXIO while there is no reader) is retried instead.
+async fn open_pipe_for_writing(pipe_path: &std::path::Path) -> Result<pipe::Sender> {
+    loop {
+        match pipe::OpenOptions::new().open_sender(pipe_path) {
+            Ok(sender) => return Ok(sender),
+            Err(e) if e.raw_os_error() == Some(ENXIO) => {
+                tokio::time::sleep(PIPE_OPEN_RETRY_INTERVAL).await;
+            }
+            Err(e) => return Err(e.into()),
+        }
+    }
+}
+
+async fn feed_part_to_pipe(work_dir: PathBuf, part_num: i32) -> Result<()> {
+    let input_filename = work_dir.join(format!("part-{}.avi.xz", part_num));
+    let compressed_input = tokio::fs::read(input_filename).await?;
     let mut uncompressed_input =
         async_compression::tokio::bufread::XzDecoder::new(compressed_input.deref());
-    let pipe_path = format!("/tmp/video-{}.pipe", part_num);
-    let mut pipe = tokio::fs::OpenOptions::new().write(true).open(pipe_path).await?;
+    let pipe_path = work_dir.join(format!("video-{}.pipe", part_num));
+    let mut pipe = open_pipe_for_writing(&pipe_path).await?;
     let mut buf = vec![0u8; 65536];
     loop {
         let n = uncompressed_input.read(&mut buf).await?;
@@ -155,10 +243,10 @@ async fn feed_part_to_pipe(part_num: i32) -> Result<()> {
     Ok(())
 }
 
-async fn feed_input_to_pipes(num_parts: i32) -> Result<()> {
+async fn feed_input_to_pipes(work_dir: PathBuf, num_parts: i32) -> Result<()> {
     let mut tasks = vec![];
     for part_num in 0..num_parts {
-        tasks.push(feed_part_to_pipe(part_num));
+        tasks.push(feed_part_to_pipe(work_dir.clone(), part_num));
     }
     let res = join_all(tasks).await;
     for task_res in res {
@@ -167,8 +255,55 @@ async fn feed_input_to_pipes(num_parts: i32) -> Result<()> {
     Ok(())
 }
 
+#[derive(Debug)]
+struct FfmpegError {
+    status: std::process::ExitStatus,
+    stderr_tail: String,
+}
+
+impl std::fmt::Display for FfmpegError {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        write!(f, "ffmpeg returned non-zero status ({})", self.status)
+    }
+}
+
+impl std::error::Error for FfmpegError {}
+
+// Feed the video parts to a spawned ffmpeg process and wait for it to finish. Its stderr
+// (which must be piped) is passed through to ours, with the last lines kept in case it fails.
+async fn wait_for_ffmpeg(mut child: Child, work_dir: &std::path::Path, num_parts: i32) -> Result<()> {
+    let stderr = child.stderr.take().expect("ffmpeg stderr not piped");
+    let stderr_thread = std::thread::spawn(move || {
+        let mut tail: VecDeque<String> = VecDeque::new();
+        for line in BufReader::new(stderr).lines() {
+            let Ok(line) = line else {
+                break;
+            };
+            eprintln!("{}", line);
+            if tail.len() == STDERR_TAIL_LINES {
+                tail.pop_front();
+            }
+            tail.push_back(line);
+        }
+        Vec::from(tail).join("\n")
+    });
+
+    let fut = tokio::spawn(feed_input_to_pipes(work_dir.to_owned(), num_parts));
+    // Wait on a blocking thread, so that other tasks (including feeding the input) can proceed.
+    let status = tokio::task::spawn_blocking(move || child.wait()).await??;
+    info!("ffmpeg {}", status);
+    fut.abort();
+    let stderr_tail = stderr_thread.join().unwrap_or_default();
+    if !status.success() {
+        return Err(FfmpegError { status, stderr_tail }.into());
+    }
+    Ok(())
+}
+
+#[allow(clippy::too_many_arguments)]
 async fn encode_thumbnail(
     app_data: &AppData,
+    work_dir: &std::path::Path,
     video_id: i32,
     num_parts: i32,
     crop_center_x: i32,
@@ -176,44 +311,38 @@ async fn encode_thumbnail(
     crop_size: i32,
     frame_number: i32,
 ) -> Result<()> {
-    create_input_pipes(app_data, video_id, num_parts).await?;
+    create_input_pipes(app_data, work_dir, video_id, num_parts).await?;
 
-    let output_path = "/tmp/thumbnail.png";
+    let output_path = work_dir.join("thumbnail.png");
     let crop_x = crop_center_x - crop_size / 2;
     let crop_y = crop_center_y - crop_size / 2;
 
     // Run ffmpeg to extract and crop a single selected frame from the video:
-    let mut child = Command::new(&app_data.args.ffmpeg_path)
+    let child = Command::new(&app_data.args.ffmpeg_path)
         .arg("-y")
         .arg("-safe")
         .arg("0")
         .arg("-f")
         .arg("concat")
         .arg("-i")
-        .arg("/tmp/manifest.txt")
+        .arg(work_dir.join("manifest.txt"))
         .arg("-vf")
-        .arg(&format!(
+        .arg(format!(
             "select=eq(n\\, {frame_number}),crop={crop_size}:{crop_size}:{crop_x}:{crop_y}"
         ))
         .arg("-vframes")
         .arg("1")
-        .arg(output_path)
+        .arg(&output_path)
         .stdin(Stdio::null())
         .stdout(Stdio::inherit())
-        .stderr(Stdio::inherit())
+        .stderr(Stdio::piped())
         .spawn()
         .expect("error spawning ffmpeg");
 
-    let fut = tokio::spawn(feed_input_to_pipes(num_parts));
-    let status = child.wait()?;
-    info!("ffmpeg {}", status);
-    fut.abort();
-    if !status.success() {
-        bail!("ffmpeg returned non-zero status");
-    }
+    wait_for_ffmpeg(child, work_dir, num_parts).await?;
 
     // Write the output thumbnail to object storage:
-    let output_data = std::fs::read(output_path)?;
+    let output_data = std::fs::read(&output_path)?;
     let output_key = format!("png/{}.png", video_id);
     let output_path = object_store::path::Path::parse(output_key.clone())?;
     let mut attrs = object_store::Attributes::new();
@@ -228,20 +357,22 @@ async fn encode_thumbnail(
         .put_opts(&output_path, output_data.into(), put_opts)
         .await?;
 
-    // Invalidate the object cache (Bunny CDN)
-    bunny_purge_file(&output_key, app_data).await?;
+    // Invalidate the object cache (CDN)
+    app_data.cache_invalidator.invalidate(&output_key).await?;
 
     // Update the `thumbnail_processed_ts` in the database:
     let db = app_data.db.get().await?;
     let sql = "UPDATE video SET thumbnail_processed_ts=current_timestamp WHERE id=$1";
-    let stmt = db.prepare_cached(&sql).await?;
+    let stmt = db.prepare_cached(sql).await?;
     db.execute(&stmt, &[&video_id]).await?;
 
     Ok(())
 }
 
+#[allow(clippy::too_many_arguments)]
 async fn encode_highlight(
     app_data: &AppData,
+    work_dir: &std::path::Path,
     video_id: i32,
     num_parts: i32,
     crop_center_x: i32,
@@ -255,23 +386,23 @@ async fn encode_highlight(
         return Ok(())
     }
 
-    create_input_pipes(app_data, video_id, num_parts).await?;
+    create_input_pipes(app_data, work_dir, video_id, num_parts).await?;
 
-    let output_path = "/tmp/highlight.webp";
+    let output_path = work_dir.join("highlight.webp");
     let crop_x = crop_center_x - crop_size / 2;
     let crop_y = crop_center_y - crop_size / 2;
 
     // Run ffmpeg to extract and crop a selected range of frames from the video, cutting the frame rate by a factor of 3:
-    let mut child = Command::new(&app_data.args.ffmpeg_path)
+    let child = Command::new(&app_data.args.ffmpeg_path)
         .arg("-y")
         .arg("-safe")
         .arg("0")
         .arg("-f")
         .arg("concat")
         .arg("-i")
-        .arg("/tmp/manifest.txt")
+        .arg(work_dir.join("manifest.txt"))
         .arg("-vf")
-        .arg(&format!(
+        .arg(format!(
             "select='between(n\\, {start_frame_number}, {end_frame_number})*not(mod(n-{start_frame_number}\\,3))',crop={crop_size}:{crop_size}:{crop_x}:{crop_y}"
         ))
         .arg("-c:v")
@@ -280,23 +411,17 @@ async fn encode_highlight(
         .arg("1")
         .arg("-loop")
         .arg("0")
-        .arg(output_path)
+        .arg(&output_path)
         .stdin(Stdio::null())
         .stdout(Stdio::inherit())
-        .stderr(Stdio::inherit())
+        .stderr(Stdio::piped())
         .spawn()
         .expect("error spawning ffmpeg");
 
-    let fut = tokio::spawn(feed_input_to_pipes(num_parts));
-    let status = child.wait()?;
-    info!("ffmpeg {}", status);
-    fut.abort();
-    if !status.success() {
-        bail!("ffmpeg returned non-zero status");
-    }
+    wait_for_ffmpeg(child, work_dir, num_parts).await?;
     
     // Write the output highlight to object storage:
-    let output_data = std::fs::read(output_path)?;
+    let output_data = std::fs::read(&output_path)?;
     let output_key = format!("webp/{}.webp", video_id);
     let output_path = object_store::path::Path::parse(output_key.clone())?;
     let mut attrs = object_store::Attributes::new();
@@ -311,13 +436,13 @@ async fn encode_highlight(
         .put_opts(&output_path, output_data.into(), put_opts)
         .await?;
 
-    // Invalidate the object cache (Bunny CDN)
-    bunny_purge_file(&output_key, app_data).await?;
+    // Invalidate the object cache (CDN)
+    app_data.cache_invalidator.invalidate(&output_key).await?;
 
     // Update the `highlight_processed_ts` in the database:
     let db = app_data.db.get().await?;
     let sql = "UPDATE video SET highlight_processed_ts=current_timestamp WHERE id=$1";
-    let stmt = db.prepare_cached(&sql).await?;
+    let stmt = db.prepare_cached(sql).await?;
     db.execute(&stmt, &[&video_id]).await?;
 
     Ok(())
@@ -325,23 +450,24 @@ async fn encode_highlight(
 
 async fn encode_full_video(
     app_data: &AppData,
+    work_dir: &std::path::Path,
     video_id: i32,
     num_parts: i32,
 ) -> Result<()> {
-    create_input_pipes(app_data, video_id, num_parts).await?;
+    create_input_pipes(app_data, work_dir, video_id, num_parts).await?;
 
-    let output_path = "/tmp/full_video.mp4";
+    let output_path = work_dir.join("full_video.mp4");
 
     // Run ffmpeg to encode the video into an mp4. For best compatibility, we use yuv420p pixel format;
     // this subsamples the chroma, which we counteract by upscaling the video resolution by 2x.
-    let mut child = Command::new(&app_data.args.ffmpeg_path)
+    let child = Command::new(&app_data.args.ffmpeg_path)
         .arg("-y")
         .arg("-safe")
         .arg("0")
         .arg("-f")
         .arg("concat")
         .arg("-i")
-        .arg("/tmp/manifest.txt")
+        .arg(work_dir.join("manifest.txt"))
         .arg("-vf")
         .arg("scale=512:-1:flags=neighbor")
         .arg("-pix_fmt")
@@ -350,27 +476,17 @@ async fn encode_full_video(
         .arg("veryslow")
         .arg("-crf")
         .arg("23")
-        .arg(output_path)
+        .arg(&output_path)
         .stdin(Stdio::null())
         .stdout(Stdio::inherit())
-        .stderr(Stdio::inherit())
+        .stderr(Stdio::piped())
         .spawn()
         .expect("error spawning ffmpeg");
 
-    let fut = tokio::spawn(feed_input_to_pipes(num_parts));
-    let status = child.wait()?;
-    info!("ffmpeg {}", status);
-    fut.abort();
-    if !status.success() {
-        bail!("ffmpeg returned non-zero status");
-    }
-    
-    if !status.success() {
-        bail!("ffmpeg returned non-zero status");
-    }
+    wait_for_ffmpeg(child, work_dir, num_parts).await?;
 
     // Write the output mp4 to object storage:
-    let output_data = std::fs::read(output_path)?;
+    let output_data = std::fs::read(&output_path)?;
     let output_key = format!("mp4/{}.mp4", video_id);
     let output_path = object_store::path::Path::parse(output_key.clone())?;
     let mut attrs = object_store::Attributes::new();
@@ -385,21 +501,53 @@ async fn encode_full_video(
         .put_opts(&output_path, output_data.into(), put_opts)
         .await?;
 
-    // Invalidate the object cache (Bunny CDN)
-    bunny_purge_file(&output_key, app_data).await?;
+    // Invalidate the object cache (CDN)
+    app_data.cache_invalidator.invalidate(&output_key).await?;
 
     // Update the `full_video_processed_ts` in the database:
     let db = app_data.db.get().await?;
     let sql = "UPDATE video SET full_video_processed_ts=current_timestamp WHERE id=$1";
-    let stmt = db.prepare_cached(&sql).await?;
+    let stmt = db.prepare_cached(sql).await?;
     db.execute(&stmt, &[&video_id]).await?;
 
     Ok(())
 }
 
+async fn write_chapters(app_data: &AppData, video_id: i32) -> Result<()> {
+    let db = app_data.db.get().await?;
+    let sql = "SELECT frame_rate, annotations FROM video WHERE id=$1";
+    let stmt = db.prepare_cached(sql).await?;
+    let row = db.query_one(&stmt, &[&video_id]).await?;
+    let frame_rate: Option<f64> = row.get("frame_rate");
+    let annotations: Vec<VideoAnnotation> = serde_json::from_value(row.get("annotations"))?;
+    let output_data = webvtt_chapters(&annotations, frame_rate.unwrap_or(DEFAULT_FRAME_RATE));
+
+    // Write the chapters file to object storage, next to the mp4:
+    let output_key = format!("mp4/{}.vtt", video_id);
+    let output_path = object_store::path::Path::parse(output_key.clone())?;
+    let mut attrs = object_store::Attributes::new();
+    attrs.insert(object_store::Attribute::ContentType, "text/vtt".into());
+    let put_opts = PutOptions {
+        mode: object_store::PutMode::Overwrite,
+        tags: object_store::TagSet::default(),
+        attributes: attrs,
+    };
+    app_data
+        .video_store
+        .put_opts(&output_path, output_data.into_bytes().into(), put_opts)
+        .await?;
+    info!("Wrote {} ({} chapters)", output_key, annotations.len());
+
+    // Invalidate the object cache (CDN)
+    app_data.cache_invalidator.invalidate(&output_key).await?;
+    Ok(())
+}
+
 async fn process_task(task: &EncodingTask, app_data: &AppData) -> Result<()> {
-    match task {
-        &EncodingTask::ThumbnailImage {
+    let work_dir = create_work_dir(app_data)?;
+    let work_dir = work_dir.path();
+    match *task {
+        EncodingTask::ThumbnailImage {
             video_id,
             num_parts,
             crop_center_x,
@@ -408,7 +556,8 @@ async fn process_task(task: &EncodingTask, app_data: &AppData) -> Result<()> {
             frame_number,
         } => {
             encode_thumbnail(
-                &app_data,
+                app_data,
+                work_dir,
                 video_id,
                 num_parts,
                 crop_center_x,
@@ -418,7 +567,7 @@ async fn process_task(task: &EncodingTask, app_data: &AppData) -> Result<()> {
             )
             .await?;
         }
-        &EncodingTask::HighlightAnimation {
+        EncodingTask::HighlightAnimation {
             video_id,
             num_parts,
             crop_center_x,
@@ -429,6 +578,7 @@ async fn process_task(task: &EncodingTask, app_data: &AppData) -> Result<()> {
         } => {
             encode_highlight(
                 app_data,
+                work_dir,
                 video_id,
                 num_parts,
                 crop_center_x,
@@ -439,9 +589,116 @@ async fn process_task(task: &EncodingTask, app_data: &AppData) -> Result<()> {
             )
             .await?;
         }
-        &EncodingTask::FullVideo { video_id, num_parts } => {
-            encode_full_video(app_data, video_id, num_parts).await?;
+        EncodingTask::FullVideo { video_id, num_parts } => {
+            encode_full_video(app_data, work_dir, video_id, num_parts).await?;
+        }
+        EncodingTask::Chapters { video_id } => {
+            write_chapters(app_data, video_id).await?;
+        }
+    }
+    Ok(())
+}
+
+// Run the task, recording its progress and outcome in its `encoding_job` row.
+async fn process_job(msg: &EncodingJobMessage, job_id: Option<i32>, app_data: &AppData) -> Result<()> {
+    if let Some(job_id) = job_id {
+        let db = app_data.db.get().await?;
+        start_encoding_job(&db, job_id, &app_data.worker_id).await?;
+    }
+    process_task(&msg.task, app_data).await?;
+    if let Some(job_id) = job_id {
+        info!("Encoding job {} succeeded", job_id);
+        let db = app_data.db.get().await?;
+        finish_encoding_job(&db, job_id, EncodingJobStatus::Succeeded, None, None).await?;
+    }
+    Ok(())
+}
+
+async fn publish_message(channel: &Channel, queue: &str, data: &[u8]) -> Result<()> {
+    let props = lapin::BasicProperties::default().with_delivery_mode(2); // persistent delivery
+    let confirmation = channel
+        .basic_publish(
+            "",
+            queue,
+            lapin::options::BasicPublishOptions::default(),
+            data,
+            props,
+        )
+        .await?
+        .await?;
+    if !confirmation.is_ack() {
+        bail!("Publishing to queue '{}' was not acknowledged", queue);
+    }
+    Ok(())
+}
+
+async fn record_job_failure(
+    app_data: &AppData,
+    job_id: Option<i32>,
+    status: EncodingJobStatus,
+    err: &anyhow::Error,
+) {
+    let Some(job_id) = job_id else {
+        return;
+    };
+    let stderr_tail = err.downcast_ref::<FfmpegError>().map(|e| e.stderr_tail.as_str());
+    let res = match app_data.db.get().await {
+        Ok(db) => {
+            finish_encoding_job(&db, job_id, status, Some(&format!("{:#}", err)), stderr_tail)
+                .await
+        }
+        Err(e) => Err(e.into()),
+    };
+    if let Err(e) = res {
+        error!("Failed to record failure of encoding job {}: {:?}", job_id, e);
+    }
+}
+
+// Handle a message from the encoding queue. A failed task is re-published to a retry queue (or,
+// once its attempts are exhausted, to the dead-letter queue), so that the message can be acked
+// either way and a task which keeps failing doesn't block the queue or crash the encoder.
+async fn handle_message(data: &[u8], channel: &Channel, app_data: &AppData) -> Result<()> {
+    let queue = &app_data.args.rabbit_queue;
+    let mut msg: EncodingJobMessage = match serde_json::from_slice(data) {
+        Ok(msg) => msg,
+        Err(e) => {
+            error!("Moving unparseable message to dead-letter queue: {}", e);
+            return publish_message(channel, &dead_letter_queue_name(queue), data).await;
         }
+    };
+    if msg.job_id.is_none() {
+        // Published before jobs were recorded:
+        let db = app_data.db.get().await?;
+        msg.job_id = create_encoding_job(&db, msg.task.clone()).await?.job_id;
+    }
+    let job_id = msg.job_id;
+
+    let Err(err) = process_job(&msg, job_id, app_data).await else {
+        return Ok(());
+    };
+    msg.failed_attempts += 1;
+    msg.last_error = Some(format!("{:#}", err));
+    if msg.failed_attempts < app_data.args.max_attempts {
+        let delay_secs = retry_delay_secs(
+            msg.failed_attempts,
+            app_data.args.retry_base_delay_secs,
+            app_data.args.retry_max_delay_secs,
+        );
+        error!(
+            "Encoding job {:?} failed (attempt {} of {}), retrying in {}s: {:?}",
+            job_id, msg.failed_attempts, app_data.args.max_attempts, delay_secs, err
+        );
+        record_job_failure(app_data, job_id, EncodingJobStatus::Queued, &err).await;
+        let retry_queue = retry_queue_name(queue, delay_secs);
+        publish_message(channel, &retry_queue, &serde_json::to_vec(&msg)?).await?;
+    } else {
+        error!(
+            "Encoding job {:?} failed (attempt {} of {}), moving to dead-letter queue: {:?}",
+            job_id, msg.failed_attempts, app_data.args.max_attempts, err
+        );
+        record_job_failure(app_data, job_id, EncodingJobStatus::Failed, &err).await;
+        let dead_letter_queue = dead_letter_queue_name(queue);
+        publish_message(channel, &dead_letter_queue, &serde_json::to_vec(&msg)?).await?;
     }
     Ok(())
 }
@@ -456,9 +713,12 @@ async fn main() -> Result<()> {
     let app_data = build_app_data().await?;
     let mq = app_data.mq.get().await?;
     let channel = mq.create_channel().await?;
+    channel
+        .confirm_select(lapin::options::ConfirmSelectOptions::default())
+        .await?;
     let opts = lapin::options::BasicConsumeOptions::default();
-    channel.basic_qos(1, BasicQosOptions::default()).await?;
-    let mut consumer = channel
+    channel.basic_qos(args.concurrency, BasicQosOptions::default()).await?;
+    let consumer = channel
         .basic_consume(
             &args.rabbit_queue,
             "video-encoder",
@@ -466,18 +726,26 @@ async fn main() -> Result<()> {
             lapin::types::FieldTable::default(),
         )
         .await?;
-    info!("Waiting for messages");
-    while let Some(delivery) = consumer.next().await {
-        let delivery = delivery?;
-        info!(
-            "Consuming message: {}",
-            String::from_utf8(delivery.data.clone())?
-        );
-        let task: EncodingTask = serde_json::from_slice(&delivery.data)?;
-        process_task(&task, &app_data).await?;
-        delivery
-        .ack(lapin::options::BasicAckOptions::default())
+    info!("Waiting for messages (concurrency {})", args.concurrency);
+    consumer
+        .map_err(anyhow::Error::from)
+        .try_for_each_concurrent(args.concurrency as usize, |delivery| {
+            let channel = &channel;
+            let app_data = &app_data;
+            async move {
+                info!(
+                    "Consuming message: {}",
+                    String::from_utf8_lossy(&delivery.data)
+                );
+                // Errors here (e.g. failing to re-publish a failed task) leave the message unacked,
+                // so it is redelivered when the encoder restarts.
+                handle_message(&delivery.data, channel, app_data).await?;
+                delivery
+                    .ack(lapin::options::BasicAckOptions::default())
+                    .await?;
+                Ok(())
+            }
+        })
         .await?;
-    }
     bail!("Consumer unexpectedly finished");
 }
@@ -0,0 +1,520 @@
+use std::sync::{Arc, Mutex};
+
+use anyhow::{anyhow, bail, Context, Result};
+use async_trait::async_trait;
+use log::{error, info};
+use object_store::aws::{AwsAuthorizer, AwsCredential};
+
+// Invalidation of CDN-cached copies of objects which have been overwritten or deleted in object
+// storage. Paths are relative to the root of the video storage bucket, e.g. "png/123.png".
+
+#[async_trait]
+pub trait CacheInvalidator: Send + Sync {
+    async fn invalidate(&self, path: &str) -> Result<()>;
+}
+
+#[derive(clap::ValueEnum, Clone, Copy, Debug)]
+pub enum CacheInvalidatorKind {
+    None,
+    // Logs and records the invalidated paths, without contacting a CDN:
+    Recording,
+    Bunny,
+    #[value(name = "cloudfront")]
+    CloudFront,
+    Cloudflare,
+    // POSTs each invalidated path as JSON to a configured URL:
+    Webhook,
+}
+
+#[derive(clap::Args)]
+pub struct CacheInvalidationArgs {
+    // If not given, Bunny is used if `bunny_url` and `bunny_api_key` are set, and otherwise none:
+    #[arg(long, env, value_enum)]
+    cdn_invalidator: Option<CacheInvalidatorKind>,
+    // Public base URL of the CDN (for Cloudflare and webhook invalidation):
+    #[arg(long, env)]
+    cdn_url: Option<String>,
+    // Overrides the base URL of the CDN provider's API, e.g. to use a local stand-in:
+    #[arg(long, env)]
+    cdn_api_url: Option<String>,
+    #[arg(long, env)]
+    bunny_url: Option<String>,
+    #[arg(long, env)]
+    bunny_api_key: Option<String>,
+    // CloudFront uses the AWS credentials from the environment (AWS_ACCESS_KEY_ID, etc.):
+    #[arg(long, env)]
+    cloudfront_distribution_id: Option<String>,
+    #[arg(long, env)]
+    cloudflare_zone_id: Option<String>,
+    #[arg(long, env)]
+    cloudflare_api_token: Option<String>,
+    #[arg(long, env)]
+    purge_webhook_url: Option<String>,
+    // Sent as a bearer token with webhook requests, if set:
+    #[arg(long, env)]
+    purge_webhook_token: Option<String>,
+}
+
+fn required<'a>(value: &'a Option<String>, name: &str) -> Result<&'a str> {
+    value
+        .as_deref()
+        .with_context(|| format!("{} is required for the selected CDN invalidator", name))
+}
+
+pub fn create_cache_invalidator(args: &CacheInvalidationArgs) -> Result<Box<dyn CacheInvalidator>> {
+    let kind = args.cdn_invalidator.unwrap_or(
+        if args.bunny_url.is_some() && args.bunny_api_key.is_some() {
+            CacheInvalidatorKind::Bunny
+        } else {
+            CacheInvalidatorKind::None
+        },
+    );
+    let client = reqwest::Client::default();
+    let api_url = |default: &str| {
+        args.cdn_api_url
+            .as_deref()
+            .unwrap_or(default)
+            .trim_end_matches('/')
+            .to_string()
+    };
+    let invalidator: Box<dyn CacheInvalidator> = match kind {
+        CacheInvalidatorKind::None => Box::new(NoopInvalidator),
+        CacheInvalidatorKind::Recording => Box::<RecordingInvalidator>::default(),
+        CacheInvalidatorKind::Bunny => Box::new(BunnyInvalidator {
+            client,
+            api_url: api_url("https://api.bunny.net"),
+            cdn_url: required(&args.bunny_url, "bunny_url")?.to_string(),
+            api_key: required(&args.bunny_api_key, "bunny_api_key")?.to_string(),
+        }),
+        CacheInvalidatorKind::CloudFront => {
+            let env = |name: &str| std::env::var(name).ok();
+            Box::new(CloudFrontInvalidator {
+                client,
+                api_url: api_url("https://cloudfront.amazonaws.com"),
+                distribution_id: required(
+                    &args.cloudfront_distribution_id,
+                    "cloudfront_distribution_id",
+                )?
+                .to_string(),
+                credential: AwsCredential {
+                    key_id: required(&env("AWS_ACCESS_KEY_ID"), "AWS_ACCESS_KEY_ID")?.to_string(),
+                    secret_key: required(&env("AWS_SECRET_ACCESS_KEY"), "AWS_SECRET_ACCESS_KEY")?
+                        .to_string(),
+                    token: env("AWS_SESSION_TOKEN"),
+                },
+            })
+        }
+        CacheInvalidatorKind::Cloudflare => Box::new(CloudflareInvalidator {
+            client,
+            api_url: api_url("https://api.cloudflare.com/client/v4"),
+            cdn_url: required(&args.cdn_url, "cdn_url")?.to_string(),
+            zone_id: required(&args.cloudflare_zone_id, "cloudflare_zone_id")?.to_string(),
+            api_token: required(&args.cloudflare_api_token, "cloudflare_api_token")?.to_string(),
+        }),
+        CacheInvalidatorKind::Webhook => Box::new(WebhookInvalidator {
+            client,
+            webhook_url: required(&args.purge_webhook_url, "purge_webhook_url")?.to_string(),
+            token: args.purge_webhook_token.clone(),
+            cdn_url: args.cdn_url.clone(),
+        }),
+    };
+    info!("Using CDN cache invalidator: {:?}", kind);
+    Ok(invalidator)
+}
+
+async fn check_response(result: reqwest::Response, provider: &str) -> Result<()> {
+    let status = result.status();
+    if !status.is_success() {
+        error!("Response body: {}", result.text().await?);
+        bail!("Error purging {} cache: {:?}", provider, status);
+    }
+    Ok(())
+}
+
+pub struct NoopInvalidator;
+
+#[async_trait]
+impl CacheInvalidator for NoopInvalidator {
+    async fn invalidate(&self, _path: &str) -> Result<()> {
+        Ok(())
+    }
+}
+
+// Clones share the recorded paths, so a clone can be kept to observe the paths invalidated through
+// another (e.g. once boxed as a `dyn CacheInvalidator`).
+#[derive(Default, Clone)]
+pub struct RecordingInvalidator {
+    paths: Arc<Mutex<Vec<String>>>,
+}
+
+impl RecordingInvalidator {
+    // Paths invalidated so far, in order.
+    pub fn paths(&self) -> Vec<String> {
+        self.paths.lock().unwrap().clone()
+    }
+}
+
+#[async_trait]
+impl CacheInvalidator for RecordingInvalidator {
+    async fn invalidate(&self, path: &str) -> Result<()> {
+        info!("Invalidating CDN cache (recorded only): {}", path);
+        self.paths.lock().unwrap().push(path.to_string());
+        Ok(())
+    }
+}
+
+pub struct BunnyInvalidator {
+    client: reqwest::Client,
+    api_url: String,
+    cdn_url: String,
+    api_key: String,
+}
+
+#[async_trait]
+impl CacheInvalidator for BunnyInvalidator {
+    async fn invalidate(&self, path: &str) -> Result<()> {
+        let params = [
+            ("async", "false".to_string()),
+            ("url", format!("{}/{}", self.cdn_url, path)),
+        ];
+        let encoded_params = serde_urlencoded::to_string(params)?;
+        let req_url = format!("{}/purge?{}", self.api_url, encoded_params);
+        info!("Purging Bunny cache: {}", req_url);
+        let result = self
+            .client
+            .get(req_url)
+            .header("AccessKey", &self.api_key)
+            .send()
+            .await
+            .map_err(|e| anyhow!("{:?}", e))?;
+        if result.status() == 404 {
+            // Ignore 404 errors, as it can be normal in case this is a new file.
+            return Ok(());
+        }
+        check_response(result, "Bunny").await
+    }
+}
+
+fn xml_escape(s: &str) -> String {
+    s.replace('&', "&amp;")
+        .replace('<', "&lt;")
+        .replace('>', "&gt;")
+}
+
+pub struct CloudFrontInvalidator {
+    client: reqwest::Client,
+    api_url: String,
+    distribution_id: String,
+    credential: AwsCredential,
+}
+
+#[async_trait]
+impl CacheInvalidator for CloudFrontInvalidator {
+    async fn invalidate(&self, path: &str) -> Result<()> {
+        let req_url = format!(
+            "{}/2020-05-31/distribution/{}/invalidation",
+            self.api_url, self.distribution_id
+        );
+        // CloudFront rejects a repeated caller reference with a different batch, so it must be
+        // unique per invalidation:
+        let caller_reference = format!(
+            "{}-{}",
+            chrono::Utc::now().timestamp_nanos_opt().unwrap_or_default(),
+            path
+        );
+        let body = format!(
+            r#"<?xml version="1.0" encoding="UTF-8"?><InvalidationBatch xmlns="http://cloudfront.amazonaws.com/doc/2020-05-31/"><Paths><Quantity>1</Quantity><Items><Path>/{}</Path></Items></Paths><CallerReference>{}</CallerReference></InvalidationBatch>"#,
+            xml_escape(path),
+            xml_escape(&caller_reference)
+        );
+        info!("Creating CloudFront invalidation: {}", path);
+        let mut req = self
+            .client
+            .post(req_url)
+            .header("Content-Type", "text/xml")
+            .body(body)
+            .build()?;
+        // CloudFront is a global service, signed for us-east-1:
+        AwsAuthorizer::new(&self.credential, "cloudfront", "us-east-1").authorize(&mut req, None);
+        let result = self
+            .client
+            .execute(req)
+            .await
+            .map_err(|e| anyhow!("{:?}", e))?;
+        check_response(result, "CloudFront").await
+    }
+}
+
+pub struct CloudflareInvalidator {
+    client: reqwest::Client,
+    api_url: String,
+    cdn_url: String,
+    zone_id: String,
+    api_token: String,
+}
+
+#[async_trait]
+impl CacheInvalidator for CloudflareInvalidator {
+    async fn invalidate(&self, path: &str) -> Result<()> {
+        let req_url = format!("{}/zones/{}/purge_cache", self.api_url, self.zone_id);
+        let file_url = format!("{}/{}", self.cdn_url, path);
+        info!("Purging Cloudflare cache: {}", file_url);
+        let body = serde_json::json!({ "files": [file_url] });
+        let result = self
+            .client
+            .post(req_url)
+            .bearer_auth(&self.api_token)
+            .header("Content-Type", "application/json")
+            .body(serde_json::to_vec(&body)?)
+            .send()
+            .await
+            .map_err(|e| anyhow!("{:?}", e))?;
+        check_response(result, "Cloudflare").await
+    }
+}
+
+pub struct WebhookInvalidator {
+    client: reqwest::Client,
+    webhook_url: String,
+    token: Option<String>,
+    cdn_url: Option<String>,
+}
+
+#[async_trait]
+impl CacheInvalidator for WebhookInvalidator {
+    async fn invalidate(&self, path: &str) -> Result<()> {
+        let url = self
+            .cdn_url
+            .as_ref()
+            .map(|cdn_url| format!("{}/{}", cdn_url, path));
+        let body = serde_json::json!({ "path": path, "url": url });
+        info!("Calling purge webhook: {}", path);
+        let mut req = self
+            .client
+            .post(&self.webhook_url)
+            .header("Content-Type", "application/json")
+            .body(serde_json::to_vec(&body)?);
+        if let Some(token) = &self.token {
+            req = req.bearer_auth(token);
+        }
+        let result = req.send().await.map_err(|e| anyhow!("{:?}", e))?;
+        check_response(result, "webhook").await
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use tokio::io::{AsyncReadExt, AsyncWriteExt};
+
+    struct ReceivedRequest {
+        // e.g. "GET /purge?... HTTP/1.1":
+        request_line: String,
+        // Lowercase names:
+        headers: Vec<(String, String)>,
+        body: Vec<u8>,
+    }
+
+    impl ReceivedRequest {
+        fn header(&self, name: &str) -> Option<&str> {
+            self.headers
+                .iter()
+                .find(|(n, _)| n == name)
+                .map(|(_, v)| v.as_str())
+        }
+
+        fn json_body(&self) -> serde_json::Value {
+            serde_json::from_slice(&self.body).unwrap()
+        }
+    }
+
+    // Start a local HTTP server which accepts a single request, responding with the given status,
+    // and returns the request. Also returns the server's base URL.
+    async fn serve_one(status: u16) -> (String, tokio::task::JoinHandle<ReceivedRequest>) {
+        let listener = tokio::net::TcpListener::bind("127.0.0.1:0").await.unwrap();
+        let url = format!("http://{}", listener.local_addr().unwrap());
+        let handle = tokio::spawn(async move {
+            let (mut stream, _) = listener.accept().await.unwrap();
+            let mut data = vec![];
+            let header_end = loop {
+                let mut buf = [0u8; 4096];
+                let n = stream.read(&mut buf).await.unwrap();
+                assert!(n > 0, "connection closed before end of headers");
+                data.extend_from_slice(&buf[..n]);
+                if let Some(i) = data.windows(4).position(|w| w == b"\r\n\r\n") {
+                    break i;
+                }
+            };
+            let head = String::from_utf8(data[..header_end].to_vec()).unwrap();
+            let mut lines = head.split("\r\n");
+            let request_line = lines.next().unwrap().to_string();
+            let headers: Vec<(String, String)> = lines
+                .map(|line| {
+                    let (name, value) = line.split_once(':').unwrap();
+                    (name.trim().to_lowercase(), value.trim().to_string())
+                })
+                .collect();
+            let content_length: usize = headers
+                .iter()
+                .find(|(n, _)| n == "content-length")
+                .map_or(0, |(_, v)| v.parse().unwrap());
+            let mut body = data[header_end + 4..].to_vec();
+            while body.len() < content_length {
+                let mut buf = [0u8; 4096];
+                let n = stream.read(&mut buf).await.unwrap();
+                assert!(n > 0, "connection closed before end of body");
+                body.extend_from_slice(&buf[..n]);
+            }
+            let response = format!(
+                "HTTP/1.1 {} Test\r\nContent-Length: 0\r\nConnection: close\r\n\r\n",
+                status
+            );
+            stream.write_all(response.as_bytes()).await.unwrap();
+            ReceivedRequest {
+                request_line,
+                headers,
+                body,
+            }
+        });
+        (url, handle)
+    }
+
+    fn no_args() -> CacheInvalidationArgs {
+        CacheInvalidationArgs {
+            cdn_invalidator: None,
+            cdn_url: None,
+            cdn_api_url: None,
+            bunny_url: None,
+            bunny_api_key: None,
+            cloudfront_distribution_id: None,
+            cloudflare_zone_id: None,
+            cloudflare_api_token: None,
+            purge_webhook_url: None,
+            purge_webhook_token: None,
+        }
+    }
+
+    fn bunny_args(api_url: String) -> CacheInvalidationArgs {
+        // Bunny is used by default when its settings are given:
+        CacheInvalidationArgs {
+            cdn_api_url: Some(api_url),
+            bunny_url: Some("https://videos.b-cdn.net".to_string()),
+            bunny_api_key: Some("bunny-key".to_string()),
+            ..no_args()
+        }
+    }
+
+    #[tokio::test]
+    async fn bunny_purges_url() {
+        let (url, server) = serve_one(200).await;
+        let invalidator = create_cache_invalidator(&bunny_args(url)).unwrap();
+        invalidator.invalidate("png/1.png").await.unwrap();
+        let req = server.await.unwrap();
+        assert_eq!(
+            req.request_line,
+            "GET /purge?async=false&url=https%3A%2F%2Fvideos.b-cdn.net%2Fpng%2F1.png HTTP/1.1"
+        );
+        assert_eq!(req.header("accesskey"), Some("bunny-key"));
+    }
+
+    #[tokio::test]
+    async fn bunny_ignores_not_found() {
+        let (url, server) = serve_one(404).await;
+        let invalidator = create_cache_invalidator(&bunny_args(url)).unwrap();
+        invalidator.invalidate("png/1.png").await.unwrap();
+        server.await.unwrap();
+    }
+
+    #[tokio::test]
+    async fn bunny_error() {
+        let (url, server) = serve_one(500).await;
+        let invalidator = create_cache_invalidator(&bunny_args(url)).unwrap();
+        assert!(invalidator.invalidate("png/1.png").await.is_err());
+        server.await.unwrap();
+    }
+
+    #[tokio::test]
+    async fn cloudflare_purges_file() {
+        let (url, server) = serve_one(200).await;
+        let args = CacheInvalidationArgs {
+            cdn_invalidator: Some(CacheInvalidatorKind::Cloudflare),
+            cdn_url: Some("https://videos.example.com".to_string()),
+            cdn_api_url: Some(format!("{}/", url)),
+            cloudflare_zone_id: Some("zone-1".to_string()),
+            cloudflare_api_token: Some("cf-token".to_string()),
+            ..no_args()
+        };
+        let invalidator = create_cache_invalidator(&args).unwrap();
+        invalidator.invalidate("mp4/1.mp4").await.unwrap();
+        let req = server.await.unwrap();
+        assert_eq!(req.request_line, "POST /zones/zone-1/purge_cache HTTP/1.1");
+        assert_eq!(req.header("authorization"), Some("Bearer cf-token"));
+        assert_eq!(req.header("content-type"), Some("application/json"));
+        assert_eq!(
+            req.json_body(),
+            serde_json::json!({"files": ["https://videos.example.com/mp4/1.mp4"]})
+        );
+    }
+
+    #[test]
+    fn cloudflare_requires_zone() {
+        let args = CacheInvalidationArgs {
+            cdn_invalidator: Some(CacheInvalidatorKind::Cloudflare),
+            cdn_url: Some("https://videos.example.com".to_string()),
+            cloudflare_api_token: Some("cf-token".to_string()),
+            ..no_args()
+        };
+        assert!(create_cache_invalidator(&args).is_err());
+    }
+
+    #[tokio::test]
+    async fn webhook_posts_path() {
+        let (url, server) = serve_one(204).await;
+        let args = CacheInvalidationArgs {
+            cdn_invalidator: Some(CacheInvalidatorKind::Webhook),
+            cdn_url: Some("https://videos.example.com".to_string()),
+            purge_webhook_url: Some(format!("{}/purge", url)),
+            purge_webhook_token: Some("hook-token".to_string()),
+            ..no_args()
+        };
+        let invalidator = create_cache_invalidator(&args).unwrap();
+        invalidator.invalidate("webp/1.webp").await.unwrap();
+        let req = server.await.unwrap();
+        assert_eq!(req.request_line, "POST /purge HTTP/1.1");
+        assert_eq!(req.header("authorization"), Some("Bearer hook-token"));
+        assert_eq!(
+            req.json_body(),
+            serde_json::json!({
+                "path": "webp/1.webp",
+                "url": "https://videos.example.com/webp/1.webp",
+            })
+        );
+    }
+
+    #[tokio::test]
+    async fn webhook_without_token_or_cdn_url() {
+        let (url, server) = serve_one(200).await;
+        let args = CacheInvalidationArgs {
+            cdn_invalidator: Some(CacheInvalidatorKind::Webhook),
+            purge_webhook_url: Some(url),
+            ..no_args()
+        };
+        let invalidator = create_cache_invalidator(&args).unwrap();
+        invalidator.invalidate("webp/1.webp").await.unwrap();
+        let req = server.await.unwrap();
+        assert_eq!(req.header("authorization"), None);
+        assert_eq!(
+            req.json_body(),
+            serde_json::json!({"path": "webp/1.webp", "url": null})
+        );
+    }
+
+    #[tokio::test]
+    async fn recording_clones_share_paths() {
+        let recorder = RecordingInvalidator::default();
+        let invalidator: Box<dyn CacheInvalidator> = Box::new(recorder.clone());
+        invalidator.invalidate("png/1.png").await.unwrap();
+        invalidator.invalidate("mp4/1.vtt").await.unwrap();
+        assert_eq!(recorder.paths(), vec!["png/1.png", "mp4/1.vtt"]);
+    }
+}
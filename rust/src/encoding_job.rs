@@ -0,0 +1,215 @@
+use anyhow::Result;
+use deadpool_postgres::GenericClient;
+use lapin::types::{AMQPValue, FieldTable};
+use serde::{Deserialize, Serialize};
+
+use crate::EncodingTask;
+
+// Every published `EncodingTask` has an `encoding_job` row which tracks its progress through the
+// encoder, so that failures are visible after the fact (e.g. to the uploader of the video).
+
+#[derive(
+    Serialize,
+    Deserialize,
+    Debug,
+    Clone,
+    Copy,
+    PartialEq,
+    Eq,
+    strum::EnumString,
+    strum::IntoStaticStr,
+)]
+pub enum EncodingJobStatus {
+    Queued,
+    Running,
+    Succeeded,
+    Failed,
+}
+
+// The message published to the encoding queue. Messages published before jobs were recorded
+// consist of the task alone, and have no `job_id`.
+#[derive(Serialize, Deserialize)]
+pub struct EncodingJobMessage {
+    #[serde(default)]
+    pub job_id: Option<i32>,
+    #[serde(flatten)]
+    pub task: EncodingTask,
+    // Number of earlier attempts at the task which failed:
+    #[serde(default)]
+    pub failed_attempts: u32,
+    // Error from the latest failed attempt, if any:
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub last_error: Option<String>,
+}
+
+// Failed tasks are published to a retry queue, where they wait out the delay (as the queue's
+// message TTL) before being dead-lettered back to the encoding queue. There is one retry queue per
+// delay, so that messages expire in order. Tasks which fail too many times end up in the
+// dead-letter queue, where they stay until re-driven by `encoding-dead-letters`.
+
+pub fn retry_queue_name(queue: &str, delay_secs: u64) -> String {
+    format!("{}.retry-{}s", queue, delay_secs)
+}
+
+pub fn dead_letter_queue_name(queue: &str) -> String {
+    format!("{}.dead-letter", queue)
+}
+
+// Delay before retrying a task which has failed the given number of times (at least 1), doubling
+// with each failure.
+pub fn retry_delay_secs(failed_attempts: u32, base_delay_secs: u64, max_delay_secs: u64) -> u64 {
+    let factor = 1u64 << failed_attempts.saturating_sub(1).min(32);
+    base_delay_secs.saturating_mul(factor).min(max_delay_secs)
+}
+
+pub async fn declare_retry_queue(
+    channel: &lapin::Channel,
+    queue: &str,
+    delay_secs: u64,
+) -> Result<String> {
+    let name = retry_queue_name(queue, delay_secs);
+    let mut args = FieldTable::default();
+    args.insert(
+        "x-message-ttl".into(),
+        AMQPValue::LongLongInt((delay_secs * 1000) as i64),
+    );
+    args.insert(
+        "x-dead-letter-exchange".into(),
+        AMQPValue::LongString("".into()),
+    );
+    args.insert(
+        "x-dead-letter-routing-key".into(),
+        AMQPValue::LongString(queue.into()),
+    );
+    let opts = lapin::options::QueueDeclareOptions {
+        durable: true,
+        ..Default::default()
+    };
+    channel.queue_declare(&name, opts, args).await?;
+    Ok(name)
+}
+
+pub async fn declare_dead_letter_queue(channel: &lapin::Channel, queue: &str) -> Result<String> {
+    let name = dead_letter_queue_name(queue);
+    let opts = lapin::options::QueueDeclareOptions {
+        durable: true,
+        ..Default::default()
+    };
+    channel
+        .queue_declare(&name, opts, FieldTable::default())
+        .await?;
+    Ok(name)
+}
+
+impl EncodingTask {
+    pub fn video_id(&self) -> i32 {
+        match *self {
+            EncodingTask::ThumbnailImage { video_id, .. }
+            | EncodingTask::HighlightAnimation { video_id, .. }
+            | EncodingTask::FullVideo { video_id, .. }
+            | EncodingTask::Chapters { video_id } => video_id,
+        }
+    }
+
+    pub fn task_type(&self) -> &'static str {
+        match self {
+            EncodingTask::ThumbnailImage { .. } => "ThumbnailImage",
+            EncodingTask::HighlightAnimation { .. } => "HighlightAnimation",
+            EncodingTask::FullVideo { .. } => "FullVideo",
+            EncodingTask::Chapters { .. } => "Chapters",
+        }
+    }
+}
+
+// Record a new job for the task (in the Queued state), returning the message to publish for it.
+pub async fn create_encoding_job(
+    db: &impl GenericClient,
+    task: EncodingTask,
+) -> Result<EncodingJobMessage> {
+    let sql = r#"
+        INSERT INTO encoding_job (video_id, task_type, task, status)
+        VALUES ($1, $2, $3, $4)
+        RETURNING id
+    "#;
+    let stmt = db.prepare_cached(sql).await?;
+    let row = db
+        .query_one(
+            &stmt,
+            &[
+                &task.video_id(),
+                &task.task_type(),
+                &serde_json::to_value(&task)?,
+                &<&str>::from(EncodingJobStatus::Queued),
+            ],
+        )
+        .await?;
+    Ok(EncodingJobMessage {
+        job_id: Some(row.get("id")),
+        task,
+        failed_attempts: 0,
+        last_error: None,
+    })
+}
+
+pub async fn start_encoding_job(
+    db: &impl GenericClient,
+    job_id: i32,
+    worker_id: &str,
+) -> Result<()> {
+    let sql = r#"
+        UPDATE encoding_job
+        SET status = $2,
+            attempts = attempts + 1,
+            worker_id = $3,
+            started_ts = current_timestamp,
+            finished_ts = NULL
+        WHERE id = $1
+    "#;
+    let stmt = db.prepare_cached(sql).await?;
+    db.execute(
+        &stmt,
+        &[
+            &job_id,
+            &<&str>::from(EncodingJobStatus::Running),
+            &worker_id,
+        ],
+    )
+    .await?;
+    Ok(())
+}
+
+// Record the outcome of an attempt at the job: Succeeded, Failed, or Queued if it failed but will
+// be retried. The error and ffmpeg stderr are those of the latest attempt, and are cleared if it
+// succeeds.
+pub async fn finish_encoding_job(
+    db: &impl GenericClient,
+    job_id: i32,
+    status: EncodingJobStatus,
+    error: Option<&str>,
+    stderr_tail: Option<&str>,
+) -> Result<()> {
+    let sql = r#"
+        UPDATE encoding_job
+        SET status = $2,
+            error = $3,
+            stderr_tail = $4,
+            finished_ts = current_timestamp
+        WHERE id = $1
+    "#;
+    let stmt = db.prepare_cached(sql).await?;
+    db.execute(
+        &stmt,
+        &[&job_id, &<&str>::from(status), &error, &stderr_tail],
+    )
+    .await?;
+    Ok(())
+}
+
+// Mark a job as queued again, e.g. when its task is re-driven from the dead-letter queue.
+pub async fn requeue_encoding_job(db: &impl GenericClient, job_id: i32) -> Result<()> {
+    let sql = "UPDATE encoding_job SET status = $2 WHERE id = $1";
+    let stmt = db.prepare_cached(sql).await?;
+    db.execute(&stmt, &[&job_id, &<&str>::from(EncodingJobStatus::Queued)])
+        .await?;
+    Ok(())
+}
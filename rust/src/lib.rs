@@ -1,9 +1,19 @@
+pub mod annotation;
+pub mod avi;
+pub mod cache_invalidation;
+pub mod encoding_job;
+pub mod markdown;
+pub mod object_cache;
+pub mod password;
+pub mod rate_limit;
+pub mod session;
+
 use std::path::Path;
 
 use object_store::{aws::AmazonS3Builder, gcp::GoogleCloudStorageBuilder, local::LocalFileSystem, memory::InMemory, ObjectStore};
 use serde::{Deserialize, Serialize};
 
-#[derive(Serialize, Deserialize)]
+#[derive(Serialize, Deserialize, Clone, Debug)]
 pub enum EncodingTask {
     ThumbnailImage {
         video_id: i32,
@@ -26,6 +36,15 @@ pub enum EncodingTask {
         video_id: i32,
         num_parts: i32,
     },
+    // WebVTT chapters file generated from the video's annotations (which are read from the
+    // database, so that the latest ones are used even if tasks are processed out of order).
+    Chapters {
+        video_id: i32,
+    },
+}
+
+pub fn hex_string(data: &[u8]) -> String {
+    data.iter().map(|b| format!("{:02x}", b)).collect()
 }
 
 pub fn create_object_store(url: &str) -> Box<dyn ObjectStore> {
@@ -36,8 +55,7 @@ pub fn create_object_store(url: &str) -> Box<dyn ObjectStore> {
                 .build()
                 .unwrap(),
         )
-    } else if url.starts_with("s3:") {
-        let bucket = &url[3..];
+    } else if let Some(bucket) = url.strip_prefix("s3:") {
         Box::new(
             AmazonS3Builder::from_env()
                 .with_bucket_name(bucket)
@@ -46,8 +64,7 @@ pub fn create_object_store(url: &str) -> Box<dyn ObjectStore> {
         )
     } else if url == "mem" {
         Box::new(InMemory::new())
-    } else if url.starts_with("file:") {
-        let root = &url[5..];
+    } else if let Some(root) = url.strip_prefix("file:") {
         Box::new(LocalFileSystem::new_with_prefix(Path::new(root)).unwrap())
     } else {
         panic!("Unsupported seed repository type: {}", url);
@@ -0,0 +1,128 @@
+use pulldown_cmark::{html, CowStr, Event, Options, Parser, Tag, TagEnd};
+
+// User-written Markdown (e.g. video comments) is rendered to HTML on the server. Raw HTML in the
+// source is shown as text rather than passed through, images are replaced by their alt text, and
+// links only keep http(s), mailto, or relative URLs, so the output is safe to insert into the page.
+
+fn is_safe_url(url: &str) -> bool {
+    // Browsers ignore whitespace and control characters in the scheme (e.g. "java\tscript:").
+    let url: String = url
+        .chars()
+        .filter(|c| !c.is_whitespace() && !c.is_control())
+        .collect::<String>()
+        .to_lowercase();
+    match url.find([':', '/', '?', '#']) {
+        Some(i) if url[i..].starts_with(':') => {
+            let scheme = &url[..i];
+            scheme == "http" || scheme == "https" || scheme == "mailto"
+        }
+        _ => true,
+    }
+}
+
+pub fn render_markdown(source: &str) -> String {
+    let options = Options::ENABLE_STRIKETHROUGH | Options::ENABLE_TABLES;
+    let events = Parser::new_ext(source, options).filter_map(|event| match event {
+        Event::Html(s) | Event::InlineHtml(s) => Some(Event::Text(s)),
+        Event::Start(Tag::Image { .. }) | Event::End(TagEnd::Image) => None,
+        Event::Start(Tag::Link {
+            link_type,
+            dest_url,
+            title,
+            id,
+        }) if !is_safe_url(&dest_url) => Some(Event::Start(Tag::Link {
+            link_type,
+            dest_url: CowStr::Borrowed(""),
+            title,
+            id,
+        })),
+        _ => Some(event),
+    });
+    let mut out = String::new();
+    html::push_html(&mut out, events);
+    out
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn formatting() {
+        assert_eq!(
+            render_markdown("**bold** and ~~struck~~"),
+            "<p><strong>bold</strong> and <del>struck</del></p>\n"
+        );
+    }
+
+    #[test]
+    fn raw_html_is_escaped() {
+        assert_eq!(
+            render_markdown("<script>alert(1)</script>"),
+            "&lt;script&gt;alert(1)&lt;/script&gt;"
+        );
+        assert_eq!(
+            render_markdown("a <img src=x onerror=alert(1)> b"),
+            "<p>a &lt;img src=x onerror=alert(1)&gt; b</p>\n"
+        );
+    }
+
+    #[test]
+    fn safe_links_are_kept() {
+        assert_eq!(
+            render_markdown("[a](https://example.com/x?y#z)"),
+            "<p><a href=\"https://example.com/x?y#z\">a</a></p>\n"
+        );
+        assert_eq!(
+            render_markdown("[a](mailto:someone@example.com)"),
+            "<p><a href=\"mailto:someone@example.com\">a</a></p>\n"
+        );
+        assert_eq!(
+            render_markdown("[a](/video/1)"),
+            "<p><a href=\"/video/1\">a</a></p>\n"
+        );
+        assert_eq!(
+            render_markdown("[a](page?x=javascript:1)"),
+            "<p><a href=\"page?x=javascript:1\">a</a></p>\n"
+        );
+    }
+
+    #[test]
+    fn unsafe_links_are_removed() {
+        for source in [
+            "[a](javascript:alert(1))",
+            "[a](JavaScript:alert(1))",
+            "[a](<java\tscript:alert(1)>)",
+            "[a](data:text/html;base64,PHNjcmlwdD4=)",
+            "[a](vbscript:msgbox)",
+        ] {
+            assert_eq!(
+                render_markdown(source),
+                "<p><a href=\"\">a</a></p>\n",
+                "{:?}",
+                source
+            );
+        }
+    }
+
+    #[test]
+    fn images_are_replaced_by_alt_text() {
+        assert_eq!(
+            render_markdown("![a *map*](https://example.com/x.png)"),
+            "<p>a <em>map</em></p>\n"
+        );
+        assert_eq!(render_markdown("![x](javascript:alert(1))"), "<p>x</p>\n");
+    }
+
+    #[test]
+    fn autolinks() {
+        assert_eq!(
+            render_markdown("<https://example.com>"),
+            "<p><a href=\"https://example.com\">https://example.com</a></p>\n"
+        );
+        assert_eq!(
+            render_markdown("<javascript:alert(1)>"),
+            "<p><a href=\"\">javascript:alert(1)</a></p>\n"
+        );
+    }
+}
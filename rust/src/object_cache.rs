@@ -0,0 +1,294 @@
+use std::collections::{HashMap, HashSet};
+use std::path::{Path, PathBuf};
+use std::sync::Mutex;
+
+use anyhow::Result;
+use log::{error, info};
+use object_store::ObjectStore;
+use sha2::{Digest, Sha256};
+
+use crate::hex_string;
+
+// A local, size-bounded cache of objects from object storage, keyed by object path and ETag (so
+// an overwritten object is never served stale). When full, the least recently used objects are
+// evicted. Cached files are hard-linked (or copied) to their destination, so they can be evicted
+// while still in use there.
+
+struct CacheEntry {
+    size: u64,
+    last_used: u64,
+}
+
+#[derive(Default)]
+struct CacheState {
+    entries: HashMap<String, CacheEntry>,
+    // Keys whose files are being added or removed (outside the lock), which can't be added again
+    // until that is done:
+    pending: HashSet<String>,
+    total_bytes: u64,
+    // Incremented on each use of an entry, to order them by recency:
+    clock: u64,
+}
+
+pub struct ObjectCache {
+    dir: PathBuf,
+    max_bytes: u64,
+    state: Mutex<CacheState>,
+}
+
+fn link_or_copy(src: &Path, dest: &Path) -> std::io::Result<()> {
+    std::fs::hard_link(src, dest).or_else(|_| std::fs::copy(src, dest).map(|_| ()))
+}
+
+impl ObjectCache {
+    // The cache's files in `dir` (e.g. left by an earlier process) are removed, as they aren't
+    // tracked. A `max_bytes` of 0 disables caching.
+    pub fn new(dir: PathBuf, max_bytes: u64) -> Result<Self> {
+        std::fs::create_dir_all(&dir)?;
+        for dir_entry in std::fs::read_dir(&dir)? {
+            let dir_entry = dir_entry?;
+            let name = dir_entry.file_name();
+            let name = name.to_string_lossy();
+            if name.len() == 64 && name.chars().all(|c| c.is_ascii_hexdigit()) {
+                std::fs::remove_file(dir_entry.path())?;
+            }
+        }
+        Ok(ObjectCache {
+            dir,
+            max_bytes,
+            state: Mutex::new(CacheState::default()),
+        })
+    }
+
+    fn cache_key(path: &object_store::path::Path, e_tag: &str) -> String {
+        hex_string(&Sha256::digest(format!("{}\n{}", path, e_tag).as_bytes()))
+    }
+
+    // Write the object to `dest`, from the cache if possible. The object is requested either way,
+    // for its current ETag, but its data is only read on a cache miss.
+    pub async fn fetch(
+        &self,
+        store: &dyn ObjectStore,
+        path: &object_store::path::Path,
+        dest: &Path,
+    ) -> Result<()> {
+        let result = store.get(path).await?;
+        let e_tag = result
+            .meta
+            .e_tag
+            .clone()
+            .filter(|_| self.max_bytes > 0 && result.meta.size as u64 <= self.max_bytes);
+        let Some(e_tag) = e_tag else {
+            std::fs::write(dest, result.bytes().await?)?;
+            return Ok(());
+        };
+
+        let key = Self::cache_key(path, &e_tag);
+        if self.take_cached(&key, dest) {
+            info!("Object cache hit: {}", path);
+            return Ok(());
+        }
+        info!("Object cache miss: {}", path);
+        let data = result.bytes().await?;
+        std::fs::write(dest, &data)?;
+        self.insert(&key, dest, data.len() as u64);
+        Ok(())
+    }
+
+    // Link the cached file for `key` to `dest`, returning whether it was cached.
+    fn take_cached(&self, key: &str, dest: &Path) -> bool {
+        {
+            let mut state = self.state.lock().unwrap();
+            state.clock += 1;
+            let clock = state.clock;
+            let Some(entry) = state.entries.get_mut(key) else {
+                return false;
+            };
+            entry.last_used = clock;
+        }
+        // The file may have been evicted since the lock was released, in which case this fails
+        // and the object is fetched again.
+        link_or_copy(&self.dir.join(key), dest).is_ok()
+    }
+
+    fn insert(&self, key: &str, src: &Path, size: u64) {
+        {
+            let mut state = self.state.lock().unwrap();
+            if state.entries.contains_key(key) || state.pending.contains(key) {
+                // Added by a concurrent fetch of the same object (or being evicted).
+                return;
+            }
+            state.pending.insert(key.to_string());
+        }
+
+        let res = link_or_copy(src, &self.dir.join(key));
+
+        let evicted_keys = {
+            let mut state = self.state.lock().unwrap();
+            state.pending.remove(key);
+            if let Err(e) = res {
+                error!("Failed to add object to cache: {}", e);
+                return;
+            }
+            state.clock += 1;
+            let clock = state.clock;
+            state.entries.insert(
+                key.to_string(),
+                CacheEntry {
+                    size,
+                    last_used: clock,
+                },
+            );
+            state.total_bytes += size;
+
+            let mut evicted_keys = vec![];
+            while state.total_bytes > self.max_bytes {
+                let Some(lru_key) = state
+                    .entries
+                    .iter()
+                    .filter(|(k, _)| k.as_str() != key)
+                    .min_by_key(|(_, e)| e.last_used)
+                    .map(|(k, _)| k.clone())
+                else {
+                    break;
+                };
+                let entry = state.entries.remove(&lru_key).unwrap();
+                state.total_bytes -= entry.size;
+                state.pending.insert(lru_key.clone());
+                evicted_keys.push(lru_key);
+            }
+            evicted_keys
+        };
+
+        for evicted_key in &evicted_keys {
+            if let Err(e) = std::fs::remove_file(self.dir.join(evicted_key)) {
+                error!("Failed to remove evicted object from cache: {}", e);
+            }
+        }
+        if !evicted_keys.is_empty() {
+            let mut state = self.state.lock().unwrap();
+            for evicted_key in &evicted_keys {
+                state.pending.remove(evicted_key);
+            }
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use object_store::{memory::InMemory, path::Path as ObjectPath, PutPayload};
+
+    fn cached_keys(cache: &ObjectCache) -> HashSet<String> {
+        cache
+            .state
+            .lock()
+            .unwrap()
+            .entries
+            .keys()
+            .cloned()
+            .collect()
+    }
+
+    fn write_file(dir: &Path, name: &str, size: usize) -> PathBuf {
+        let path = dir.join(name);
+        std::fs::write(&path, vec![0u8; size]).unwrap();
+        path
+    }
+
+    #[test]
+    fn evicts_least_recently_used() {
+        let tmp = tempfile::tempdir().unwrap();
+        let cache = ObjectCache::new(tmp.path().join("cache"), 10).unwrap();
+        let a = write_file(tmp.path(), "a", 4);
+        let b = write_file(tmp.path(), "b", 4);
+        let c = write_file(tmp.path(), "c", 4);
+        cache.insert("a", &a, 4);
+        cache.insert("b", &b, 4);
+        // Using `a` makes `b` the least recently used:
+        assert!(cache.take_cached("a", &tmp.path().join("a-out")));
+        cache.insert("c", &c, 4);
+
+        assert_eq!(cached_keys(&cache), HashSet::from(["a".into(), "c".into()]));
+        assert_eq!(cache.state.lock().unwrap().total_bytes, 8);
+        assert!(cache.state.lock().unwrap().pending.is_empty());
+        assert!(!tmp.path().join("cache/b").exists());
+        assert!(!cache.take_cached("b", &tmp.path().join("b-out")));
+        assert!(cache.take_cached("c", &tmp.path().join("c-out")));
+        assert_eq!(std::fs::read(tmp.path().join("c-out")).unwrap().len(), 4);
+    }
+
+    #[test]
+    fn evicts_several_to_fit() {
+        let tmp = tempfile::tempdir().unwrap();
+        let cache = ObjectCache::new(tmp.path().join("cache"), 10).unwrap();
+        for name in ["a", "b", "c"] {
+            let path = write_file(tmp.path(), name, 3);
+            cache.insert(name, &path, 3);
+        }
+        let d = write_file(tmp.path(), "d", 7);
+        cache.insert("d", &d, 7);
+        assert_eq!(cached_keys(&cache), HashSet::from(["c".into(), "d".into()]));
+        assert_eq!(cache.state.lock().unwrap().total_bytes, 10);
+    }
+
+    #[test]
+    fn duplicate_insert_is_ignored() {
+        let tmp = tempfile::tempdir().unwrap();
+        let cache = ObjectCache::new(tmp.path().join("cache"), 10).unwrap();
+        let a = write_file(tmp.path(), "a", 4);
+        cache.insert("a", &a, 4);
+        cache.insert("a", &a, 4);
+        assert_eq!(cache.state.lock().unwrap().total_bytes, 4);
+    }
+
+    #[test]
+    fn new_removes_leftover_files() {
+        let tmp = tempfile::tempdir().unwrap();
+        let dir = tmp.path().join("cache");
+        std::fs::create_dir(&dir).unwrap();
+        let leftover = write_file(&dir, &"0".repeat(64), 1);
+        let other = write_file(&dir, "other", 1);
+        ObjectCache::new(dir, 10).unwrap();
+        assert!(!leftover.exists());
+        assert!(other.exists());
+    }
+
+    #[tokio::test]
+    async fn fetch_caches_by_e_tag() {
+        let tmp = tempfile::tempdir().unwrap();
+        let cache = ObjectCache::new(tmp.path().join("cache"), 10).unwrap();
+        let store = InMemory::new();
+        let path = ObjectPath::from("avi-xz/1-0.avi.xz");
+        store.put(&path, PutPayload::from("first")).await.unwrap();
+
+        let dest = tmp.path().join("out-1");
+        cache.fetch(&store, &path, &dest).await.unwrap();
+        assert_eq!(std::fs::read(&dest).unwrap(), b"first");
+        assert_eq!(cached_keys(&cache).len(), 1);
+        let dest = tmp.path().join("out-2");
+        cache.fetch(&store, &path, &dest).await.unwrap();
+        assert_eq!(std::fs::read(&dest).unwrap(), b"first");
+        assert_eq!(cached_keys(&cache).len(), 1);
+
+        // Overwriting the object changes its ETag, so the cached copy isn't used:
+        store.put(&path, PutPayload::from("second")).await.unwrap();
+        let dest = tmp.path().join("out-3");
+        cache.fetch(&store, &path, &dest).await.unwrap();
+        assert_eq!(std::fs::read(&dest).unwrap(), b"second");
+        assert_eq!(cached_keys(&cache).len(), 1);
+    }
+
+    #[tokio::test]
+    async fn fetch_skips_objects_too_large_to_cache() {
+        let tmp = tempfile::tempdir().unwrap();
+        let cache = ObjectCache::new(tmp.path().join("cache"), 4).unwrap();
+        let store = InMemory::new();
+        let path = ObjectPath::from("avi-xz/1-0.avi.xz");
+        store.put(&path, PutPayload::from("large")).await.unwrap();
+        let dest = tmp.path().join("out");
+        cache.fetch(&store, &path, &dest).await.unwrap();
+        assert_eq!(std::fs::read(&dest).unwrap(), b"large");
+        assert!(cached_keys(&cache).is_empty());
+    }
+}
@@ -0,0 +1,62 @@
+use std::sync::OnceLock;
+
+use anyhow::{anyhow, Result};
+use argon2::{
+    password_hash::{
+        rand_core::{OsRng, RngCore},
+        PasswordHash, PasswordHasher, PasswordVerifier, SaltString,
+    },
+    Argon2,
+};
+use sha2::{Digest, Sha256};
+use subtle::ConstantTimeEq;
+
+use crate::hex_string;
+
+// Account tokens are stored as Argon2id hashes in PHC string format (which includes the
+// per-account salt and parameters). Accounts created before this was introduced instead have an
+// unsalted SHA-256 `token_hash`, which is replaced by an Argon2id hash on the next successful login.
+
+pub fn hash_password(password: &str) -> Result<String> {
+    let salt = SaltString::generate(&mut OsRng);
+    let hash = Argon2::default()
+        .hash_password(password.as_bytes(), &salt)
+        .map_err(|e| anyhow!("hashing password: {}", e))?;
+    Ok(hash.to_string())
+}
+
+pub fn verify_password(password: &str, password_hash: &str) -> Result<bool> {
+    let parsed_hash =
+        PasswordHash::new(password_hash).map_err(|e| anyhow!("parsing password hash: {}", e))?;
+    // The comparison of the computed hash with the stored one is constant-time.
+    Ok(Argon2::default()
+        .verify_password(password.as_bytes(), &parsed_hash)
+        .is_ok())
+}
+
+// Verify a password against the hash of a random one, taking as long as `verify_password` does,
+// so that the time taken to reject an unknown username doesn't reveal that it doesn't exist.
+pub fn verify_dummy_password(password: &str) {
+    static DUMMY_PASSWORD_HASH: OnceLock<String> = OnceLock::new();
+    let password_hash = DUMMY_PASSWORD_HASH
+        .get_or_init(|| hash_password(&generate_token()).expect("hashing random password"));
+    let _ = verify_password(password, password_hash);
+}
+
+pub fn verify_legacy_token_hash(password: &str, token_hash: &[u8]) -> bool {
+    let presented_token_hash = Sha256::digest(password.as_bytes());
+    presented_token_hash.as_slice().ct_eq(token_hash).into()
+}
+
+// Generate a random token (e.g. for an API token or CSRF token), as a hex string.
+pub fn generate_token() -> String {
+    let mut bytes = [0u8; 32];
+    OsRng.fill_bytes(&mut bytes);
+    hex_string(&bytes)
+}
+
+// Random tokens (API tokens, invite codes) have full entropy, so unlike passwords a fast
+// unsalted hash is enough (and allows looking them up by hash).
+pub fn hash_random_token(token: &str) -> Vec<u8> {
+    Sha256::digest(token.as_bytes()).to_vec()
+}
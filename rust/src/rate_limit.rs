@@ -0,0 +1,239 @@
+use std::collections::HashMap;
+use std::sync::Mutex;
+use std::time::{Duration, Instant};
+
+// Fixed-window counters keyed by strings such as "login-ip:1.2.3.4". They are kept in memory,
+// so limits apply per server process and are reset when it restarts.
+
+// Once there are this many counters, expired ones are dropped before adding another.
+const PRUNE_THRESHOLD: usize = 10000;
+
+struct Window {
+    start: Instant,
+    length: Duration,
+    count: u64,
+}
+
+impl Window {
+    fn remaining(&self, now: Instant) -> Option<Duration> {
+        self.length.checked_sub(now.duration_since(self.start))
+    }
+}
+
+#[derive(Default)]
+pub struct RateLimiter {
+    windows: Mutex<HashMap<String, Window>>,
+}
+
+// The current window for `key`, starting a new one of the given length if there is none.
+fn current_window<'a>(
+    windows: &'a mut HashMap<String, Window>,
+    key: &str,
+    length: Duration,
+    now: Instant,
+) -> &'a mut Window {
+    if windows.len() >= PRUNE_THRESHOLD {
+        windows.retain(|_, w| w.remaining(now).is_some());
+    }
+    let window = windows.entry(key.to_string()).or_insert(Window {
+        start: now,
+        length,
+        count: 0,
+    });
+    if window.remaining(now).is_none() {
+        *window = Window {
+            start: now,
+            length,
+            count: 0,
+        };
+    }
+    window
+}
+
+impl RateLimiter {
+    // If the count for `key` has reached `limit` in its current window, returns the time until
+    // the window ends (and the count is reset).
+    pub fn check(&self, key: &str, limit: u64) -> Option<Duration> {
+        self.check_at(key, limit, Instant::now())
+    }
+
+    fn check_at(&self, key: &str, limit: u64, now: Instant) -> Option<Duration> {
+        let windows = self.windows.lock().unwrap();
+        let window = windows.get(key)?;
+        if window.count < limit {
+            return None;
+        }
+        window.remaining(now)
+    }
+
+    // Add `amount` to the count for `key`, starting a new window of the given length if there
+    // is no current one.
+    pub fn add(&self, key: &str, amount: u64, length: Duration) {
+        self.add_at(key, amount, length, Instant::now());
+    }
+
+    fn add_at(&self, key: &str, amount: u64, length: Duration, now: Instant) {
+        let mut windows = self.windows.lock().unwrap();
+        let window = current_window(&mut windows, key, length, now);
+        window.count = window.count.saturating_add(amount);
+    }
+
+    // Add `amount` to the count for `key` as in `add`, unless that would take it over `limit`, in
+    // which case nothing is added and the time until the window ends is returned. This allows
+    // reserving an amount before it is known whether it will all be used (see `remove`).
+    pub fn try_add(
+        &self,
+        key: &str,
+        amount: u64,
+        limit: u64,
+        length: Duration,
+    ) -> Result<(), Duration> {
+        self.try_add_at(key, amount, limit, length, Instant::now())
+    }
+
+    fn try_add_at(
+        &self,
+        key: &str,
+        amount: u64,
+        limit: u64,
+        length: Duration,
+        now: Instant,
+    ) -> Result<(), Duration> {
+        let mut windows = self.windows.lock().unwrap();
+        let window = current_window(&mut windows, key, length, now);
+        match window.count.checked_add(amount) {
+            Some(count) if count <= limit => {
+                window.count = count;
+                Ok(())
+            }
+            _ => Err(window.remaining(now).unwrap_or_default()),
+        }
+    }
+
+    // Subtract `amount` from the count for `key` (e.g. to refund a reservation made with
+    // `try_add`), if its window hasn't ended since.
+    pub fn remove(&self, key: &str, amount: u64) {
+        self.remove_at(key, amount, Instant::now());
+    }
+
+    fn remove_at(&self, key: &str, amount: u64, now: Instant) {
+        let mut windows = self.windows.lock().unwrap();
+        if let Some(window) = windows.get_mut(key) {
+            if window.remaining(now).is_some() {
+                window.count = window.count.saturating_sub(amount);
+            }
+        }
+    }
+
+    pub fn reset(&self, key: &str) {
+        self.windows.lock().unwrap().remove(key);
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    const MINUTE: Duration = Duration::from_secs(60);
+
+    #[test]
+    fn limit_reached() {
+        let limiter = RateLimiter::default();
+        let now = Instant::now();
+        assert_eq!(limiter.check_at("k", 3, now), None);
+        limiter.add_at("k", 1, MINUTE, now);
+        limiter.add_at("k", 1, MINUTE, now);
+        assert_eq!(limiter.check_at("k", 3, now), None);
+        limiter.add_at("k", 1, MINUTE, now);
+        let later = now + Duration::from_secs(20);
+        assert_eq!(
+            limiter.check_at("k", 3, later),
+            Some(Duration::from_secs(40))
+        );
+        // Other keys are counted separately:
+        assert_eq!(limiter.check_at("other", 3, later), None);
+    }
+
+    #[test]
+    fn window_expires() {
+        let limiter = RateLimiter::default();
+        let now = Instant::now();
+        limiter.add_at("k", 5, MINUTE, now);
+        assert!(limiter.check_at("k", 5, now).is_some());
+        let later = now + MINUTE + Duration::from_secs(1);
+        assert_eq!(limiter.check_at("k", 5, later), None);
+        // Adding after the window has ended starts a new one:
+        limiter.add_at("k", 1, MINUTE, later);
+        assert_eq!(limiter.check_at("k", 2, later), None);
+        limiter.add_at("k", 1, MINUTE, later);
+        assert_eq!(limiter.check_at("k", 2, later), Some(MINUTE));
+    }
+
+    #[test]
+    fn reset() {
+        let limiter = RateLimiter::default();
+        let now = Instant::now();
+        limiter.add_at("k", 5, MINUTE, now);
+        limiter.reset("k");
+        assert_eq!(limiter.check_at("k", 5, now), None);
+    }
+
+    #[test]
+    fn try_add_reserves_up_to_limit() {
+        let limiter = RateLimiter::default();
+        let now = Instant::now();
+        assert_eq!(limiter.try_add_at("k", 60, 100, MINUTE, now), Ok(()));
+        assert_eq!(limiter.try_add_at("k", 40, 100, MINUTE, now), Ok(()));
+        assert_eq!(limiter.try_add_at("k", 1, 100, MINUTE, now), Err(MINUTE));
+        assert!(limiter.check_at("k", 100, now).is_some());
+    }
+
+    #[test]
+    fn try_add_over_limit_adds_nothing() {
+        let limiter = RateLimiter::default();
+        let now = Instant::now();
+        assert_eq!(limiter.try_add_at("k", 60, 100, MINUTE, now), Ok(()));
+        assert_eq!(limiter.try_add_at("k", 50, 100, MINUTE, now), Err(MINUTE));
+        assert_eq!(limiter.try_add_at("k", 40, 100, MINUTE, now), Ok(()));
+        assert_eq!(
+            limiter.try_add_at("k", u64::MAX, u64::MAX, MINUTE, now),
+            Err(MINUTE)
+        );
+    }
+
+    #[test]
+    fn remove_refunds_reservation() {
+        let limiter = RateLimiter::default();
+        let now = Instant::now();
+        assert_eq!(limiter.try_add_at("k", 100, 100, MINUTE, now), Ok(()));
+        limiter.remove_at("k", 70, now);
+        assert_eq!(limiter.try_add_at("k", 70, 100, MINUTE, now), Ok(()));
+        // Removing more than the count leaves it at zero:
+        limiter.remove_at("k", 1000, now);
+        assert_eq!(limiter.check_at("k", 1, now), None);
+        // Removing from a missing key does nothing:
+        limiter.remove_at("missing", 1, now);
+        assert_eq!(limiter.check_at("missing", 1, now), None);
+    }
+
+    #[test]
+    fn remove_after_window_ends_does_nothing() {
+        let limiter = RateLimiter::default();
+        let now = Instant::now();
+        limiter.add_at("k", 10, MINUTE, now);
+        limiter.remove_at("k", 10, now + MINUTE + Duration::from_secs(1));
+        assert_eq!(limiter.check_at("k", 10, now), Some(MINUTE));
+    }
+
+    #[test]
+    fn expired_windows_are_pruned() {
+        let limiter = RateLimiter::default();
+        let now = Instant::now();
+        for i in 0..PRUNE_THRESHOLD {
+            limiter.add_at(&format!("k{}", i), 1, MINUTE, now);
+        }
+        let later = now + MINUTE + Duration::from_secs(1);
+        limiter.add_at("new", 1, MINUTE, later);
+        assert_eq!(limiter.windows.lock().unwrap().len(), 1);
+    }
+}
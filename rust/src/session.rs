@@ -0,0 +1,137 @@
+use hmac::{Hmac, Mac};
+use sha2::Sha256;
+
+use crate::hex_string;
+
+// Browser sessions are stateless: the cookie carries the account ID, issue and expiry times and a
+// CSRF token, signed with a server-side secret. The CSRF token must be echoed back in a header on
+// mutating requests, which a cross-site form or script cannot do since it can't read the cookie.
+
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct SessionClaims {
+    pub account_id: i32,
+    // Unix timestamp (milliseconds) when the session was created, so that sessions issued before
+    // the account's token was changed can be rejected, even within the same second:
+    pub issued_ts: i64,
+    // Unix timestamp (seconds) after which the session is no longer valid:
+    pub expires_ts: i64,
+    pub csrf_token: String,
+}
+
+fn signature(secret: &[u8], payload: &str) -> Hmac<Sha256> {
+    let mut mac = Hmac::<Sha256>::new_from_slice(secret).expect("HMAC accepts any key length");
+    mac.update(payload.as_bytes());
+    mac
+}
+
+fn parse_hex(s: &str) -> Option<Vec<u8>> {
+    let chunks = s.as_bytes().chunks_exact(2);
+    if !chunks.remainder().is_empty() {
+        return None;
+    }
+    chunks
+        .map(|c| u8::from_str_radix(std::str::from_utf8(c).ok()?, 16).ok())
+        .collect()
+}
+
+pub fn encode_session(secret: &[u8], claims: &SessionClaims) -> String {
+    let payload = format!(
+        "{}.{}.{}.{}",
+        claims.account_id, claims.issued_ts, claims.expires_ts, claims.csrf_token
+    );
+    let sig = signature(secret, &payload).finalize().into_bytes();
+    format!("{}.{}", payload, hex_string(&sig))
+}
+
+// Returns the claims of a correctly signed session cookie which has not expired as of `now_ts`.
+pub fn decode_session(secret: &[u8], value: &str, now_ts: i64) -> Option<SessionClaims> {
+    let (payload, sig_hex) = value.rsplit_once('.')?;
+    signature(secret, payload)
+        .verify_slice(&parse_hex(sig_hex)?)
+        .ok()?;
+    let mut fields = payload.split('.');
+    let claims = SessionClaims {
+        account_id: fields.next()?.parse().ok()?,
+        issued_ts: fields.next()?.parse().ok()?,
+        expires_ts: fields.next()?.parse().ok()?,
+        csrf_token: fields.next()?.to_string(),
+    };
+    if fields.next().is_some() || claims.expires_ts <= now_ts {
+        return None;
+    }
+    Some(claims)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    const SECRET: &[u8] = b"secret";
+    const NOW_TS: i64 = 1_700_000_000;
+
+    fn claims() -> SessionClaims {
+        SessionClaims {
+            account_id: 7,
+            issued_ts: NOW_TS * 1000,
+            expires_ts: NOW_TS + 3600,
+            csrf_token: "0123abcd".to_string(),
+        }
+    }
+
+    #[test]
+    fn round_trip() {
+        let value = encode_session(SECRET, &claims());
+        assert_eq!(decode_session(SECRET, &value, NOW_TS), Some(claims()));
+    }
+
+    #[test]
+    fn expired() {
+        let value = encode_session(SECRET, &claims());
+        assert!(decode_session(SECRET, &value, NOW_TS + 3599).is_some());
+        assert_eq!(decode_session(SECRET, &value, NOW_TS + 3600), None);
+    }
+
+    #[test]
+    fn tampered() {
+        let value = encode_session(SECRET, &claims());
+        assert_eq!(decode_session(b"other secret", &value, NOW_TS), None);
+        let (payload, sig) = value.rsplit_once('.').unwrap();
+        let other_account = payload.replacen("7.", "8.", 1);
+        assert_eq!(
+            decode_session(SECRET, &format!("{}.{}", other_account, sig), NOW_TS),
+            None
+        );
+        let mut sig = sig.to_string();
+        let last = if sig.ends_with('0') { "1" } else { "0" };
+        sig.replace_range(sig.len() - 1.., last);
+        assert_eq!(
+            decode_session(SECRET, &format!("{}.{}", payload, sig), NOW_TS),
+            None
+        );
+    }
+
+    #[test]
+    fn extra_fields() {
+        // Correctly signed, but with a field too many:
+        let payload = format!("7.{}.{}.0123abcd.extra", NOW_TS * 1000, NOW_TS + 3600);
+        let sig = signature(SECRET, &payload).finalize().into_bytes();
+        let value = format!("{}.{}", payload, hex_string(&sig));
+        assert_eq!(decode_session(SECRET, &value, NOW_TS), None);
+    }
+
+    #[test]
+    fn bad_hex() {
+        let value = encode_session(SECRET, &claims());
+        let (payload, sig) = value.rsplit_once('.').unwrap();
+        for bad_sig in [&sig[1..], &format!("{}zz", &sig[2..]), ""] {
+            assert_eq!(
+                decode_session(SECRET, &format!("{}.{}", payload, bad_sig), NOW_TS),
+                None
+            );
+        }
+        assert_eq!(parse_hex("0aFf"), Some(vec![0x0a, 0xff]));
+        assert_eq!(parse_hex("0a0"), None);
+        assert_eq!(parse_hex("0g"), None);
+        assert_eq!(decode_session(SECRET, "no signature", NOW_TS), None);
+    }
+}
@@ -1,10 +1,56 @@
 CREATE TABLE account (
     id serial primary key,
     username varchar(100) NOT NULL,
-    token_hash bytea NOT NULL,
-    permission varchar(100) NOT NULL,
+    token_hash bytea,  -- legacy unsalted SHA-256 of the token, replaced by password_hash on next login
+    password_hash varchar(200),  -- Argon2id hash in PHC string format
+    permission varchar(100) NOT NULL,  -- role: Default, Reviewer, Curator, Editor, or Admin
     created_ts timestamptz default current_timestamp,
-    active boolean default FALSE
+    active boolean default FALSE,
+    disabled boolean NOT NULL default FALSE,
+    -- Sessions issued before this time (e.g. before the token was reset) are rejected:
+    sessions_valid_after_ts timestamptz,
+    invite_code_id integer  -- invite code the account registered with, if any
+);
+
+-- Usernames are unique regardless of case:
+CREATE UNIQUE INDEX account_username_lower ON account (lower(username));
+
+--- Codes allowing new contributors to register an account, usable up to `max_uses` times until `expires_ts`.
+
+CREATE TABLE invite_code (
+    id serial primary key,
+    code_hash bytea NOT NULL UNIQUE,  -- SHA-256 of the code
+    created_account_id integer NOT NULL,
+    created_ts timestamptz NOT NULL default current_timestamp,
+    expires_ts timestamptz NOT NULL,
+    revoked_ts timestamptz,
+    max_uses integer NOT NULL,
+    use_count integer NOT NULL default 0,
+    permission varchar(100) NOT NULL  -- permission given to accounts registered with the code
+);
+
+--- Record of account management actions.
+
+CREATE TABLE audit_log (
+    id serial primary key,
+    ts timestamptz NOT NULL default current_timestamp,
+    actor_account_id integer NOT NULL,
+    action varchar(100) NOT NULL,
+    target_account_id integer,
+    details jsonb NOT NULL
+);
+
+--- Revocable tokens for scripted clients, with a limited scope (ReadOnly, Upload, or Editor).
+
+CREATE TABLE api_token (
+    id serial primary key,
+    account_id integer NOT NULL,
+    name varchar(100) NOT NULL,
+    scope varchar(100) NOT NULL,
+    token_hash bytea NOT NULL UNIQUE,  -- SHA-256 of the token
+    created_ts timestamptz NOT NULL default current_timestamp,
+    expires_ts timestamptz,
+    revoked_ts timestamptz
 );
 
 CREATE TABLE video (
@@ -33,7 +79,133 @@ CREATE TABLE video (
     highlight_processed_ts timestamptz,
     full_video_processed_ts timestamptz,
     permanent boolean NOT NULL default false,
-    priority integer
+    priority integer,
+    part_frame_counts integer[],
+    frame_count integer,
+    frame_width integer,
+    frame_height integer,
+    frame_rate double precision,
+    version integer NOT NULL default 1,
+    deleted_ts timestamptz,
+    deleted_account_id integer,
+    status_before_delete varchar(100),
+    reviewer_account_id integer,  -- reviewer assigned to the video, if any
+    annotations jsonb NOT NULL default '[]'  -- named frame ranges: [{"start_t", "end_t", "name"}]
+);
+
+--- History of changes to each video, with a snapshot of its editable fields after every submit/edit/delete/revert.
+
+CREATE TABLE video_revision (
+    video_id integer,
+    revision integer,
+    action varchar(100) NOT NULL,
+    account_id integer NOT NULL,
+    created_ts timestamptz NOT NULL default current_timestamp,
+    data jsonb NOT NULL,
+    PRIMARY KEY (video_id, revision)
+);
+
+--- Strats demonstrated by a video. The primary link mirrors `video.room_id`/`video.strat_id`; others are additional strats shown in the same video.
+
+CREATE TABLE video_strat (
+    video_id integer,
+    room_id integer,
+    strat_id integer,
+    is_primary boolean NOT NULL default false,
+    PRIMARY KEY (video_id, room_id, strat_id)
+);
+
+--- Vocabulary of tags for categorizing videos (e.g. "damage boost", "alternate setup"), maintained by editors.
+
+CREATE TABLE tag (
+    tag_id serial primary key,
+    name varchar(100) NOT NULL UNIQUE,
+    description varchar(1000) NOT NULL default '',
+    created_account_id integer NOT NULL,
+    created_ts timestamptz NOT NULL default current_timestamp
+);
+
+-- Tag names are unique regardless of case:
+CREATE UNIQUE INDEX tag_name_lower ON tag (lower(name));
+
+CREATE TABLE video_tag (
+    video_id integer,
+    tag_id integer,
+    PRIMARY KEY (video_id, tag_id)
+);
+
+--- Review decisions on videos (Approve, Reject, RequestChanges), and reviewer assignments (Assign).
+
+CREATE TABLE video_review (
+    id serial primary key,
+    video_id integer NOT NULL,
+    account_id integer NOT NULL,
+    created_ts timestamptz NOT NULL default current_timestamp,
+    action varchar(100) NOT NULL,
+    assigned_account_id integer,  -- reviewer assigned (or NULL if unassigned), for Assign
+    comment varchar(10000) NOT NULL default ''
+);
+
+CREATE INDEX video_review_video_id ON video_review (video_id);
+
+--- Threaded discussion on videos, in Markdown. Deleted comments keep their row (with the body cleared) so that replies stay in place.
+
+CREATE TABLE video_comment (
+    id serial primary key,
+    video_id integer NOT NULL,
+    parent_id integer,  -- comment being replied to, if any
+    account_id integer NOT NULL,
+    created_ts timestamptz NOT NULL default current_timestamp,
+    edited_ts timestamptz,
+    deleted_ts timestamptz,
+    deleted_account_id integer,
+    body varchar(10000) NOT NULL,
+    frame_number integer  -- frame of the video that the comment refers to, if any
+);
+
+CREATE INDEX video_comment_video_id ON video_comment (video_id);
+
+--- Encoding tasks published to the encoder queue, and the outcome of the latest attempt at each.
+
+CREATE TABLE encoding_job (
+    id serial primary key,
+    video_id integer NOT NULL,
+    task_type varchar(100) NOT NULL,
+    task jsonb NOT NULL,
+    status varchar(100) NOT NULL,  -- Queued, Running, Succeeded, or Failed
+    attempts integer NOT NULL default 0,
+    worker_id varchar(255),
+    error text,
+    stderr_tail text,  -- last lines of ffmpeg output, if it failed
+    created_ts timestamptz NOT NULL default current_timestamp,
+    started_ts timestamptz,
+    finished_ts timestamptz
+);
+
+CREATE INDEX encoding_job_video_id ON encoding_job (video_id);
+
+--- Resumable multi-part uploads. The `video` row is only created once all parts have arrived and the session is finalized.
+
+CREATE TABLE upload_session (
+    video_id integer primary key,
+    account_id integer NOT NULL,
+    num_parts integer NOT NULL,
+    created_ts timestamptz NOT NULL default current_timestamp,
+    finalized_ts timestamptz
+);
+
+CREATE TABLE upload_part (
+    video_id integer,
+    part_num integer,
+    size bigint NOT NULL,
+    compressed_size bigint NOT NULL,
+    sha256 bytea NOT NULL,
+    frame_count integer NOT NULL,
+    frame_width integer NOT NULL,
+    frame_height integer NOT NULL,
+    frame_rate double precision NOT NULL,
+    uploaded_ts timestamptz NOT NULL default current_timestamp,
+    PRIMARY KEY (video_id, part_num)
 );
 
 --- Extract of essential metadata from sm-json-data, kept up-to-date by `sm-json-data-updater`:
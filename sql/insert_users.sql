@@ -1,3 +1,7 @@
-INSERT INTO account (username, token_hash, permission)
-VALUES ('user', sha256('token'), 'Default');
+-- Argon2id hash of 'token':
+INSERT INTO account (username, password_hash, permission)
+VALUES ('user', '$argon2id$v=19$m=19456,t=2,p=1$vhzTP8fsveP8Z3FN/EBGJw$NLzM/W3/xBKMb1BQFurM5uQQBOQy41+7NVMMOctZYOw', 'Default');
 
+-- Initial Admin account (also with token 'token'), which can create further accounts and invite codes:
+INSERT INTO account (username, password_hash, permission)
+VALUES ('admin', '$argon2id$v=19$m=19456,t=2,p=1$vhzTP8fsveP8Z3FN/EBGJw$NLzM/W3/xBKMb1BQFurM5uQQBOQy41+7NVMMOctZYOw', 'Admin');
@@ -0,0 +1,200 @@
+-- Statements to bring a database created from an earlier version of create.sql up to date, in
+-- the order the changes were made. Each can safely be run again on an up-to-date database.
+
+--- Resumable multi-part uploads
+
+CREATE TABLE IF NOT EXISTS upload_session (
+    video_id integer primary key,
+    account_id integer NOT NULL,
+    num_parts integer NOT NULL,
+    created_ts timestamptz NOT NULL default current_timestamp,
+    finalized_ts timestamptz
+);
+
+CREATE TABLE IF NOT EXISTS upload_part (
+    video_id integer,
+    part_num integer,
+    size bigint NOT NULL,
+    compressed_size bigint NOT NULL,
+    sha256 bytea NOT NULL,
+    frame_count integer NOT NULL,
+    frame_width integer NOT NULL,
+    frame_height integer NOT NULL,
+    frame_rate double precision NOT NULL,
+    uploaded_ts timestamptz NOT NULL default current_timestamp,
+    PRIMARY KEY (video_id, part_num)
+);
+
+--- Frame counts and video dimensions
+
+ALTER TABLE video ADD COLUMN IF NOT EXISTS part_frame_counts integer[];
+ALTER TABLE video ADD COLUMN IF NOT EXISTS frame_count integer;
+ALTER TABLE video ADD COLUMN IF NOT EXISTS frame_width integer;
+ALTER TABLE video ADD COLUMN IF NOT EXISTS frame_height integer;
+ALTER TABLE video ADD COLUMN IF NOT EXISTS frame_rate double precision;
+
+--- Video revision history
+
+CREATE TABLE IF NOT EXISTS video_revision (
+    video_id integer,
+    revision integer,
+    action varchar(100) NOT NULL,
+    account_id integer NOT NULL,
+    created_ts timestamptz NOT NULL default current_timestamp,
+    data jsonb NOT NULL,
+    PRIMARY KEY (video_id, revision)
+);
+
+--- Optimistic concurrency for video edits
+
+ALTER TABLE video ADD COLUMN IF NOT EXISTS version integer NOT NULL default 1;
+
+--- Soft deletion of videos
+
+ALTER TABLE video ADD COLUMN IF NOT EXISTS deleted_ts timestamptz;
+ALTER TABLE video ADD COLUMN IF NOT EXISTS deleted_account_id integer;
+ALTER TABLE video ADD COLUMN IF NOT EXISTS status_before_delete varchar(100);
+
+--- Argon2id account token hashes (existing SHA-256 hashes are replaced on each account's next login)
+
+ALTER TABLE account ADD COLUMN IF NOT EXISTS password_hash varchar(200);
+ALTER TABLE account ALTER COLUMN token_hash DROP NOT NULL;
+
+--- Scoped API tokens
+
+CREATE TABLE IF NOT EXISTS api_token (
+    id serial primary key,
+    account_id integer NOT NULL,
+    name varchar(100) NOT NULL,
+    scope varchar(100) NOT NULL,
+    token_hash bytea NOT NULL UNIQUE,
+    created_ts timestamptz NOT NULL default current_timestamp,
+    expires_ts timestamptz,
+    revoked_ts timestamptz
+);
+
+--- Account management and audit log (creating the index fails if two usernames differ only by case; rename one first)
+
+ALTER TABLE account ADD COLUMN IF NOT EXISTS disabled boolean NOT NULL default FALSE;
+ALTER TABLE account ADD COLUMN IF NOT EXISTS sessions_valid_after_ts timestamptz;
+CREATE UNIQUE INDEX IF NOT EXISTS account_username_lower ON account (lower(username));
+
+CREATE TABLE IF NOT EXISTS audit_log (
+    id serial primary key,
+    ts timestamptz NOT NULL default current_timestamp,
+    actor_account_id integer NOT NULL,
+    action varchar(100) NOT NULL,
+    target_account_id integer,
+    details jsonb NOT NULL
+);
+
+--- Invite codes
+
+ALTER TABLE account ADD COLUMN IF NOT EXISTS invite_code_id integer;
+
+CREATE TABLE IF NOT EXISTS invite_code (
+    id serial primary key,
+    code_hash bytea NOT NULL UNIQUE,
+    created_account_id integer NOT NULL,
+    created_ts timestamptz NOT NULL default current_timestamp,
+    expires_ts timestamptz NOT NULL,
+    revoked_ts timestamptz,
+    max_uses integer NOT NULL,
+    use_count integer NOT NULL default 0,
+    permission varchar(100) NOT NULL
+);
+
+--- Roles (Editors no longer manage accounts or `permanent` flags, so the existing Editors become Admin; this only applies while there is no Admin yet)
+
+UPDATE account SET permission = 'Admin'
+WHERE permission = 'Editor' AND NOT EXISTS (SELECT 1 FROM account WHERE permission = 'Admin');
+
+--- Review workflow
+
+ALTER TABLE video ADD COLUMN IF NOT EXISTS reviewer_account_id integer;
+
+CREATE TABLE IF NOT EXISTS video_review (
+    id serial primary key,
+    video_id integer NOT NULL,
+    account_id integer NOT NULL,
+    created_ts timestamptz NOT NULL default current_timestamp,
+    action varchar(100) NOT NULL,
+    assigned_account_id integer,
+    comment varchar(10000) NOT NULL default ''
+);
+
+CREATE INDEX IF NOT EXISTS video_review_video_id ON video_review (video_id);
+
+--- Video comments
+
+CREATE TABLE IF NOT EXISTS video_comment (
+    id serial primary key,
+    video_id integer NOT NULL,
+    parent_id integer,
+    account_id integer NOT NULL,
+    created_ts timestamptz NOT NULL default current_timestamp,
+    edited_ts timestamptz,
+    deleted_ts timestamptz,
+    deleted_account_id integer,
+    body varchar(10000) NOT NULL,
+    frame_number integer
+);
+
+CREATE INDEX IF NOT EXISTS video_comment_video_id ON video_comment (video_id);
+
+--- Video annotations
+
+ALTER TABLE video ADD COLUMN IF NOT EXISTS annotations jsonb NOT NULL default '[]';
+
+--- Multiple strats per video (existing videos' strats become their primary strat)
+
+CREATE TABLE IF NOT EXISTS video_strat (
+    video_id integer,
+    room_id integer,
+    strat_id integer,
+    is_primary boolean NOT NULL default false,
+    PRIMARY KEY (video_id, room_id, strat_id)
+);
+
+INSERT INTO video_strat (video_id, room_id, strat_id, is_primary)
+SELECT id, room_id, strat_id, true
+FROM video
+WHERE room_id IS NOT NULL AND strat_id IS NOT NULL
+ON CONFLICT DO NOTHING;
+
+--- Tags (creating the index fails if two tag names differ only by case; rename one first)
+
+CREATE TABLE IF NOT EXISTS tag (
+    tag_id serial primary key,
+    name varchar(100) NOT NULL UNIQUE,
+    description varchar(1000) NOT NULL default '',
+    created_account_id integer NOT NULL,
+    created_ts timestamptz NOT NULL default current_timestamp
+);
+
+CREATE UNIQUE INDEX IF NOT EXISTS tag_name_lower ON tag (lower(name));
+
+CREATE TABLE IF NOT EXISTS video_tag (
+    video_id integer,
+    tag_id integer,
+    PRIMARY KEY (video_id, tag_id)
+);
+
+--- Encoding jobs
+
+CREATE TABLE IF NOT EXISTS encoding_job (
+    id serial primary key,
+    video_id integer NOT NULL,
+    task_type varchar(100) NOT NULL,
+    task jsonb NOT NULL,
+    status varchar(100) NOT NULL,
+    attempts integer NOT NULL default 0,
+    worker_id varchar(255),
+    error text,
+    stderr_tail text,
+    created_ts timestamptz NOT NULL default current_timestamp,
+    started_ts timestamptz,
+    finished_ts timestamptz
+);
+
+CREATE INDEX IF NOT EXISTS encoding_job_video_id ON encoding_job (video_id);